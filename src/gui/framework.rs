@@ -1,13 +1,30 @@
 use std::{collections::VecDeque, sync::Arc};
 
-use egui::{ClippedMesh, Color32, Context, RichText, TexturesDelta, Ui};
+use egui::{
+    pos2, vec2, ClippedMesh, Color32, Context, Pos2, RichText, Sense, Stroke, TexturesDelta, Ui,
+};
 use egui_wgpu_backend::{BackendError, RenderPass, ScreenDescriptor};
 use parking_lot::RwLock;
 use pixels::{wgpu, PixelsContext};
 use winit::window::Window;
 
 use crate::{
-    patches::{FrequencyMultiplier, PatchDefinition, OPERATOR_COUNT},
+    notes::{self, NoteFormat, Tuning},
+    patches::{
+        tremolo_depth, EnvelopeCurve, EnvelopeDefinition, EnvelopeMode, EnvelopePlot,
+        EnvelopePosition, FrequencyMultiplier, FrequencyRatio, LevelScaling, LfoDefinition,
+        MorphSource, PatchDefinition, RenderMode, ScalingCurve, SsgEgMode, TriggerMode,
+        WaveformMorph, ALGORITHM_MAX, AMPLITUDE_SENSITIVITY_MAX, ATTENUATION_MAX, COARSE_MAX,
+        DEFAULT_FIXED_FREQUENCY, DETUNE_MAX, ENV_DB, FEEDBACK_MAX, FINE_MAX, FIXED_FREQUENCY_MAX,
+        FIXED_FREQUENCY_MIN, KEY_SCALE_MAX, LFO_RATE_MAX, LFO_RATE_MIN, LFO_TIME_MAX,
+        OPERATOR_COUNT, PITCH_SENSITIVITY_MAX, STAGE_TIME_MAX, VELOCITY_SENSITIVITY_MAX,
+    },
+    samples::{LoopMode, SampleBank, SampleDefinition, SampleMixerHandle},
+    sequencer::SequenceInstanceHandle,
+    waveform::{
+        set_waveform_tables_enabled, waveform_tables_enabled, Wavetable, LONG_NOISE_WIDTH,
+        SHORT_NOISE_WIDTH, WAVETABLE_SIZES,
+    },
     Waveform, WIDTH,
 };
 
@@ -30,8 +47,36 @@ pub struct Gui {
     /// Only show the egui window when true.
     pub(crate) patch_handle: Arc<RwLock<PatchDefinition>>,
     pub(crate) graph_points: Arc<RwLock<VecDeque<f32>>>,
+    /// Envelopes of every note playing, updated by the audio thread
+    pub(crate) envelope_positions: Arc<RwLock<Vec<[Option<EnvelopePosition>; OPERATOR_COUNT]>>>,
+    /// Only redrawn when an envelope changes
+    pub(crate) envelope_plots: [EnvelopePlot; OPERATOR_COUNT],
+    /// YM2612 register values typed in for each operator's envelope, and why they couldn't be imported
+    pub(crate) envelope_registers: [String; OPERATOR_COUNT],
+    pub(crate) envelope_register_status: [String; OPERATOR_COUNT],
+    pub(crate) patch_path: String,
+    pub(crate) patch_status: String,
+    /// Why the last routing change was rejected
+    pub(crate) routing_status: String,
+    pub(crate) sample_bank: Arc<RwLock<SampleBank>>,
+    /// The game sound channels, which samples are previewed on
+    pub(crate) game_samples: SampleMixerHandle,
+    pub(crate) sample_path: String,
+    pub(crate) sample_name: String,
+    pub(crate) sample_status: String,
+    pub(crate) tuning_scale_path: String,
+    pub(crate) tuning_map_path: String,
+    pub(crate) tuning_status: String,
+    /// How note names are written throughout the gui
+    pub(crate) note_format: NoteFormat,
+    pub(crate) sequence: SequenceInstanceHandle,
 }
 
+const WAVETABLE_EDITOR_WIDTH: f32 = 256.0;
+const WAVETABLE_EDITOR_HEIGHT: f32 = 96.0;
+const ENVELOPE_GRAPH_WIDTH: f32 = 360.0;
+const ENVELOPE_GRAPH_HEIGHT: f32 = 96.0;
+
 impl Framework {
     /// Create egui.
     pub(crate) fn new(
@@ -135,10 +180,70 @@ impl Gui {
             ui.label("Patch Settings");
 
             let mut patch = self.patch_handle.write();
-            ui.add(egui::Slider::new(&mut patch.feedback.0, 0..=15).text("Feedback"));
-            ui.add(egui::Slider::new(&mut patch.algorithm.0, 0..=7).text("Algorithm"));
+            ui.add(egui::Slider::new(&mut patch.feedback.0, 0..=FEEDBACK_MAX).text("Feedback"));
+            // A custom routing replaces the algorithm, which is then only a preset to load into it
+            let algorithm_label = match patch.routing {
+                Some(_) => "Algorithm preset",
+                None => "Algorithm",
+            };
+            ui.add(
+                egui::Slider::new(&mut patch.algorithm.0, 0..=ALGORITHM_MAX).text(algorithm_label),
+            );
+            ui.collapsing("Routing", |ui| {
+                routing_editor(ui, &mut patch, &mut self.routing_status)
+            });
+            ui.horizontal(|ui| {
+                ui.label("Render");
+                ui.selectable_value(&mut patch.render_mode, RenderMode::Float, "Float");
+                ui.selectable_value(
+                    &mut patch.render_mode,
+                    RenderMode::FixedPoint,
+                    "Fixed point (YM2612)",
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label("Envelope");
+                ui.selectable_value(&mut patch.envelope_mode, EnvelopeMode::Classic, "Classic");
+                ui.selectable_value(&mut patch.envelope_mode, EnvelopeMode::Ym2612, "YM2612");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Trigger");
+                ui.selectable_value(&mut patch.trigger_mode, TriggerMode::HardRetrigger, "Hard");
+                ui.selectable_value(&mut patch.trigger_mode, TriggerMode::SoftRetrigger, "Soft");
+                ui.selectable_value(&mut patch.trigger_mode, TriggerMode::Legato, "Legato");
+            });
+            ui.collapsing("LFO", |ui| lfo_editor(ui, &mut patch.lfo));
+
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.patch_path);
+                if ui.button("Save").clicked() {
+                    self.patch_status = match patch.save(&self.patch_path) {
+                        Ok(()) => format!("Saved {}", self.patch_path),
+                        Err(error) => error.to_string(),
+                    };
+                }
+                if ui.button("Load").clicked() {
+                    self.patch_status = match patch.load(&self.patch_path) {
+                        Ok(()) => format!("Loaded {}", self.patch_path),
+                        Err(error) => error.to_string(),
+                    };
+                }
+                ui.label(&self.patch_status);
+            });
             drop(patch);
 
+            let mut use_tables = waveform_tables_enabled();
+            if ui
+                .checkbox(&mut use_tables, "Waveform lookup tables")
+                .changed()
+            {
+                set_waveform_tables_enabled(use_tables);
+            }
+
+            ui.collapsing("Samples", |ui| self.samples(ui));
+            ui.collapsing("Tuning", |ui| self.tuning(ui));
+            ui.collapsing("Music", |ui| self.music(ui));
+
             // Plot
             let graph = self.graph_points.read();
             use egui::plot::{Line, Plot, Value, Values};
@@ -180,67 +285,224 @@ impl Gui {
         });
     }
 
+    fn samples(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Path");
+            ui.text_edit_singleline(&mut self.sample_path);
+            ui.label("Name");
+            ui.text_edit_singleline(&mut self.sample_name);
+
+            if ui.button("Import WAV").clicked() {
+                // Default to the file name when no name is given
+                let name = if self.sample_name.is_empty() {
+                    std::path::Path::new(&self.sample_path)
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned())
+                        .unwrap_or_default()
+                } else {
+                    self.sample_name.clone()
+                };
+
+                let result = self.sample_bank.write().load_wav(&name, &self.sample_path);
+                self.sample_status = match result {
+                    Ok(index) => format!("Loaded {} as sample {}", name, index),
+                    Err(error) => error.to_string(),
+                };
+            }
+            ui.label(&self.sample_status);
+        });
+
+        ui.horizontal(|ui| {
+            let mixer = self.game_samples.mixer.read();
+            ui.label(format!(
+                "Game channels playing: {}/{}",
+                mixer.playing_count(),
+                mixer.channel_count()
+            ));
+            drop(mixer);
+
+            if ui.button("Stop all").clicked() {
+                self.game_samples.stop_all();
+            }
+        });
+
+        let bank = self.sample_bank.read();
+        bank.iter().for_each(|(index, name, sample)| {
+            ui.horizontal(|ui| {
+                let definition = sample.read();
+                ui.label(format!(
+                    "{:03}: {} ({} frames, {:?})",
+                    index,
+                    name,
+                    definition.frame_count(),
+                    definition.channels
+                ));
+                drop(definition);
+
+                if ui.button("Play").clicked() {
+                    self.game_samples.play(sample.clone());
+                }
+            });
+            sample_editor(ui, index, &mut sample.write(), self.note_format);
+        });
+    }
+
+    fn tuning(&mut self, ui: &mut Ui) {
+        let tuning = notes::tuning();
+        ui.label(format!(
+            "Scale: {} ({} notes)",
+            tuning.scale().description(),
+            tuning.scale().len()
+        ));
+
+        ui.horizontal(|ui| {
+            ui.label("Scale (.scl)");
+            ui.text_edit_singleline(&mut self.tuning_scale_path);
+            ui.label("Keyboard map (.kbm, optional)");
+            ui.text_edit_singleline(&mut self.tuning_map_path);
+
+            if ui.button("Load").clicked() {
+                let map_path = (!self.tuning_map_path.is_empty()).then_some(&self.tuning_map_path);
+                self.tuning_status = match Tuning::load(&self.tuning_scale_path, map_path) {
+                    Ok(tuning) => {
+                        notes::set_tuning(tuning);
+                        format!("Loaded {}", self.tuning_scale_path)
+                    }
+                    Err(error) => error.to_string(),
+                };
+            }
+            if ui.button("Reset to 12-TET").clicked() {
+                notes::set_tuning(Tuning::default());
+                self.tuning_status = String::new();
+            }
+            ui.label(&self.tuning_status);
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Note names");
+            ui.selectable_value(&mut self.note_format, NoteFormat::Sharps, "C#4");
+            ui.selectable_value(&mut self.note_format, NoteFormat::Flats, "Db4");
+            ui.selectable_value(&mut self.note_format, NoteFormat::Tracker, "C-4");
+        });
+
+        let (key, mut frequency) = tuning.reference();
+        // Keyboard maps can put the reference outside of the notes table, where it can't be retuned
+        let reference = notes::midi_to_index(key as i32).ok();
+        ui.horizontal(|ui| {
+            match reference {
+                Some(index) => ui.label(format!(
+                    "Reference pitch of {}",
+                    notes::format_note(index, self.note_format).unwrap_or_default()
+                )),
+                None => ui.label(format!("Reference pitch of MIDI key {}", key)),
+            };
+            let drag = egui::DragValue::new(&mut frequency)
+                .speed(0.1)
+                .clamp_range(1.0..=20_000.0)
+                .suffix(" Hz");
+
+            if ui.add_enabled(reference.is_some(), drag).changed() {
+                if let Some(Err(error)) =
+                    reference.map(|index| notes::set_reference_pitch(index, frequency))
+                {
+                    self.tuning_status = error.to_string();
+                }
+            }
+        });
+    }
+
+    fn music(&mut self, ui: &mut Ui) {
+        let mut playing = self.sequence.is_playing();
+        let label = format!("Play the test pattern ({} bpm)", self.sequence.bpm());
+        if ui.checkbox(&mut playing, label).changed() {
+            self.sequence.set_playing(playing);
+        }
+    }
+
     fn operator(&mut self, ui: &mut Ui, index: usize) {
         ui.vertical(|ui| {
             let patch = &mut self.patch_handle.write();
             let operator = &mut patch.operators[index].write();
 
             ui.label(RichText::new(format!("Operator: {}", 1 + index)).color(
-                if patch.algorithm.get_definition().carriers[index] {
+                if patch.routing().is_carrier(index) {
                     Color32::GREEN
                 } else {
                     Color32::LIGHT_BLUE
                 },
             ));
 
-            ui.horizontal(|ui| {
-                ui.selectable_value(&mut operator.waveform, Waveform::Sine, "Sine");
-                ui.selectable_value(
-                    &mut operator.waveform,
-                    Waveform::InvertedSine,
-                    "InvertedSine",
-                );
-
-                ui.selectable_value(&mut operator.waveform, Waveform::HalfSine, "HalfSine");
-                ui.selectable_value(
-                    &mut operator.waveform,
-                    Waveform::InvertedHalfSine,
-                    "InvertedHalfSine",
-                );
-
-                ui.selectable_value(
-                    &mut operator.waveform,
-                    Waveform::AlternatingSine,
-                    "AlternatingSine",
-                );
-                ui.selectable_value(
-                    &mut operator.waveform,
-                    Waveform::InvertedAlternatingSine,
-                    "InvertedAlternatingSine",
-                );
-
-                ui.selectable_value(&mut operator.waveform, Waveform::CamelSine, "CamelSine");
-                ui.selectable_value(
-                    &mut operator.waveform,
-                    Waveform::InvertedCamelSine,
-                    "InvertedCamelSine",
+            waveform_selector(ui, &mut operator.waveform);
+            if let Waveform::Custom(wavetable) = &operator.waveform {
+                wavetable_editor(ui, wavetable);
+            }
+
+            let mut morphing = operator.morph.is_some();
+            if ui.checkbox(&mut morphing, "Morph").changed() {
+                operator.morph = morphing.then(WaveformMorph::default);
+            }
+            if let Some(morph) = &mut operator.morph {
+                morph_editor(ui, morph);
+            }
+
+            let mut fixed = operator.fixed_frequency.is_some();
+            if ui.checkbox(&mut fixed, "Fixed frequency").changed() {
+                operator.fixed_frequency = fixed.then_some(DEFAULT_FIXED_FREQUENCY);
+            }
+            if let Some(frequency) = &mut operator.fixed_frequency {
+                ui.add(
+                    egui::Slider::new(frequency, FIXED_FREQUENCY_MIN..=FIXED_FREQUENCY_MAX)
+                        .logarithmic(true)
+                        .suffix(" Hz")
+                        .text("Fixed"),
                 );
-            });
+            } else {
+                frequency_ratio_editor(ui, index, &mut operator.frequency_ratio);
+            }
 
-            // TODO: Reset clock if frequency or detune change
-            let text = operator.frequency_multiplier.as_ratio().to_string();
+            ui.add(
+                egui::Slider::new(&mut operator.detune, -DETUNE_MAX..=DETUNE_MAX).text("Detune"),
+            );
+            let tremolo =
+                tremolo_depth(operator.amplitude_sensitivity) * ENV_DB / ATTENUATION_MAX as f32;
             ui.add(
                 egui::Slider::new(
-                    &mut operator.frequency_multiplier.0,
-                    0..=FrequencyMultiplier::max_value(),
+                    &mut operator.amplitude_sensitivity,
+                    0..=AMPLITUDE_SENSITIVITY_MAX,
                 )
-                .text(text),
+                .text(format!("AMS ({:.1} dB)", tremolo)),
             );
-
-            ui.add(egui::Slider::new(&mut operator.detune, -100..=100).text("Detune"));
+            ui.add(
+                egui::Slider::new(
+                    &mut operator.velocity_sensitivity,
+                    0..=VELOCITY_SENSITIVITY_MAX,
+                )
+                .text("Velocity"),
+            );
+            level_scaling_editor(ui, index, &mut operator.level_scaling, self.note_format);
 
             // Envelope
             let envelope = &mut operator.envelope.write();
+            let times = [
+                envelope.attack_time(),
+                envelope.decay_time(),
+                envelope.sustain_time(),
+                envelope.release_time(),
+            ]
+            .map(format_time);
+
+            let positions = self.envelope_positions.read();
+            let plot = &mut self.envelope_plots[index];
+            // Draw the first note playing, so key scaling matches its marker
+            let key_code = positions
+                .iter()
+                .find_map(|envelopes| envelopes[index].map(|position| position.key_code))
+                .unwrap_or(plot.key_code());
+            if !plot.matches(envelope, patch.envelope_mode, key_code) {
+                *plot = EnvelopePlot::new(envelope, patch.envelope_mode, key_code);
+            }
+            envelope_graph(ui, index, plot, &positions);
+            drop(positions);
             ui.horizontal(|ui| {
                 ui.add(
                     egui::Slider::new(&mut envelope.total_level, u8::MIN..=u8::MAX)
@@ -248,15 +510,27 @@ impl Gui {
                         .vertical()
                         .step_by(1.0),
                 );
+                ui.add(
+                    egui::Slider::new(&mut envelope.delay, 0..=STAGE_TIME_MAX)
+                        .text("DL ms")
+                        .vertical()
+                        .logarithmic(true),
+                );
                 ui.add(
                     egui::Slider::new(&mut envelope.attack_rate, u8::MIN..=u8::MAX)
-                        .text("AR")
+                        .text(format!("AR {}", times[0]))
                         .vertical()
                         .step_by(1.0),
                 );
+                ui.add(
+                    egui::Slider::new(&mut envelope.hold, 0..=STAGE_TIME_MAX)
+                        .text("HL ms")
+                        .vertical()
+                        .logarithmic(true),
+                );
                 ui.add(
                     egui::Slider::new(&mut envelope.decay_attack_rate, u8::MIN..=u8::MAX)
-                        .text("D1")
+                        .text(format!("D1 {}", times[1]))
                         .vertical()
                         .step_by(1.0),
                 );
@@ -268,17 +542,473 @@ impl Gui {
                 );
                 ui.add(
                     egui::Slider::new(&mut envelope.decay_sustain_rate, u8::MIN..=u8::MAX)
-                        .text("D2")
+                        .text(format!("D2 {}", times[2]))
                         .vertical()
                         .step_by(1.0),
                 );
                 ui.add(
                     egui::Slider::new(&mut envelope.release_rate, u8::MIN..=u8::MAX)
-                        .text("RR")
+                        .text(format!("RR {}", times[3]))
                         .vertical()
                         .step_by(1.0),
                 );
+                ui.add(
+                    egui::Slider::new(&mut envelope.key_scale, 0..=KEY_SCALE_MAX)
+                        .text("KS")
+                        .vertical()
+                        .step_by(1.0),
+                );
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Curves");
+                envelope_curve_selector(ui, (index, "AR"), &mut envelope.attack_curve);
+                envelope_curve_selector(ui, (index, "D1"), &mut envelope.decay_curve);
+                envelope_curve_selector(ui, (index, "D2"), &mut envelope.sustain_curve);
+                envelope_curve_selector(ui, (index, "RR"), &mut envelope.release_curve);
+            });
+
+            ui.horizontal_wrapped(|ui| {
+                ui.label("SSG-EG");
+                SsgEgMode::ALL.into_iter().for_each(|mode| {
+                    ui.selectable_value(&mut envelope.ssg_eg, mode, mode.name());
+                });
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("YM2612 registers (TL AR DR SL SR RR KS [SSG])");
+                ui.text_edit_singleline(&mut self.envelope_registers[index]);
+                if ui.button("Import").clicked() {
+                    self.envelope_register_status[index] =
+                        match EnvelopeDefinition::parse_registers(&self.envelope_registers[index]) {
+                            Ok(imported) => {
+                                **envelope = imported;
+                                String::new()
+                            }
+                            Err(error) => error.to_string(),
+                        };
+                }
+                ui.label(&self.envelope_register_status[index]);
+            });
+        });
+    }
+}
+
+/// Draws an envelope over a single note, with a marker for each note playing.
+fn envelope_graph(
+    ui: &mut Ui,
+    index: usize,
+    plot: &EnvelopePlot,
+    positions: &[[Option<EnvelopePosition>; OPERATOR_COUNT]],
+) {
+    use egui::plot::{Line, MarkerShape, Plot, Points, Value, Values};
+
+    let line = Line::new(Values::from_values_iter(
+        plot.points
+            .iter()
+            .map(|[seconds, level]| Value::new(*seconds, *level)),
+    ))
+    .color(Color32::LIGHT_BLUE);
+
+    let markers = Points::new(Values::from_values_iter(
+        positions
+            .iter()
+            .filter_map(|envelopes| envelopes[index].as_ref())
+            .filter_map(|position| plot.marker(position))
+            .map(|[seconds, level]| Value::new(seconds, level)),
+    ))
+    .shape(MarkerShape::Circle)
+    .radius(4.0)
+    .color(Color32::YELLOW);
+
+    let graph = Plot::new(("Envelope", index))
+        .width(ENVELOPE_GRAPH_WIDTH)
+        .height(ENVELOPE_GRAPH_HEIGHT)
+        .allow_boxed_zoom(false)
+        .allow_drag(false)
+        .allow_zoom(false)
+        .include_x(0.0)
+        .include_x(plot.length())
+        .include_y(0.0)
+        .include_y(1.0)
+        .show_axes([false, false]);
+
+    ui.add_enabled_ui(false, |ui| {
+        graph.show(ui, |plot_ui| {
+            plot_ui.line(line);
+            plot_ui.points(markers);
+        });
+    });
+}
+
+/// Shows an envelope phase's length, where None is a phase that never ends.
+fn format_time(seconds: Option<f32>) -> String {
+    match seconds {
+        None => String::from("∞"),
+        Some(seconds) if seconds < 1.0 => format!("{:.0} ms", seconds * 1000.0),
+        Some(seconds) => format!("{:.2} s", seconds),
+    }
+}
+
+/// Picks the curve of one envelope segment, labelled by the segment's slider.
+fn envelope_curve_selector(ui: &mut Ui, id: (usize, &str), curve: &mut EnvelopeCurve) {
+    egui::ComboBox::from_id_source(("envelope curve", id))
+        .selected_text(format!("{} {}", id.1, curve.name()))
+        .width(64.0)
+        .show_ui(ui, |ui| {
+            EnvelopeCurve::ALL.into_iter().for_each(|option| {
+                ui.selectable_value(curve, option, option.name());
+            });
+        });
+}
+
+/// Coarse and fine sliders, and the original fixed ratios as presets.
+fn frequency_ratio_editor(ui: &mut Ui, index: usize, ratio: &mut FrequencyRatio) {
+    let mut coarse = ratio.coarse();
+    let mut fine = ratio.fine();
+    // Coarse and fine can't make ratios below 0.5, so editing them would jump the ratio up
+    let editable = ratio.has_coarse_fine();
+    let hint = "Ratios below 0.5 are only available as presets";
+    let coarse_changed = ui
+        .add_enabled(
+            editable,
+            egui::Slider::new(&mut coarse, 0..=COARSE_MAX).text("Coarse"),
+        )
+        .on_disabled_hover_text(hint)
+        .changed();
+    let fine_changed = ui
+        .add_enabled(
+            editable,
+            egui::Slider::new(&mut fine, 0..=FINE_MAX).text("Fine"),
+        )
+        .on_disabled_hover_text(hint)
+        .changed();
+    if coarse_changed || fine_changed {
+        *ratio = FrequencyRatio::from_coarse_fine(coarse, fine);
+    }
+
+    egui::ComboBox::from_id_source(("ratio presets", index))
+        .selected_text(format!("Ratio {}", ratio))
+        .show_ui(ui, |ui| {
+            (0..=FrequencyMultiplier::max_value())
+                .map(FrequencyMultiplier)
+                .for_each(|preset| {
+                    ui.selectable_value(ratio, preset.into(), preset.as_ratio());
+                });
+        });
+}
+
+/// Edits the patch's custom routing. Changes which would make a cycle are
+/// rejected, and the reason shown.
+fn routing_editor(ui: &mut Ui, patch: &mut PatchDefinition, status: &mut String) {
+    let mut custom = patch.routing.is_some();
+    if ui.checkbox(&mut custom, "Custom routing").changed() {
+        // Starts from the algorithm, so the sound doesn't change until edited
+        let routing = custom.then(|| patch.algorithm.routing());
+        *status = match patch.set_routing(routing) {
+            Ok(()) => String::new(),
+            Err(error) => error.to_string(),
+        };
+    }
+
+    if !custom {
+        return;
+    }
+
+    let mut routing = patch.routing();
+    if ui
+        .button(format!("Load algorithm {} preset", patch.algorithm.0))
+        .clicked()
+    {
+        routing = patch.algorithm.routing();
+    }
+
+    egui::Grid::new("routing matrix").show(ui, |ui| {
+        ui.label("Modulated by");
+        (0..OPERATOR_COUNT).for_each(|source| {
+            ui.label(format!("Op {}", source + 1));
+        });
+        ui.label("Output");
+        ui.end_row();
+
+        (0..OPERATOR_COUNT).for_each(|target| {
+            ui.label(format!("Op {}", target + 1));
+            (0..OPERATOR_COUNT).for_each(|source| {
+                if source == target {
+                    ui.label("-");
+                } else {
+                    ui.add(
+                        egui::DragValue::new(&mut routing.modulation[target][source])
+                            .speed(0.01)
+                            .clamp_range(0.0..=1.0),
+                    );
+                }
             });
+            ui.add(
+                egui::DragValue::new(&mut routing.output[target])
+                    .speed(0.01)
+                    .clamp_range(0.0..=1.0),
+            );
+            ui.end_row();
+        });
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Feedback");
+        (0..OPERATOR_COUNT).for_each(|operator| {
+            ui.selectable_value(
+                &mut routing.feedback,
+                operator,
+                format!("Op {}", operator + 1),
+            );
+        });
+    });
+
+    if routing != patch.routing() {
+        *status = match patch.set_routing(Some(routing)) {
+            Ok(()) => String::new(),
+            Err(error) => error.to_string(),
+        };
+    }
+    ui.label(status.as_str());
+}
+
+/// Edits a sample's root note and loop points.
+fn sample_editor(ui: &mut Ui, index: usize, sample: &mut SampleDefinition, format: NoteFormat) {
+    egui::CollapsingHeader::new("Pitch and loop")
+        .id_source(("sample", index))
+        .show(ui, |ui| {
+            let mut root_note = sample.root_note;
+            let name = notes::format_note(root_note, format).unwrap_or_default();
+            if ui
+                .add(
+                    egui::Slider::new(&mut root_note, 0..=notes::TOTAL_NOTES - 1)
+                        .text(format!("Root note ({})", name)),
+                )
+                .changed()
+            {
+                sample.set_root_note(root_note);
+            }
+
+            let frames = sample.frame_count();
+            let mut loop_mode = sample.loop_mode;
+            let mut looped = matches!(loop_mode, LoopMode::Loop { .. });
+            if ui.checkbox(&mut looped, "Loop while held").changed() {
+                loop_mode = match looped {
+                    true => LoopMode::Loop {
+                        start: 0,
+                        end: frames,
+                    },
+                    false => LoopMode::OneShot,
+                };
+            }
+            if let LoopMode::Loop { start, end } = &mut loop_mode {
+                ui.add(egui::Slider::new(start, 0..=frames.saturating_sub(1)).text("Loop start"));
+                ui.add(egui::Slider::new(end, 1..=frames).text("Loop end"));
+            }
+
+            if loop_mode != sample.loop_mode {
+                sample.set_loop_mode(loop_mode);
+            }
+        });
+}
+
+fn lfo_editor(ui: &mut Ui, lfo: &mut LfoDefinition) {
+    waveform_selector(ui, &mut lfo.waveform);
+    ui.add(
+        egui::Slider::new(&mut lfo.rate, LFO_RATE_MIN..=LFO_RATE_MAX)
+            .logarithmic(true)
+            .suffix(" Hz")
+            .text("Rate"),
+    );
+    ui.add(
+        egui::Slider::new(&mut lfo.delay, 0.0..=LFO_TIME_MAX)
+            .suffix(" s")
+            .text("Delay"),
+    );
+    ui.add(
+        egui::Slider::new(&mut lfo.fade_in, 0.0..=LFO_TIME_MAX)
+            .suffix(" s")
+            .text("Fade in"),
+    );
+    let depth = lfo.pitch_depth();
+    ui.add(
+        egui::Slider::new(&mut lfo.pitch_sensitivity, 0..=PITCH_SENSITIVITY_MAX)
+            .text(format!("PMS (±{} cents)", depth)),
+    );
+    ui.checkbox(&mut lfo.retrigger, "Restart on key on");
+}
+
+fn level_scaling_editor(ui: &mut Ui, index: usize, scaling: &mut LevelScaling, format: NoteFormat) {
+    egui::CollapsingHeader::new("Level scaling")
+        .id_source(("level scaling", index))
+        .show(ui, |ui| {
+            let breakpoint = notes::format_note(scaling.breakpoint, format).unwrap_or_default();
+            ui.add(
+                egui::Slider::new(&mut scaling.breakpoint, 0..=notes::TOTAL_NOTES - 1)
+                    .text(format!("Breakpoint ({})", breakpoint)),
+            );
+
+            ui.add(
+                egui::Slider::new(&mut scaling.left_depth, u8::MIN..=u8::MAX).text("Left depth"),
+            );
+            curve_selector(ui, &mut scaling.left_curve);
+            ui.add(
+                egui::Slider::new(&mut scaling.right_depth, u8::MIN..=u8::MAX).text("Right depth"),
+            );
+            curve_selector(ui, &mut scaling.right_curve);
+        });
+}
+
+fn curve_selector(ui: &mut Ui, curve: &mut ScalingCurve) {
+    ui.horizontal(|ui| {
+        ui.selectable_value(curve, ScalingCurve::Linear, "Linear");
+        ui.selectable_value(curve, ScalingCurve::Exponential, "Exponential");
+    });
+}
+
+fn waveform_selector(ui: &mut Ui, waveform: &mut Waveform) {
+    ui.horizontal_wrapped(|ui| {
+        ui.selectable_value(waveform, Waveform::Sine, "Sine");
+        ui.selectable_value(waveform, Waveform::InvertedSine, "InvertedSine");
+
+        ui.selectable_value(waveform, Waveform::HalfSine, "HalfSine");
+        ui.selectable_value(waveform, Waveform::InvertedHalfSine, "InvertedHalfSine");
+
+        ui.selectable_value(waveform, Waveform::AlternatingSine, "AlternatingSine");
+        ui.selectable_value(
+            waveform,
+            Waveform::InvertedAlternatingSine,
+            "InvertedAlternatingSine",
+        );
+
+        ui.selectable_value(waveform, Waveform::CamelSine, "CamelSine");
+        ui.selectable_value(waveform, Waveform::InvertedCamelSine, "InvertedCamelSine");
+
+        let duty = match *waveform {
+            Waveform::Pulse(duty) | Waveform::BandLimitedPulse(duty) => duty,
+            _ => 0.25,
+        };
+
+        ui.selectable_value(waveform, Waveform::Square, "Square");
+        ui.selectable_value(waveform, Waveform::BandLimitedSquare, "BandLimitedSquare");
+        ui.selectable_value(waveform, Waveform::Pulse(duty), "Pulse");
+        ui.selectable_value(
+            waveform,
+            Waveform::BandLimitedPulse(duty),
+            "BandLimitedPulse",
+        );
+        ui.selectable_value(waveform, Waveform::Saw, "Saw");
+        ui.selectable_value(waveform, Waveform::BandLimitedSaw, "BandLimitedSaw");
+
+        let wavetable = match waveform {
+            Waveform::Custom(wavetable) => wavetable.clone(),
+            _ => Wavetable::default(),
+        };
+        ui.selectable_value(waveform, Waveform::Custom(wavetable), "Custom");
+
+        ui.selectable_value(waveform, Waveform::Noise, "Noise");
+        ui.selectable_value(
+            waveform,
+            Waveform::PitchedNoise(SHORT_NOISE_WIDTH),
+            "ShortNoise",
+        );
+        ui.selectable_value(
+            waveform,
+            Waveform::PitchedNoise(LONG_NOISE_WIDTH),
+            "LongNoise",
+        );
+    });
+
+    if let Waveform::Pulse(duty) | Waveform::BandLimitedPulse(duty) = waveform {
+        ui.add(egui::Slider::new(duty, 0.01..=0.99).text("Duty"));
+    }
+}
+
+/// Draws the wavetable, and lets the user draw over it with the mouse.
+fn wavetable_editor(ui: &mut Ui, wavetable: &Wavetable) {
+    ui.horizontal(|ui| {
+        ui.label("Length");
+        WAVETABLE_SIZES.iter().for_each(|&size| {
+            if ui
+                .selectable_label(wavetable.len() == size, size.to_string())
+                .clicked()
+            {
+                wavetable.resize(size);
+            }
         });
+    });
+
+    let (response, painter) = ui.allocate_painter(
+        vec2(WAVETABLE_EDITOR_WIDTH, WAVETABLE_EDITOR_HEIGHT),
+        Sense::click_and_drag(),
+    );
+    let rect = response.rect;
+
+    if let Some(position) = response.interact_pointer_pos() {
+        let len = wavetable.len();
+        let to_sample = |position: Pos2| {
+            let index = ((position.x - rect.left()) / rect.width() * len as f32)
+                .clamp(0.0, (len - 1) as f32) as usize;
+            let value = 1.0 - 2.0 * (position.y - rect.top()) / rect.height();
+            (index, value)
+        };
+
+        // Fill in every sample the pointer moved over since the last frame
+        let (start_index, start_value) = to_sample(position - response.drag_delta());
+        let (end_index, end_value) = to_sample(position);
+        let steps = start_index.abs_diff(end_index).max(1);
+        (0..=steps).for_each(|step| {
+            let amount = step as f32 / steps as f32;
+            let index = start_index as f32 + (end_index as f32 - start_index as f32) * amount;
+            wavetable.set(
+                index.round() as usize,
+                start_value + (end_value - start_value) * amount,
+            );
+        });
+    }
+
+    painter.rect_filled(rect, 0.0, Color32::BLACK);
+    painter.line_segment(
+        [rect.left_center(), rect.right_center()],
+        Stroke::new(1.0, Color32::DARK_GRAY),
+    );
+
+    let samples = wavetable.samples();
+    let step_width = rect.width() / samples.len() as f32;
+    samples.iter().enumerate().for_each(|(index, sample)| {
+        let x = rect.left() + index as f32 * step_width;
+        let y = rect.center().y - sample * rect.height() / 2.0;
+        painter.line_segment(
+            [pos2(x, y), pos2(x + step_width, y)],
+            Stroke::new(2.0, Color32::GREEN),
+        );
+    });
+}
+
+fn morph_editor(ui: &mut Ui, morph: &mut WaveformMorph) {
+    waveform_selector(ui, &mut morph.waveform);
+    if let Waveform::Custom(wavetable) = &morph.waveform {
+        wavetable_editor(ui, wavetable);
+    }
+
+    ui.add(egui::Slider::new(&mut morph.amount, 0.0..=1.0).text("Morph Amount"));
+
+    let rate = match morph.source {
+        MorphSource::Lfo(rate) => rate,
+        _ => 1.0,
+    };
+    ui.horizontal(|ui| {
+        ui.selectable_value(&mut morph.source, MorphSource::Fixed, "Fixed");
+        ui.selectable_value(&mut morph.source, MorphSource::Envelope, "Envelope");
+        ui.selectable_value(&mut morph.source, MorphSource::Lfo(rate), "LFO");
+    });
+
+    if let MorphSource::Lfo(rate) = &mut morph.source {
+        ui.add(
+            egui::Slider::new(rate, 0.01..=20.0)
+                .logarithmic(true)
+                .text("LFO Hz"),
+        );
     }
 }
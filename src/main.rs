@@ -1,6 +1,7 @@
 mod gui;
 mod notes;
 mod patches;
+mod samples;
 mod sequencer;
 mod waveform;
 
@@ -11,9 +12,11 @@ use cpal::{
     StreamConfig,
 };
 use gui::framework::{Framework, Gui};
+use notes::NoteFormat;
 // use macroquad::prelude::*;
 use parking_lot::RwLock;
 use pixels::{Pixels, SurfaceTexture};
+use samples::{SampleBank, SampleMixer, SampleMixerHandle};
 use sequencer::{SequenceInstance, SequenceInstanceHandle};
 use winit::{
     dpi::LogicalSize,
@@ -46,6 +49,7 @@ const HEIGHT: u32 = 900;
 fn main() {
     notes::generate();
     patches::init_attenuation_table();
+    waveform::init_waveform_tables();
 
     let host = cpal::default_host();
     let device = host
@@ -79,15 +83,45 @@ fn main() {
     let graph = Arc::new(RwLock::new(graph));
     let graph_clone = graph.clone();
 
+    let envelope_positions = Arc::new(RwLock::new(Vec::new()));
+    let envelope_positions_clone = envelope_positions.clone();
+
+    let sample_bank = Arc::new(RwLock::new(SampleBank::new()));
+    // The game sound channels, played from the sample list
+    let mut game_samples = SampleMixerHandle::new(SampleMixer::default());
+
+    let sequence = SequenceDefinition::test_pattern(sample_rate.0, sample_bank.clone());
+    let sequence_instance = SequenceInstance::new(Arc::new(RwLock::new(sequence)));
+    let sequence_handle = SequenceInstanceHandle::new(sequence_instance);
+
     let gui = Gui {
         patch_handle: sound.clone(),
         graph_points: graph,
+        envelope_positions,
+        envelope_plots: Default::default(),
+        patch_path: String::from("patch.ron"),
+        patch_status: String::new(),
+        routing_status: String::new(),
+        sample_bank,
+        game_samples: game_samples.clone(),
+        sequence: sequence_handle.clone(),
+        note_format: NoteFormat::Sharps,
+        sample_path: String::new(),
+        sample_name: String::new(),
+        sample_status: String::new(),
+        tuning_scale_path: String::new(),
+        tuning_map_path: String::new(),
+        tuning_status: String::new(),
+        envelope_registers: Default::default(),
+        envelope_register_status: Default::default(),
     };
     let (mut pixels, mut framework) = init_pixels(&window, gui);
     let mut input = WinitInputHelper::new();
 
     let mut handles = Vec::new();
 
+    // Left shift plays the B below middle C, so Z is on middle C
+    let lowest_key_note = notes::parse_note("B3").unwrap();
     let mut keys = [
         (VirtualKeyCode::LShift),
         (VirtualKeyCode::Z),
@@ -113,29 +147,31 @@ fn main() {
     .enumerate()
     .map(|(index, code)| {
         let sound_handle = PatchInstanceHandle::new(PatchInstance::new(sound.clone(), 0.0));
-        sound_handle.set_frequency(notes::index_to_frequency(index + 35)); //35
         handles.push(sound_handle.clone());
-        (code, sound_handle)
+        (code, lowest_key_note + index, sound_handle)
     })
     .collect::<Vec<_>>();
 
-    let sequence = SequenceDefinition::test_pattern(sample_rate.0);
-    let sequence_instance = SequenceInstance::new(Arc::new(RwLock::new(sequence)));
-    let sequence_handle = SequenceInstanceHandle::new(sequence_instance);
-
     let _sound_thread = std::thread::spawn(move || {
         let stream = device
             .build_output_stream(
                 &config,
                 move |data, _| {
                     let graph = graph_clone.clone();
+                    let envelope_positions = envelope_positions_clone.clone();
 
                     // Reset output to zero
                     data.iter_mut().for_each(|data| *data = 0.0);
 
-                    //let sequence_handle = sequence_handle.clone();
-                    //sequence_callback(data, channels, sequence_handle);
-                    data_callback(data, channels, handles.as_mut_slice(), graph);
+                    sequence_callback(data, channels, &sequence_handle);
+                    game_samples.write_to_buffer(data, channels);
+                    data_callback(
+                        data,
+                        channels,
+                        handles.as_mut_slice(),
+                        graph,
+                        envelope_positions,
+                    );
                 },
                 move |err| {
                     println!("err: {}", err);
@@ -159,9 +195,10 @@ fn main() {
                 return;
             }
 
-            keys.iter_mut().for_each(|(key, handle)| {
-                if input.key_pressed(**key) {
-                    handle.set_active(true);
+            keys.iter_mut().for_each(|(key, note, handle)| {
+                // Set the pitch on each press, so it follows the current tuning
+                if input.key_pressed(**key) && notes::is_mapped(*note) {
+                    handle.note_on(notes::index_to_frequency(*note), VELOCITY_MAX);
                 } else if input.key_released(**key) {
                     handle.set_active(false);
                 }
@@ -218,7 +255,7 @@ fn main() {
     });
 }
 
-fn sequence_callback(data: &mut [f32], channels: u16, sequence: SequenceInstanceHandle) {
+fn sequence_callback(data: &mut [f32], channels: u16, sequence: &SequenceInstanceHandle) {
     sequence.write_to_buffer(data, channels);
 }
 
@@ -227,11 +264,23 @@ fn data_callback(
     channels: u16,
     handles: &mut [PatchInstanceHandle],
     graph: Arc<RwLock<VecDeque<f32>>>,
+    envelope_positions: Arc<RwLock<Vec<[Option<EnvelopePosition>; OPERATOR_COUNT]>>>,
 ) {
     handles
         .iter_mut()
         .for_each(|handle| handle.write_to_buffer(data, channels));
 
+    // Update the envelope graphs with every note still sounding
+    let mut positions = envelope_positions.write();
+    positions.clear();
+    positions.extend(
+        handles
+            .iter()
+            .map(|handle| handle.patch.read().envelope_positions())
+            .filter(|envelopes| envelopes.iter().any(Option::is_some)),
+    );
+    drop(positions);
+
     // Update the oscilliscope
     let mut graph = graph.write();
     graph.drain(0..data.len() / channels as usize);
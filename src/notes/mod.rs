@@ -0,0 +1,93 @@
+mod names;
+mod scala;
+mod tuning;
+
+pub use names::*;
+pub use scala::*;
+pub use tuning::*;
+
+use parking_lot::{const_rwlock, RwLock};
+
+const NAMES: [&str; 12] = [
+    "C ", "C#", "D ", "D#", "E ", "F ", "F#", "G ", "G#", "A ", "A#", "B ",
+];
+
+/// The lowest note in the table is C1
+const LOWEST_OCTAVE: usize = 1;
+
+/// MIDI key number of the lowest note in the table
+pub const MIDI_OFFSET: usize = 24;
+
+/// Notes from C1 up to B8
+pub const TOTAL_NOTES: usize = 8 * NAMES.len();
+
+static NOTES_TABLE: RwLock<[NoteEntry; TOTAL_NOTES]> = const_rwlock(
+    [NoteEntry {
+        name: 0,
+        octave: 0,
+        frequency: 0.0,
+    }; TOTAL_NOTES],
+);
+
+static TUNING: RwLock<Option<Tuning>> = const_rwlock(None);
+
+#[derive(Clone, Copy)]
+pub struct NoteEntry {
+    name: usize,
+    octave: usize,
+    frequency: f32,
+}
+
+/// Fills the notes table with the default tuning, 12 tone equal temperament with A4 at 440 Hz.
+pub fn generate() {
+    set_tuning(Tuning::default());
+
+    println!(
+        "generated {} notes from {:?} to {:?}",
+        TOTAL_NOTES,
+        index_to_name(0),
+        index_to_name(TOTAL_NOTES - 1)
+    );
+}
+
+/// Switches every note to a new tuning. Notes which are already
+/// playing keep their pitch until they are pressed again.
+pub fn set_tuning(tuning: Tuning) {
+    let mut table = NOTES_TABLE.write();
+    table.iter_mut().enumerate().for_each(|(index, note)| {
+        *note = NoteEntry {
+            name: index % NAMES.len(),
+            octave: index / NAMES.len() + LOWEST_OCTAVE,
+            frequency: tuning.frequency(index + MIDI_OFFSET).unwrap_or_default() as f32,
+        }
+    });
+
+    *TUNING.write() = Some(tuning);
+}
+
+/// The tuning the notes table was generated from.
+pub fn tuning() -> Tuning {
+    TUNING.read().clone().unwrap_or_default()
+}
+
+/// Retunes the current tuning, so the note at index plays at frequency.
+pub fn set_reference_pitch(index: usize, frequency: f64) -> Result<(), TuningError> {
+    let mut tuning = tuning();
+    tuning.set_reference(index + MIDI_OFFSET, frequency)?;
+    set_tuning(tuning);
+    Ok(())
+}
+
+pub fn index_to_frequency(index: usize) -> f32 {
+    NOTES_TABLE.read()[index].frequency
+}
+
+/// Keyboard maps can leave keys without a pitch, and these shouldn't be played.
+pub fn is_mapped(index: usize) -> bool {
+    NOTES_TABLE.read()[index].frequency > 0.0
+}
+
+pub fn index_to_name(index: usize) -> String {
+    let note = NOTES_TABLE.read()[index];
+    format!("{}{}", NAMES[note.name], note.octave)
+}
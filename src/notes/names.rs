@@ -0,0 +1,213 @@
+use std::fmt;
+
+use super::{MIDI_OFFSET, TOTAL_NOTES};
+
+const SHARP_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+const FLAT_NAMES: [&str; 12] = [
+    "C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B",
+];
+const TRACKER_NAMES: [&str; 12] = [
+    "C-", "C#", "D-", "D#", "E-", "F-", "F#", "G-", "G#", "A-", "A#", "B-",
+];
+
+/// How to write the notes between the natural notes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoteFormat {
+    /// C#4
+    Sharps,
+    /// Db4
+    Flats,
+    /// C#4 and C-4, always three characters
+    Tracker,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NoteError {
+    Empty,
+    InvalidName(String),
+    InvalidOctave(String),
+    /// A valid note, outside of the notes table
+    OutOfRange {
+        midi: i32,
+    },
+    IndexOutOfRange(usize),
+}
+
+impl fmt::Display for NoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "no note given"),
+            Self::InvalidName(name) => write!(f, "invalid note name \"{}\"", name),
+            Self::InvalidOctave(note) => write!(f, "invalid octave in \"{}\"", note),
+            Self::OutOfRange { midi } => write!(
+                f,
+                "MIDI note {} is out of range, notes go from {} to {}",
+                midi,
+                MIDI_OFFSET,
+                MIDI_OFFSET + TOTAL_NOTES - 1
+            ),
+            Self::IndexOutOfRange(index) => write!(
+                f,
+                "note index {} is out of range, there are {} notes",
+                index, TOTAL_NOTES
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NoteError {}
+
+pub fn midi_to_index(midi: i32) -> Result<usize, NoteError> {
+    let index = midi.saturating_sub(MIDI_OFFSET as i32);
+
+    if (0..TOTAL_NOTES as i32).contains(&index) {
+        Ok(index as usize)
+    } else {
+        Err(NoteError::OutOfRange { midi })
+    }
+}
+
+pub fn index_to_midi(index: usize) -> usize {
+    index + MIDI_OFFSET
+}
+
+/// Parses a note into an index in the notes table. Accepts scientific pitch
+/// names ("C#4", "Db4", "e4"), tracker names ("A-3") and MIDI note numbers ("69").
+pub fn parse_note(text: &str) -> Result<usize, NoteError> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(NoteError::Empty);
+    }
+
+    if text.bytes().all(|byte| byte.is_ascii_digit()) {
+        let midi = text
+            .parse()
+            .map_err(|_| NoteError::InvalidName(text.to_string()))?;
+        return midi_to_index(midi);
+    }
+
+    let mut chars = text.chars();
+    let natural = match chars.next().map(|letter| letter.to_ascii_uppercase()) {
+        Some('C') => 0,
+        Some('D') => 2,
+        Some('E') => 4,
+        Some('F') => 5,
+        Some('G') => 7,
+        Some('A') => 9,
+        Some('B') => 11,
+        _ => return Err(NoteError::InvalidName(text.to_string())),
+    };
+
+    let rest = chars.as_str();
+    let (accidental, octave) = if let Some(octave) = rest.strip_prefix('-') {
+        // Tracker style natural
+        (0, octave)
+    } else {
+        let octave = rest.trim_start_matches(['#', 'b']);
+        let accidentals = &rest[..rest.len() - octave.len()];
+        let sharps = accidentals.matches('#').count() as i32;
+        let flats = accidentals.matches('b').count() as i32;
+
+        if sharps > 0 && flats > 0 {
+            return Err(NoteError::InvalidName(text.to_string()));
+        }
+        (sharps - flats, octave)
+    };
+
+    let octave: i32 = octave
+        .parse()
+        .map_err(|_| NoteError::InvalidOctave(text.to_string()))?;
+
+    // Saturating, so huge octaves are out of range rather than overflowing
+    let midi = octave
+        .saturating_add(1)
+        .saturating_mul(12)
+        .saturating_add(natural + accidental);
+    midi_to_index(midi)
+}
+
+/// Formats an index in the notes table, such as "C#4" or "Db4".
+pub fn format_note(index: usize, format: NoteFormat) -> Result<String, NoteError> {
+    if index >= TOTAL_NOTES {
+        return Err(NoteError::IndexOutOfRange(index));
+    }
+
+    let midi = index_to_midi(index);
+    let names = match format {
+        NoteFormat::Sharps => &SHARP_NAMES,
+        NoteFormat::Flats => &FLAT_NAMES,
+        NoteFormat::Tracker => &TRACKER_NAMES,
+    };
+
+    Ok(format!("{}{}", names[midi % 12], midi / 12 - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_style() {
+        assert_eq!(parse_note("A4"), Ok(45));
+        assert_eq!(parse_note("a4"), Ok(45));
+        assert_eq!(parse_note("C#4"), Ok(37));
+        assert_eq!(parse_note("Db4"), Ok(37));
+        assert_eq!(parse_note("A-3"), Ok(33));
+        assert_eq!(parse_note("69"), Ok(45));
+        assert_eq!(parse_note(" C1 "), Ok(0));
+        assert_eq!(parse_note("B8"), Ok(TOTAL_NOTES - 1));
+
+        // Accidentals can cross into the next octave
+        assert_eq!(parse_note("Cb4"), parse_note("B3"));
+        assert_eq!(parse_note("B#3"), parse_note("C4"));
+        assert_eq!(parse_note("Dbb4"), parse_note("C4"));
+    }
+
+    #[test]
+    fn reports_invalid_notes() {
+        assert_eq!(parse_note(""), Err(NoteError::Empty));
+        assert_eq!(parse_note("H4"), Err(NoteError::InvalidName("H4".into())));
+        assert_eq!(
+            parse_note("C#b4"),
+            Err(NoteError::InvalidName("C#b4".into()))
+        );
+        assert_eq!(parse_note("C"), Err(NoteError::InvalidOctave("C".into())));
+        assert_eq!(
+            parse_note("C4x"),
+            Err(NoteError::InvalidOctave("C4x".into()))
+        );
+        assert_eq!(parse_note("B0"), Err(NoteError::OutOfRange { midi: 23 }));
+        assert_eq!(parse_note("C9"), Err(NoteError::OutOfRange { midi: 120 }));
+        assert_eq!(parse_note("127"), Err(NoteError::OutOfRange { midi: 127 }));
+        assert_eq!(
+            parse_note("C999999999"),
+            Err(NoteError::OutOfRange { midi: i32::MAX })
+        );
+        assert_eq!(
+            parse_note("Cb-999999999"),
+            Err(NoteError::OutOfRange { midi: i32::MIN })
+        );
+        assert_eq!(
+            format_note(TOTAL_NOTES, NoteFormat::Sharps),
+            Err(NoteError::IndexOutOfRange(TOTAL_NOTES))
+        );
+    }
+
+    #[test]
+    fn formats_round_trip() {
+        assert_eq!(format_note(37, NoteFormat::Sharps).unwrap(), "C#4");
+        assert_eq!(format_note(37, NoteFormat::Flats).unwrap(), "Db4");
+        assert_eq!(format_note(36, NoteFormat::Tracker).unwrap(), "C-4");
+
+        [NoteFormat::Sharps, NoteFormat::Flats, NoteFormat::Tracker]
+            .into_iter()
+            .for_each(|format| {
+                (0..TOTAL_NOTES).for_each(|index| {
+                    let name = format_note(index, format).unwrap();
+                    assert_eq!(parse_note(&name), Ok(index), "{}", name);
+                })
+            });
+    }
+}
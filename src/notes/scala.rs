@@ -0,0 +1,279 @@
+use std::{fmt, fs, io, path::Path};
+
+use super::{KeyboardMap, Scale};
+
+/// Highest MIDI key a keyboard map can refer to
+const HIGHEST_KEY: usize = 127;
+
+#[derive(Debug)]
+pub enum TuningError {
+    Io(io::Error),
+    /// A required line is missing from the file
+    Missing(&'static str),
+    InvalidLine {
+        line: usize,
+        text: String,
+    },
+    InvalidPitch(String),
+    WrongCount {
+        expected: usize,
+        found: usize,
+    },
+    EmptyScale,
+    UnmappedReference(usize),
+}
+
+impl fmt::Display for TuningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "tuning file error: {}", error),
+            Self::Missing(field) => write!(f, "tuning file is missing the {}", field),
+            Self::InvalidLine { line, text } => write!(f, "invalid line {}: \"{}\"", line, text),
+            Self::InvalidPitch(pitch) => write!(f, "invalid pitch {}", pitch),
+            Self::WrongCount { expected, found } => {
+                write!(f, "expected {} entries, but found {}", expected, found)
+            }
+            Self::EmptyScale => write!(f, "scale has no notes"),
+            Self::UnmappedReference(key) => {
+                write!(f, "reference key {} is not mapped to the scale", key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TuningError {}
+
+impl From<io::Error> for TuningError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+/// Lines which aren't comments, with their line numbers from 1.
+fn lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.starts_with('!'))
+}
+
+/// Values may be followed by any text, which is ignored.
+fn first_word(line: usize, text: &str) -> Result<&str, TuningError> {
+    text.split_whitespace()
+        .next()
+        .ok_or_else(|| TuningError::InvalidLine {
+            line,
+            text: text.to_string(),
+        })
+}
+
+fn parse_number<T: std::str::FromStr>(line: usize, text: &str) -> Result<T, TuningError> {
+    first_word(line, text)?
+        .parse()
+        .map_err(|_| TuningError::InvalidLine {
+            line,
+            text: text.to_string(),
+        })
+}
+
+/// Pitches with a period are in cents, otherwise they are a ratio or a whole number.
+fn parse_pitch(line: usize, text: &str) -> Result<f64, TuningError> {
+    let word = first_word(line, text)?;
+    let invalid = || TuningError::InvalidLine {
+        line,
+        text: text.to_string(),
+    };
+
+    let ratio = if word.contains('.') {
+        let cents: f64 = word.parse().map_err(|_| invalid())?;
+        2.0f64.powf(cents / 1200.0)
+    } else {
+        let (numerator, denominator) = word.split_once('/').unwrap_or((word, "1"));
+        let numerator: u64 = numerator.parse().map_err(|_| invalid())?;
+        let denominator: u64 = denominator.parse().map_err(|_| invalid())?;
+        numerator as f64 / denominator as f64
+    };
+
+    if ratio.is_finite() && ratio > 0.0 {
+        Ok(ratio)
+    } else {
+        Err(invalid())
+    }
+}
+
+impl Scale {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TuningError> {
+        Self::parse_scl(&fs::read_to_string(path)?)
+    }
+
+    /// Parses the text of a Scala .scl file.
+    pub fn parse_scl(text: &str) -> Result<Self, TuningError> {
+        let mut lines = lines(text);
+
+        let (_, description) = lines.next().ok_or(TuningError::Missing("description"))?;
+        let (line, count) = lines.next().ok_or(TuningError::Missing("note count"))?;
+        let count: usize = parse_number(line, count)?;
+
+        let ratios = lines
+            .filter(|(_, text)| !text.is_empty())
+            .map(|(line, text)| parse_pitch(line, text))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if ratios.len() != count {
+            return Err(TuningError::WrongCount {
+                expected: count,
+                found: ratios.len(),
+            });
+        }
+
+        Scale::new(description, ratios)
+    }
+}
+
+impl KeyboardMap {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TuningError> {
+        Self::parse_kbm(&fs::read_to_string(path)?)
+    }
+
+    /// Parses the text of a Scala .kbm file.
+    pub fn parse_kbm(text: &str) -> Result<Self, TuningError> {
+        let mut lines = lines(text).filter(|(_, text)| !text.is_empty());
+        let mut next = |field| lines.next().ok_or(TuningError::Missing(field));
+
+        let (line, text) = next("map size")?;
+        let size: usize = parse_number(line, text)?;
+
+        let mut key = |field| -> Result<usize, TuningError> {
+            let (line, text) = next(field)?;
+            let key = parse_number(line, text)?;
+            if key > HIGHEST_KEY {
+                return Err(TuningError::InvalidLine {
+                    line,
+                    text: text.to_string(),
+                });
+            }
+            Ok(key)
+        };
+
+        let first_key = key("first key")?;
+        let last_key = key("last key")?;
+        let middle_key = key("middle key")?;
+        let reference_key = key("reference key")?;
+
+        let (line, text) = next("reference frequency")?;
+        let reference_frequency: f64 = parse_number(line, text)?;
+        if !reference_frequency.is_finite() || reference_frequency <= 0.0 {
+            return Err(TuningError::InvalidPitch(text.to_string()));
+        }
+
+        let (line, text) = next("octave degree")?;
+        let octave_degree = parse_number(line, text)?;
+
+        // Keys past the end of a short mapping are left unmapped
+        let mut mapping = lines
+            .map(|(line, text)| match first_word(line, text)? {
+                "x" => Ok(None),
+                _ => parse_number(line, text).map(Some),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if mapping.len() > size {
+            return Err(TuningError::WrongCount {
+                expected: size,
+                found: mapping.len(),
+            });
+        }
+        mapping.resize(size, None);
+
+        Ok(Self {
+            first_key,
+            last_key,
+            middle_key,
+            reference_key,
+            reference_frequency,
+            octave_degree,
+            mapping,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::Tuning;
+
+    #[test]
+    fn parses_cents_and_ratios() {
+        let scale = Scale::parse_scl(
+            "! meantone.scl
+!
+Quarter comma meantone, partial
+ 4
+!
+ 76.04900
+ 5/4   major third
+ 3
+ 1200.0
+",
+        )
+        .unwrap();
+
+        assert_eq!(scale.description(), "Quarter comma meantone, partial");
+        assert_eq!(scale.len(), 4);
+        assert!((scale.ratios[0] - 1.044_906_7).abs() < 1e-6);
+        assert_eq!(scale.ratios[1], 1.25);
+        assert_eq!(scale.ratios[2], 3.0);
+        assert!((scale.period() - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn reports_invalid_scales() {
+        assert!(matches!(
+            Scale::parse_scl("description\n3\n100.0\n200.0\n"),
+            Err(TuningError::WrongCount {
+                expected: 3,
+                found: 2
+            })
+        ));
+        assert!(matches!(
+            Scale::parse_scl("description\n1\n3/0\n"),
+            Err(TuningError::InvalidLine { line: 3, .. })
+        ));
+        assert!(matches!(
+            Scale::parse_scl("description\n0\n"),
+            Err(TuningError::EmptyScale)
+        ));
+        assert!(matches!(
+            Scale::parse_scl("! only a comment"),
+            Err(TuningError::Missing("description"))
+        ));
+    }
+
+    #[test]
+    fn parses_keyboard_maps() {
+        let map = KeyboardMap::parse_kbm(
+            "! white keys only
+5
+0
+127
+60
+67
+432.0
+5
+! mapping
+0
+x
+1
+2
+",
+        )
+        .unwrap();
+
+        assert_eq!(map.mapping, vec![Some(0), None, Some(1), Some(2), None]);
+        assert_eq!(map.reference_frequency, 432.0);
+
+        let tuning = Tuning::new(Scale::equal_temperament(5), map).unwrap();
+        assert!((tuning.frequency(67).unwrap() - 432.0).abs() < 1e-9);
+        assert_eq!(tuning.frequency(61), None);
+    }
+}
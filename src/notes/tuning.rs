@@ -0,0 +1,288 @@
+use std::path::Path;
+
+use super::TuningError;
+
+/// MIDI key of A4
+const DEFAULT_REFERENCE_KEY: usize = 69;
+const DEFAULT_REFERENCE_FREQUENCY: f64 = 440.0;
+
+/// MIDI key of C4, where the first degree of a scale is placed by default
+const DEFAULT_MIDDLE_KEY: usize = 60;
+
+const HIGHEST_KEY: usize = 127;
+
+/// The pitches of a scale, as ratios above its first degree. The last ratio
+/// is the period the scale repeats at, usually an octave (2/1).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scale {
+    pub(crate) description: String,
+    pub(crate) ratios: Vec<f64>,
+}
+
+impl Scale {
+    pub fn new(description: impl Into<String>, ratios: Vec<f64>) -> Result<Self, TuningError> {
+        if ratios.is_empty() {
+            return Err(TuningError::EmptyScale);
+        }
+        if let Some(ratio) = ratios
+            .iter()
+            .find(|ratio| !ratio.is_finite() || **ratio <= 0.0)
+        {
+            return Err(TuningError::InvalidPitch(ratio.to_string()));
+        }
+
+        Ok(Self {
+            description: description.into(),
+            ratios,
+        })
+    }
+
+    /// Divides the octave into equal steps.
+    pub fn equal_temperament(divisions: usize) -> Self {
+        assert!(divisions > 0);
+
+        Self {
+            description: format!("{} tone equal temperament", divisions),
+            ratios: (1..=divisions)
+                .map(|step| 2.0f64.powf(step as f64 / divisions as f64))
+                .collect(),
+        }
+    }
+
+    /// How many degrees there are before the scale repeats.
+    pub fn len(&self) -> usize {
+        self.ratios.len()
+    }
+
+    pub fn period(&self) -> f64 {
+        self.ratios[self.ratios.len() - 1]
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// The ratio of any degree to the first, repeating the scale above and below.
+    fn ratio(&self, degree: i64) -> f64 {
+        let len = self.len() as i64;
+        let period = degree.div_euclid(len);
+        let step = degree.rem_euclid(len) as usize;
+
+        let ratio = match step {
+            0 => 1.0,
+            step => self.ratios[step - 1],
+        };
+
+        ratio * self.period().powi(period as i32)
+    }
+}
+
+/// Maps MIDI keys onto the degrees of a scale, as a Scala .kbm file does.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyboardMap {
+    pub(crate) first_key: usize,
+    pub(crate) last_key: usize,
+    /// The key which plays the first degree of the scale
+    pub(crate) middle_key: usize,
+    pub(crate) reference_key: usize,
+    pub(crate) reference_frequency: f64,
+    /// The degree reached after one repeat of the mapping
+    pub(crate) octave_degree: usize,
+    /// The degree each key plays, repeating every mapping.len() keys. None
+    /// leaves the key unmapped. An empty mapping maps each key to the next degree.
+    pub(crate) mapping: Vec<Option<usize>>,
+}
+
+impl KeyboardMap {
+    /// Maps each key to the next degree of the scale, with the first degree on
+    /// middle C, and A4 at 440 Hz.
+    pub fn linear(scale_len: usize) -> Self {
+        Self {
+            first_key: 0,
+            last_key: HIGHEST_KEY,
+            middle_key: DEFAULT_MIDDLE_KEY,
+            reference_key: DEFAULT_REFERENCE_KEY,
+            reference_frequency: DEFAULT_REFERENCE_FREQUENCY,
+            octave_degree: scale_len,
+            mapping: Vec::new(),
+        }
+    }
+
+    /// Which degree of the scale a key plays, relative to the middle key.
+    fn degree(&self, key: usize) -> Option<i64> {
+        if key < self.first_key || key > self.last_key {
+            return None;
+        }
+
+        let offset = key as i64 - self.middle_key as i64;
+        if self.mapping.is_empty() {
+            return Some(offset);
+        }
+
+        let size = self.mapping.len() as i64;
+        let repeat = offset.div_euclid(size);
+        let index = offset.rem_euclid(size) as usize;
+
+        self.mapping[index].map(|degree| repeat * self.octave_degree as i64 + degree as i64)
+    }
+}
+
+/// A scale and how it's laid out on the keyboard.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tuning {
+    pub(crate) scale: Scale,
+    pub(crate) keyboard_map: KeyboardMap,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::from_scale(Scale::equal_temperament(12))
+    }
+}
+
+impl Tuning {
+    pub fn new(scale: Scale, keyboard_map: KeyboardMap) -> Result<Self, TuningError> {
+        if keyboard_map.degree(keyboard_map.reference_key).is_none() {
+            return Err(TuningError::UnmappedReference(keyboard_map.reference_key));
+        }
+
+        Ok(Self {
+            scale,
+            keyboard_map,
+        })
+    }
+
+    /// Lays the scale out linearly on the keyboard, starting from middle C.
+    pub fn from_scale(scale: Scale) -> Self {
+        let keyboard_map = KeyboardMap::linear(scale.len());
+        Self {
+            scale,
+            keyboard_map,
+        }
+    }
+
+    /// Loads a Scala scale, and optionally a keyboard map. Without a keyboard
+    /// map the scale is laid out with `from_scale`.
+    pub fn load(
+        scale_path: impl AsRef<Path>,
+        keyboard_map_path: Option<impl AsRef<Path>>,
+    ) -> Result<Self, TuningError> {
+        let scale = Scale::load(scale_path)?;
+
+        match keyboard_map_path {
+            Some(path) => Self::new(scale, KeyboardMap::load(path)?),
+            None => Ok(Self::from_scale(scale)),
+        }
+    }
+
+    pub fn scale(&self) -> &Scale {
+        &self.scale
+    }
+
+    pub fn reference(&self) -> (usize, f64) {
+        (
+            self.keyboard_map.reference_key,
+            self.keyboard_map.reference_frequency,
+        )
+    }
+
+    /// Tunes the whole scale so the MIDI key plays at frequency.
+    pub fn set_reference(&mut self, key: usize, frequency: f64) -> Result<(), TuningError> {
+        if !frequency.is_finite() || frequency <= 0.0 {
+            return Err(TuningError::InvalidPitch(frequency.to_string()));
+        }
+        if self.keyboard_map.degree(key).is_none() {
+            return Err(TuningError::UnmappedReference(key));
+        }
+
+        self.keyboard_map.reference_key = key;
+        self.keyboard_map.reference_frequency = frequency;
+        Ok(())
+    }
+
+    /// The frequency of a MIDI key, or None if the keyboard map leaves it unmapped.
+    pub fn frequency(&self, key: usize) -> Option<f64> {
+        let map = &self.keyboard_map;
+        let degree = map.degree(key)?;
+        let reference_degree = map.degree(map.reference_key)?;
+
+        Some(
+            map.reference_frequency * self.scale.ratio(degree) / self.scale.ratio(reference_degree),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.unwrap();
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn default_is_twelve_tone_equal_temperament() {
+        let tuning = Tuning::default();
+
+        assert_close(tuning.frequency(69), 440.0);
+        assert_close(tuning.frequency(81), 880.0);
+        assert_close(tuning.frequency(60), 261.625_565_300_6);
+        assert_close(tuning.frequency(24), 32.703_195_662_6);
+    }
+
+    #[test]
+    fn just_intonation_follows_the_reference() {
+        let scale = Scale::new(
+            "just major",
+            vec![
+                9.0 / 8.0,
+                5.0 / 4.0,
+                4.0 / 3.0,
+                3.0 / 2.0,
+                5.0 / 3.0,
+                15.0 / 8.0,
+                2.0,
+            ],
+        )
+        .unwrap();
+        let map = KeyboardMap {
+            first_key: 0,
+            last_key: 127,
+            middle_key: 60,
+            reference_key: 60,
+            reference_frequency: 264.0,
+            octave_degree: 7,
+            mapping: vec![
+                Some(0),
+                None,
+                Some(1),
+                None,
+                Some(2),
+                Some(3),
+                None,
+                Some(4),
+                None,
+                Some(5),
+                None,
+                Some(6),
+            ],
+        };
+        let mut tuning = Tuning::new(scale, map).unwrap();
+
+        assert_close(tuning.frequency(64), 330.0);
+        assert_close(tuning.frequency(67), 396.0);
+        assert_close(tuning.frequency(72), 528.0);
+        assert_close(tuning.frequency(59), 247.5);
+        assert_eq!(tuning.frequency(61), None);
+
+        tuning.set_reference(69, 432.0).unwrap();
+        assert_close(tuning.frequency(60), 259.2);
+        assert!(tuning.set_reference(61, 440.0).is_err());
+    }
+}
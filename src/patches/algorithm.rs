@@ -1,79 +1,50 @@
-use super::OPERATOR_COUNT;
+use serde::{Deserialize, Serialize};
 
-pub enum ModulatedBy {
-    None,
-    Single(usize),
-    Double(usize, usize),
-}
+use super::{RoutingMatrix, OPERATOR_COUNT};
 
-#[derive(PartialEq, Copy, Clone, Debug, Default)]
+/// The eight classic OPN routings, kept as presets for `RoutingMatrix`.
+#[derive(PartialEq, Copy, Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Algorithm(pub u8);
 
+pub const ALGORITHM_MAX: u8 = 7;
+
 impl Algorithm {
-    pub fn get_definition(self) -> &'static AlgorithmDefinition {
-        match self.0 {
-            0 => &AlgorithmDefinition {
-                carriers: [false, false, false, true],
-                modulators: [
-                    ModulatedBy::Single(0),
-                    ModulatedBy::Single(1),
-                    ModulatedBy::Single(2),
-                ],
-            },
-            1 => &AlgorithmDefinition {
-                carriers: [false, false, false, true],
-                modulators: [
-                    ModulatedBy::None,
-                    ModulatedBy::Double(0, 1),
-                    ModulatedBy::Single(2),
-                ],
-            },
-            2 => &AlgorithmDefinition {
-                carriers: [false, false, false, true],
-                modulators: [
-                    ModulatedBy::None,
-                    ModulatedBy::Single(1),
-                    ModulatedBy::Single(2),
-                ],
-            },
-            3 => &AlgorithmDefinition {
-                carriers: [false, false, false, true],
-                modulators: [
-                    ModulatedBy::Single(0),
-                    ModulatedBy::None,
-                    ModulatedBy::Double(1, 2),
-                ],
-            },
-            4 => &AlgorithmDefinition {
-                carriers: [false, true, false, true],
-                modulators: [
-                    ModulatedBy::Single(0),
-                    ModulatedBy::None,
-                    ModulatedBy::Single(2),
-                ],
-            },
-            5 => &AlgorithmDefinition {
-                carriers: [false, true, true, true],
-                modulators: [
-                    ModulatedBy::Single(0),
-                    ModulatedBy::Single(0),
-                    ModulatedBy::Single(0),
-                ],
-            },
-            6 => &AlgorithmDefinition {
-                carriers: [false, true, true, true],
-                modulators: [ModulatedBy::Single(0), ModulatedBy::None, ModulatedBy::None],
-            },
-            7 => &AlgorithmDefinition {
-                carriers: [true, true, true, true],
-                modulators: [ModulatedBy::None, ModulatedBy::None, ModulatedBy::None],
-            },
-            _ => panic!("invalid algorithm value"),
-        }
+    /// The routing of this algorithm. Values above `ALGORITHM_MAX` are treated as the last one.
+    pub fn routing(self) -> RoutingMatrix {
+        // Each operator with the operators modulating it
+        let (carriers, modulators): ([bool; OPERATOR_COUNT], [&[usize]; OPERATOR_COUNT]) =
+            match self.0.min(ALGORITHM_MAX) {
+                0 => ([false, false, false, true], [&[], &[0], &[1], &[2]]),
+                1 => ([false, false, false, true], [&[], &[], &[0, 1], &[2]]),
+                2 => ([false, false, false, true], [&[], &[], &[1], &[2]]),
+                3 => ([false, false, false, true], [&[], &[0], &[], &[1, 2]]),
+                4 => ([false, true, false, true], [&[], &[0], &[], &[2]]),
+                5 => ([false, true, true, true], [&[], &[0], &[0], &[0]]),
+                6 => ([false, true, true, true], [&[], &[0], &[], &[]]),
+                _ => ([true, true, true, true], [&[], &[], &[], &[]]),
+            };
+
+        let mut routing = RoutingMatrix::additive();
+        (0..OPERATOR_COUNT).for_each(|target| {
+            routing.output[target] = if carriers[target] { 1.0 } else { 0.0 };
+            modulators[target]
+                .iter()
+                .for_each(|&source| routing.modulation[target][source] = 1.0);
+        });
+
+        routing
     }
 }
 
-pub struct AlgorithmDefinition {
-    pub(crate) carriers: [bool; OPERATOR_COUNT],
-    pub(crate) modulators: [ModulatedBy; OPERATOR_COUNT - 1],
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_have_no_cycles() {
+        (0..=ALGORITHM_MAX).for_each(|algorithm| {
+            assert_eq!(Algorithm(algorithm).routing().order(), Ok([0, 1, 2, 3]));
+        });
+        assert_eq!(Algorithm(200).routing(), Algorithm(ALGORITHM_MAX).routing());
+    }
 }
@@ -1,10 +1,16 @@
-use std::sync::Arc;
+use std::{fmt, sync::Arc};
 
 use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
 
-use super::{attenuation_table_u10, attenuation_table_u8, ATTENUATION_MAX};
+use super::{
+    attenuation_table_u10, attenuation_table_u8, attenuation_to_volume, effective_rate,
+    effective_release_rate, eg_increment, sustain_attenuation, RenderMode, ATTENUATION_MAX,
+    EG_ATTENUATION_MAX, EG_INSTANT_ATTACK_RATE, ENV_DB,
+};
+use crate::TARGET_SAMPLE_RATE;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct EnvelopeDefinition {
     pub(crate) total_level: u8,
     pub(crate) sustain_level: u8,
@@ -13,6 +19,228 @@ pub struct EnvelopeDefinition {
     pub(crate) decay_attack_rate: u8,
     pub(crate) decay_sustain_rate: u8,
     pub(crate) release_rate: u8,
+
+    /// How much higher notes speed up the rates, from 0 to 3. Like the
+    /// hardware, even 0 speeds up the highest notes slightly.
+    #[serde(default)]
+    pub(crate) key_scale: u8,
+
+    #[serde(default)]
+    pub(crate) ssg_eg: SsgEgMode,
+
+    /// Milliseconds of silence after key on, before the attack starts. 0 skips it.
+    #[serde(default)]
+    pub(crate) delay: u16,
+    /// Milliseconds to stay at full level after the attack, before decaying. 0 skips it.
+    #[serde(default)]
+    pub(crate) hold: u16,
+
+    #[serde(default)]
+    pub(crate) attack_curve: EnvelopeCurve,
+    #[serde(default)]
+    pub(crate) decay_curve: EnvelopeCurve,
+    #[serde(default)]
+    pub(crate) sustain_curve: EnvelopeCurve,
+    #[serde(default)]
+    pub(crate) release_curve: EnvelopeCurve,
+}
+
+pub const KEY_SCALE_MAX: u8 = 3;
+
+/// Longest delay or hold, in milliseconds.
+pub const STAGE_TIME_MAX: u16 = 10_000;
+
+/// Frequency of C0, where the YM2612's lowest block starts
+const KEY_CODE_BASE_FREQUENCY: f32 = 16.351_6;
+const KEY_CODE_MAX: u8 = 31;
+
+/// The YM2612's 5 bit key code for a frequency: its block (octave) and
+/// which quarter of the octave it falls in.
+pub fn key_code(frequency: f32) -> u8 {
+    let quarter_octaves = (4.0 * (frequency / KEY_CODE_BASE_FREQUENCY).log2()).floor();
+    quarter_octaves.clamp(0.0, KEY_CODE_MAX as f32) as u8
+}
+
+/// The YM2612's SSG-EG modes, which loop or invert the envelope once it has
+/// decayed halfway. The shapes show the level over time while a key is held.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SsgEgMode {
+    #[default]
+    Off,
+    /// \\\\ (8)
+    Repeat,
+    /// \___ (9)
+    Once,
+    /// \/\/ (10)
+    Alternate,
+    /// \‾‾‾ (11)
+    OnceHoldHigh,
+    /// //// (12)
+    InvertedRepeat,
+    /// /‾‾‾ (13)
+    InvertedOnce,
+    /// /\/\ (14)
+    InvertedAlternate,
+    /// /___ (15)
+    InvertedOnceHoldLow,
+}
+
+impl SsgEgMode {
+    pub const ALL: [Self; 9] = [
+        Self::Off,
+        Self::Repeat,
+        Self::Once,
+        Self::Alternate,
+        Self::OnceHoldHigh,
+        Self::InvertedRepeat,
+        Self::InvertedOnce,
+        Self::InvertedAlternate,
+        Self::InvertedOnceHoldLow,
+    ];
+
+    /// The mode's value in the YM2612's SSG-EG register, 0 when off.
+    pub fn register(self) -> u8 {
+        match self {
+            Self::Off => 0,
+            Self::Repeat => 8,
+            Self::Once => 9,
+            Self::Alternate => 10,
+            Self::OnceHoldHigh => 11,
+            Self::InvertedRepeat => 12,
+            Self::InvertedOnce => 13,
+            Self::InvertedAlternate => 14,
+            Self::InvertedOnceHoldLow => 15,
+        }
+    }
+
+    /// Modes are only enabled when bit 3 is set, as on the hardware.
+    pub fn from_register(register: u8) -> Self {
+        match register & 0xF {
+            8 => Self::Repeat,
+            9 => Self::Once,
+            10 => Self::Alternate,
+            11 => Self::OnceHoldHigh,
+            12 => Self::InvertedRepeat,
+            13 => Self::InvertedOnce,
+            14 => Self::InvertedAlternate,
+            15 => Self::InvertedOnceHoldLow,
+            _ => Self::Off,
+        }
+    }
+
+    pub fn is_enabled(self) -> bool {
+        self != Self::Off
+    }
+
+    /// Stop after the first cycle, instead of looping.
+    fn hold(self) -> bool {
+        self.register() & 0b001 != 0
+    }
+
+    /// Flip the output after every cycle.
+    fn alternate(self) -> bool {
+        self.register() & 0b010 != 0
+    }
+
+    /// Start with the output flipped.
+    fn invert(self) -> bool {
+        self.register() & 0b100 != 0
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Repeat => "\\\\\\\\",
+            Self::Once => "\\___",
+            Self::Alternate => "\\/\\/",
+            Self::OnceHoldHigh => "\\‾‾‾",
+            Self::InvertedRepeat => "////",
+            Self::InvertedOnce => "/‾‾‾",
+            Self::InvertedAlternate => "/\\/\\",
+            Self::InvertedOnceHoldLow => "/___",
+        }
+    }
+}
+
+/// The shape of one segment of a classic envelope. Each curve crosses the full
+/// range in about the same time, so the rates keep their meaning.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EnvelopeCurve {
+    /// Moves evenly in decibels, so it sounds even to the ear.
+    #[default]
+    LinearDb,
+    /// Moves further the further it is from its target, like the OPN chips'
+    /// attack. Attacks jump up and then ease into full level.
+    Exponential,
+    /// Moves evenly in amplitude, like most analog and sample based envelopes.
+    /// Attacks swell slowly and decays drop away at the end.
+    LinearAmplitude,
+}
+
+impl EnvelopeCurve {
+    pub const ALL: [Self; 3] = [Self::LinearDb, Self::Exponential, Self::LinearAmplitude];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::LinearDb => "dB",
+            Self::Exponential => "Exp",
+            Self::LinearAmplitude => "Lin",
+        }
+    }
+
+    /// How far to move from `attenuation` this tick, given the rate's step and
+    /// how far is left to go.
+    fn step(self, step: f32, attenuation: f32, remaining: f32) -> f32 {
+        if step == 0.0 {
+            return 0.0;
+        }
+
+        match self {
+            Self::LinearDb => step,
+            Self::Exponential => {
+                // The extra step makes sure it arrives, rather than only getting close
+                step * (remaining + 1.0) * EXPONENTIAL_CURVE_SPEED as f32 / ENVELOPE_MAX
+            }
+            Self::LinearAmplitude => {
+                let index = (attenuation as u16).min(ATTENUATION_MAX - 1);
+                let amplitude = attenuation_table_u10(index);
+
+                // A constant change in amplitude is a change in attenuation
+                // inversely proportional to the amplitude
+                (step / (AMPLITUDE_CURVE_SLOPE * amplitude)).min(ENVELOPE_MAX)
+            }
+        }
+    }
+
+    /// The fixed point version of `step`, with ENVELOPE_FRACTION_BITS. Never
+    /// rounds a moving envelope down to standing still.
+    fn fixed_step(self, step: u32, attenuation: u32, remaining: u32) -> u32 {
+        if step == 0 {
+            return 0;
+        }
+
+        match self {
+            Self::LinearDb => step,
+            Self::Exponential => {
+                let remaining = remaining as u64 + (1 << ENVELOPE_FRACTION_BITS);
+                let step = step as u64 * remaining * EXPONENTIAL_CURVE_SPEED as u64
+                    / FIXED_ENVELOPE_MAX as u64;
+                step.max(1) as u32
+            }
+            Self::LinearAmplitude => {
+                // The exp table's amplitude, which is 1 << 13 at full level, with
+                // 16 more bits so the quietest levels aren't shifted away
+                let index = (attenuation >> ENVELOPE_FRACTION_BITS).min(ATTENUATION_MAX as u32 - 1);
+                let exponent = (index << 2) >> 8;
+                let mantissa = attenuation_to_volume((index << 2) & 0xFF) as u64;
+                let amplitude = (mantissa << 16) >> exponent;
+
+                let step = ((step as u64) << (13 + 16 + AMPLITUDE_CURVE_SLOPE_BITS))
+                    / (FIXED_AMPLITUDE_CURVE_SLOPE * amplitude);
+                step.clamp(1, FIXED_ENVELOPE_MAX as u64) as u32
+            }
+        }
+    }
 }
 
 impl Default for EnvelopeDefinition {
@@ -25,10 +253,32 @@ impl Default for EnvelopeDefinition {
             decay_attack_rate: 0,
             decay_sustain_rate: 0,
             release_rate: u8::MAX,
+
+            key_scale: 0,
+            ssg_eg: SsgEgMode::Off,
+
+            delay: 0,
+            hold: 0,
+            attack_curve: EnvelopeCurve::default(),
+            decay_curve: EnvelopeCurve::default(),
+            sustain_curve: EnvelopeCurve::default(),
+            release_curve: EnvelopeCurve::default(),
         }
     }
 }
 
+/// Which envelope generator a patch uses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EnvelopeMode {
+    /// Smooth rates over the full 0-255 range of each setting.
+    #[default]
+    Classic,
+    /// Follows the YM2612's envelope generator step for step. Each setting is
+    /// reduced to the register's bits, such as 5 bits for the rates. Delay,
+    /// hold and curves are ignored, as the chip has none of them.
+    Ym2612,
+}
+
 impl EnvelopeDefinition {
     pub fn new(
         total_level: u8,
@@ -45,125 +295,1354 @@ impl EnvelopeDefinition {
             decay_attack_rate,
             decay_sustain_rate,
             release_rate,
+            ..Self::default()
+        }
+    }
+
+    /// Creates an envelope from YM2612 register values: a 7 bit total level, 5 bit
+    /// attack and decay rates, and a 4 bit sustain level and release rate. Total
+    /// level and sustain level are attenuations, so 0 is the loudest.
+    pub fn from_registers(
+        total_level: u8,
+        attack_rate: u8,
+        decay_attack_rate: u8,
+        sustain_level: u8,
+        decay_sustain_rate: u8,
+        release_rate: u8,
+        key_scale: u8,
+    ) -> Self {
+        // Spread each register across the full range, so they convert back exactly
+        let rate = |rate: u8| (rate & 0x1F) << 3 | (rate & 0x1F) >> 2;
+        let nibble = |value: u8| (value & 0xF) << 4 | (value & 0xF);
+
+        Self {
+            key_scale: key_scale.min(KEY_SCALE_MAX),
+            ..Self::new(
+                u8::MAX - ((total_level & 0x7F) << 1),
+                rate(attack_rate),
+                rate(decay_attack_rate),
+                u8::MAX - nibble(sustain_level),
+                rate(decay_sustain_rate),
+                nibble(release_rate),
+            )
+        }
+    }
+}
+
+/// The registers `EnvelopeDefinition::parse_registers` reads, in order, with
+/// the largest value of each. SSG-EG is the only one which can be left out.
+const REGISTERS: [(&str, u8); 8] = [
+    ("TL", 127),
+    ("AR", 31),
+    ("DR", 31),
+    ("SL", 15),
+    ("SR", 31),
+    ("RR", 15),
+    ("KS", 3),
+    ("SSG", 15),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RegisterError {
+    /// How many values were given, when there should be one for each register
+    Count(usize),
+    InvalidValue(String),
+    OutOfRange {
+        register: &'static str,
+        value: u32,
+        max: u8,
+    },
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Count(count) => write!(
+                f,
+                "expected {} or {} register values ({}), found {}",
+                REGISTERS.len() - 1,
+                REGISTERS.len(),
+                REGISTERS.map(|(name, _)| name).join(" "),
+                count
+            ),
+            Self::InvalidValue(value) => write!(f, "\"{}\" is not a register value", value),
+            Self::OutOfRange {
+                register,
+                value,
+                max,
+            } => write!(f, "{} is {}, but can be at most {}", register, value, max),
         }
     }
 }
 
+impl std::error::Error for RegisterError {}
+
+impl EnvelopeDefinition {
+    /// Reads an envelope from YM2612 register values, as listed by patch editors
+    /// and sound driver sources, for porting patches: total level, attack rate,
+    /// decay rate, sustain level, sustain rate, release rate, key scale and
+    /// optionally SSG-EG, in decimal and separated by spaces or commas.
+    pub fn parse_registers(text: &str) -> Result<Self, RegisterError> {
+        let values = text
+            .split(|character: char| character == ',' || character.is_whitespace())
+            .filter(|value| !value.is_empty())
+            .collect::<Vec<_>>();
+        if !(REGISTERS.len() - 1..=REGISTERS.len()).contains(&values.len()) {
+            return Err(RegisterError::Count(values.len()));
+        }
+
+        let mut registers = [0; REGISTERS.len()];
+        for ((register, (name, max)), value) in registers.iter_mut().zip(REGISTERS).zip(values) {
+            let value: u32 = value
+                .parse()
+                .map_err(|_| RegisterError::InvalidValue(value.to_string()))?;
+            if value > max as u32 {
+                return Err(RegisterError::OutOfRange {
+                    register: name,
+                    value,
+                    max,
+                });
+            }
+            *register = value as u8;
+        }
+
+        let [total_level, attack_rate, decay_rate, sustain_level, sustain_rate, release_rate, key_scale, ssg_eg] =
+            registers;
+        Ok(Self {
+            ssg_eg: SsgEgMode::from_register(ssg_eg),
+            ..Self::from_registers(
+                total_level,
+                attack_rate,
+                decay_rate,
+                sustain_level,
+                sustain_rate,
+                release_rate,
+                key_scale,
+            )
+        })
+    }
+}
+
+/// The float path's envelope moves in fractions of a 10 bit attenuation step.
+const ENVELOPE_MAX: f32 = ATTENUATION_MAX as f32;
+
+/// Fractional bits of the fixed point path's envelope, so slow rates can still move it.
+const ENVELOPE_FRACTION_BITS: u32 = 21;
+const FIXED_ENVELOPE_MAX: u32 = (ATTENUATION_MAX as u32) << ENVELOPE_FRACTION_BITS;
+
+/// Exponential curves move this many times faster than linear ones at the
+/// far end, and are within 2% of their target after a linear curve's time.
+const EXPONENTIAL_CURVE_SPEED: u32 = 4;
+
+/// The change in amplitude for each step of attenuation, relative to the
+/// amplitude: ln(10) / 20 per dB, and ENV_DB dB across the range.
+const AMPLITUDE_CURVE_SLOPE: f32 = ENV_DB * std::f32::consts::LN_10 / 20.0;
+
+/// The slope in 256ths for the fixed point path, worked out by the compiler.
+const AMPLITUDE_CURVE_SLOPE_BITS: u32 = 8;
+const FIXED_AMPLITUDE_CURVE_SLOPE: u64 =
+    (AMPLITUDE_CURVE_SLOPE * (1 << AMPLITUDE_CURVE_SLOPE_BITS) as f32) as u64;
+
+/// SSG-EG cycles end once the envelope has decayed to this many steps.
+const SSG_EG_MAX: u32 = 0x200;
+
+/// The hardware moves 4 times faster while SSG-EG is enabled.
+const SSG_EG_RATE_MULTIPLIER: u32 = 4;
+
+/// Rates are defined by how far they move each tick at this tick rate. Envelopes
+/// can tick at any rate, and their steps are scaled to keep the same timing.
+const ENVELOPE_REFERENCE_RATE: u32 = 48_000;
+
+/// Rates go from 0 (never moves) to 255, which crosses the full range in about
+/// 21 ms. Rates are cubed, so the slow end of the range is more usable, and
+/// halving a rate makes it take 8 times as long. This is the fixed point
+/// path's step, with ENVELOPE_FRACTION_BITS.
+fn rate_to_step(rate: u8, tick_rate: u32) -> u32 {
+    if rate == 0 {
+        return 0;
+    }
+
+    let max = (u8::MAX as u64).pow(3) * tick_rate as u64;
+    let step =
+        ((rate as u64).pow(3) << ENVELOPE_FRACTION_BITS) * ENVELOPE_REFERENCE_RATE as u64 / max;
+
+    // The slowest rates would otherwise round down to never moving
+    step.max(1) as u32
+}
+
+/// The float path's step, which the slowest rates don't have to round.
+fn rate_to_float_step(rate: u8, tick_rate: u32) -> f32 {
+    // Cubed by hand, as powi can round differently between platforms
+    let rate = rate as f32 / u8::MAX as f32;
+    rate * rate * rate * ENVELOPE_REFERENCE_RATE as f32 / tick_rate as f32
+}
+
+/// How long a rate takes to move the envelope by some 10 bit attenuation
+/// steps, or None if it never moves.
+pub fn rate_to_seconds(rate: u8, steps: u32) -> Option<f32> {
+    let step = rate_to_float_step(rate, ENVELOPE_REFERENCE_RATE);
+    if step == 0.0 {
+        return None;
+    }
+
+    let ticks = steps as f32 / step;
+    Some(ticks / ENVELOPE_REFERENCE_RATE as f32)
+}
+
+fn milliseconds_to_ticks(milliseconds: u16, tick_rate: u32) -> u32 {
+    milliseconds as u32 * tick_rate / 1000
+}
+
+/// How long each phase takes, ignoring key scaling, so for the lowest notes.
+/// Curves other than dB take about as long. None means the phase never ends.
+impl EnvelopeDefinition {
+    /// From silence to full level.
+    pub fn attack_time(&self) -> Option<f32> {
+        rate_to_seconds(self.attack_rate, ATTENUATION_MAX as u32)
+    }
+
+    /// From full level down to the sustain level.
+    pub fn decay_time(&self) -> Option<f32> {
+        rate_to_seconds(
+            self.decay_attack_rate,
+            u8::MAX as u32 - self.sustain_level as u32,
+        )
+    }
+
+    /// From the sustain level to silence, while the key is held.
+    pub fn sustain_time(&self) -> Option<f32> {
+        let steps = ATTENUATION_MAX as u32 - (u8::MAX - self.sustain_level) as u32;
+        rate_to_seconds(self.decay_sustain_rate, steps)
+    }
+
+    /// From full level to silence, after the key is released.
+    pub fn release_time(&self) -> Option<f32> {
+        rate_to_seconds(self.release_rate, ATTENUATION_MAX as u32)
+    }
+}
+
 impl EnvelopeDefinition {
-    fn get_attack_rate(&self) -> f32 {
-        (self.attack_rate as f32 / u8::MAX as f32).powi(3)
+    fn delay_ticks(&self, tick_rate: u32) -> u32 {
+        milliseconds_to_ticks(self.delay, tick_rate)
+    }
+
+    fn hold_ticks(&self, tick_rate: u32) -> u32 {
+        milliseconds_to_ticks(self.hold, tick_rate)
+    }
+
+    /// How much the hardware raises its 64 step rates for a key code.
+    pub(crate) fn key_scale_offset(&self, key_code: u8) -> u8 {
+        key_code >> (KEY_SCALE_MAX - self.key_scale.min(KEY_SCALE_MAX))
+    }
+
+    /// Raises a rate for higher key codes. The hardware adds up to 31 to its
+    /// 64 step rates, which is 4 times that in our 256 steps.
+    fn key_scaled_rate(&self, rate: u8, key_code: u8) -> u8 {
+        if rate == 0 {
+            return 0;
+        }
+
+        let offset = self.key_scale_offset(key_code) * 4;
+        rate.saturating_add(offset)
+    }
+
+    fn get_attack_rate(&self, key_code: u8) -> u8 {
+        self.key_scaled_rate(self.attack_rate, key_code)
+    }
+
+    fn get_decay_rate(&self, key_code: u8) -> u8 {
+        self.key_scaled_rate(self.decay_attack_rate, key_code)
+    }
+
+    fn get_sustain_rate(&self, key_code: u8) -> u8 {
+        self.key_scaled_rate(self.decay_sustain_rate, key_code)
+    }
+
+    fn get_release_rate(&self, key_code: u8) -> u8 {
+        self.key_scaled_rate(self.release_rate, key_code)
+    }
+
+    /// Total level as attenuation in envelope steps. Each step of total level is 4 envelope steps,
+    /// or 8 for the 7 bit register of the YM2612.
+    fn total_level_attenuation(&self, mode: EnvelopeMode) -> u32 {
+        match mode {
+            EnvelopeMode::Classic => (u8::MAX - self.total_level) as u32 * 4,
+            EnvelopeMode::Ym2612 => (((u8::MAX - self.total_level) >> 1) as u32) << 3,
+        }
+    }
+
+    /// The effective YM2612 rates of each phase, from 0 to 63.
+    fn register_attack_rate(&self, key_code: u8) -> u8 {
+        effective_rate(self.attack_rate >> 3, self.key_scale_offset(key_code))
+    }
+
+    fn register_decay_rate(&self, key_code: u8) -> u8 {
+        effective_rate(self.decay_attack_rate >> 3, self.key_scale_offset(key_code))
+    }
+
+    fn register_sustain_rate(&self, key_code: u8) -> u8 {
+        effective_rate(
+            self.decay_sustain_rate >> 3,
+            self.key_scale_offset(key_code),
+        )
+    }
+
+    fn register_release_rate(&self, key_code: u8) -> u8 {
+        effective_release_rate(self.release_rate >> 4, self.key_scale_offset(key_code))
+    }
+
+    fn register_sustain_attenuation(&self) -> i32 {
+        sustain_attenuation((u8::MAX - self.sustain_level) >> 4)
+    }
+
+    fn sustain_attenuation<A: Attenuation>(&self) -> A {
+        A::from_steps((u8::MAX - self.sustain_level) as u32)
+    }
+}
+
+/// The envelope's attenuation while it moves. The float path keeps it in
+/// floats, and the fixed point path in integers with ENVELOPE_FRACTION_BITS,
+/// so the classic envelope is only written once for both.
+trait Attenuation: Copy + PartialOrd + std::ops::Add<Output = Self> {
+    /// Full level
+    const NONE: Self;
+    const SILENT: Self;
+    const SSG_EG_END: Self;
+
+    fn from_steps(steps: u32) -> Self;
+    /// Whole 10 bit attenuation steps
+    fn steps(self) -> u32;
+    /// How far a key scaled rate moves each tick, at some ticks a second.
+    fn rate_step(rate: u8, tick_rate: u32) -> Self;
+    fn curve_step(curve: EnvelopeCurve, step: Self, attenuation: Self, remaining: Self) -> Self;
+    fn saturating_sub(self, other: Self) -> Self;
+    fn times(self, multiplier: u32) -> Self;
+    /// The attenuation heard while SSG-EG is flipping the output.
+    fn ssg_eg_inverted(self) -> Self;
+    /// From 0.0 (silent) to 1.0 (full).
+    fn level(self) -> f32;
+
+    fn get(envelope: &EnvelopeInstance) -> Self;
+    fn set(self, envelope: &mut EnvelopeInstance);
+}
+
+impl Attenuation for f32 {
+    const NONE: Self = 0.0;
+    const SILENT: Self = ENVELOPE_MAX;
+    const SSG_EG_END: Self = SSG_EG_MAX as f32;
+
+    fn from_steps(steps: u32) -> Self {
+        steps as f32
+    }
+
+    fn steps(self) -> u32 {
+        self as u32
+    }
+
+    fn rate_step(rate: u8, tick_rate: u32) -> Self {
+        rate_to_float_step(rate, tick_rate)
+    }
+
+    fn curve_step(curve: EnvelopeCurve, step: Self, attenuation: Self, remaining: Self) -> Self {
+        curve.step(step, attenuation, remaining)
+    }
+
+    fn saturating_sub(self, other: Self) -> Self {
+        (self - other).max(0.0)
+    }
+
+    fn times(self, multiplier: u32) -> Self {
+        self * multiplier as f32
+    }
+
+    fn ssg_eg_inverted(self) -> Self {
+        (Self::SSG_EG_END - self).rem_euclid(ENVELOPE_MAX)
+    }
+
+    fn level(self) -> f32 {
+        1.0 - self / ENVELOPE_MAX
+    }
+
+    fn get(envelope: &EnvelopeInstance) -> Self {
+        envelope.current_attenuation
+    }
+
+    fn set(self, envelope: &mut EnvelopeInstance) {
+        envelope.current_attenuation = self;
+    }
+}
+
+impl Attenuation for u32 {
+    const NONE: Self = 0;
+    const SILENT: Self = FIXED_ENVELOPE_MAX;
+    const SSG_EG_END: Self = SSG_EG_MAX << ENVELOPE_FRACTION_BITS;
+
+    fn from_steps(steps: u32) -> Self {
+        steps << ENVELOPE_FRACTION_BITS
+    }
+
+    fn steps(self) -> u32 {
+        self >> ENVELOPE_FRACTION_BITS
+    }
+
+    fn rate_step(rate: u8, tick_rate: u32) -> Self {
+        rate_to_step(rate, tick_rate)
     }
 
-    fn get_decay_rate(&self) -> f32 {
-        (self.decay_attack_rate as f32 / u8::MAX as f32).powi(3)
+    fn curve_step(curve: EnvelopeCurve, step: Self, attenuation: Self, remaining: Self) -> Self {
+        curve.fixed_step(step, attenuation, remaining)
     }
 
-    fn get_sustain_rate(&self) -> f32 {
-        (self.decay_sustain_rate as f32 / u8::MAX as f32).powi(3)
+    fn saturating_sub(self, other: Self) -> Self {
+        u32::saturating_sub(self, other)
     }
 
-    fn get_release_rate(&self) -> f32 {
-        (self.release_rate as f32 / u8::MAX as f32).powi(3)
+    fn times(self, multiplier: u32) -> Self {
+        self * multiplier
+    }
+
+    fn ssg_eg_inverted(self) -> Self {
+        Self::SSG_EG_END.wrapping_sub(self) & (FIXED_ENVELOPE_MAX - 1)
+    }
+
+    fn level(self) -> f32 {
+        1.0 - self as f32 / FIXED_ENVELOPE_MAX as f32
+    }
+
+    fn get(envelope: &EnvelopeInstance) -> Self {
+        envelope.fixed_attenuation
+    }
+
+    fn set(self, envelope: &mut EnvelopeInstance) {
+        envelope.fixed_attenuation = self;
     }
 }
 
-#[derive(Clone, PartialEq, Debug)]
-enum EnvelopePhase {
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EnvelopePhase {
+    Delay,
     Attack,
+    Hold,
     Decay,
     Sustain,
     Release,
     Off,
 }
+
+/// A snapshot of where a playing envelope is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EnvelopePosition {
+    pub(crate) phase: EnvelopePhase,
+    /// Seconds since the phase started
+    pub(crate) elapsed: f32,
+    /// From 0.0 (silent) to 1.0 (full), after total level, in decibels. Level
+    /// scaling, velocity and tremolo are left out, like the envelope plot.
+    pub(crate) level: f32,
+    /// Key code of the note, which key scaling changes the timing for
+    pub(crate) key_code: u8,
+}
+
 #[derive(Clone, Debug)]
 pub struct EnvelopeInstance {
     definition: Arc<RwLock<EnvelopeDefinition>>,
     current_attenuation: f32,
-    attenuation_rate: f32,
+    /// The fixed point path's attenuation, with ENVELOPE_FRACTION_BITS
+    fixed_attenuation: u32,
+    /// Key scaled rate of the current phase, or 0 while it isn't moving
+    attenuation_rate: u8,
     current_phase: EnvelopePhase,
+    /// Ticks left in the delay or hold phase
+    stage_ticks: u32,
+    /// Ticks since the current phase started
+    phase_ticks: u32,
+    /// Whether SSG-EG is currently flipping the output
+    ssg_inverted: bool,
+    /// Key code of the note being played, for key scaling
+    key_code: u8,
+    /// Extra attenuation from the operator's level scaling, in envelope steps
+    level_scaling: u32,
+    /// Extra attenuation from the patch's LFO, in envelope steps
+    tremolo: u32,
+    /// Extra attenuation from the note's velocity, in envelope steps
+    velocity: u32,
+    mode: EnvelopeMode,
+    render_mode: RenderMode,
+    /// How many times a second `tick` is called, which the rates, delay and
+    /// hold are scaled to
+    tick_rate: u32,
 }
 
 impl EnvelopeInstance {
-    pub fn new(definition: Arc<RwLock<EnvelopeDefinition>>) -> Self {
+    pub fn new(definition: Arc<RwLock<EnvelopeDefinition>>, tick_rate: u32) -> Self {
         Self {
             definition,
-            current_attenuation: ATTENUATION_MAX as f32,
-            attenuation_rate: 0.0,
+            current_attenuation: ENVELOPE_MAX,
+            fixed_attenuation: FIXED_ENVELOPE_MAX,
+            attenuation_rate: 0,
             current_phase: EnvelopePhase::Off,
+            stage_ticks: 0,
+            phase_ticks: 0,
+            ssg_inverted: false,
+            key_code: 0,
+            level_scaling: 0,
+            tremolo: 0,
+            velocity: 0,
+            mode: EnvelopeMode::default(),
+            render_mode: RenderMode::default(),
+            tick_rate,
+        }
+    }
+
+    /// Sets which envelope generator to follow, from the patch.
+    pub(crate) fn set_mode(&mut self, mode: EnvelopeMode) {
+        self.mode = mode;
+    }
+
+    /// Sets whether the envelope moves in floats or integers, from the patch.
+    /// Switching carries on from the same level.
+    pub(crate) fn set_render_mode(&mut self, render_mode: RenderMode) {
+        if render_mode == self.render_mode {
+            return;
+        }
+
+        let scale = (1 << ENVELOPE_FRACTION_BITS) as f32;
+        match render_mode {
+            RenderMode::Float => self.current_attenuation = self.fixed_attenuation as f32 / scale,
+            RenderMode::FixedPoint => {
+                self.fixed_attenuation = (self.current_attenuation * scale) as u32
+            }
+        }
+        self.render_mode = render_mode;
+    }
+
+    /// Sets the note being played, for key scaling. Takes effect from the next phase.
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.key_code = key_code(frequency);
+    }
+
+    /// Sets the key code for key scaling directly, such as for plotting a note.
+    pub(crate) fn set_key_code(&mut self, key_code: u8) {
+        self.key_code = key_code;
+    }
+
+    /// Sets the extra attenuation from level scaling, applied on top of total level.
+    pub(crate) fn set_level_scaling(&mut self, attenuation: u32) {
+        self.level_scaling = attenuation;
+    }
+
+    /// Sets the extra attenuation from the note's velocity.
+    pub(crate) fn set_velocity(&mut self, attenuation: u32) {
+        self.velocity = attenuation;
+    }
+
+    /// Sets the extra attenuation from the LFO, which changes every sample.
+    pub(crate) fn set_tremolo(&mut self, attenuation: u32) {
+        self.tremolo = attenuation;
+    }
+
+    /// The attenuation heard, after any SSG-EG inversion.
+    fn output_attenuation<A: Attenuation>(&self) -> A {
+        let attenuation = A::get(self);
+        if self.ssg_inverted {
+            attenuation.ssg_eg_inverted()
+        } else {
+            attenuation
+        }
+    }
+
+    /// The attenuation heard in whole 10 bit steps, in either render mode.
+    fn output_steps(&self) -> u32 {
+        match self.render_mode {
+            RenderMode::Float => self.output_attenuation::<f32>().steps(),
+            RenderMode::FixedPoint => self.output_attenuation::<u32>().steps(),
+        }
+    }
+
+    /// The envelope's output as an amplitude, after total level and any extra attenuation.
+    pub(crate) fn attenuation_with(&self, definition: &EnvelopeDefinition) -> f32 {
+        if self.mode == EnvelopeMode::Ym2612 {
+            return attenuation_table_u10(self.attenuation_fixed_with(definition) as u16);
+        }
+
+        let envelope = self.output_steps() + self.level_scaling + self.tremolo + self.velocity;
+
+        attenuation_table_u10(envelope.min(ATTENUATION_MAX as u32 - 1) as u16)
+            * attenuation_table_u8(u8::MAX - definition.total_level)
+    }
+
+    /// The envelope and total level combined, as a 10 bit attenuation for the fixed point path.
+    pub(crate) fn attenuation_fixed_with(&self, definition: &EnvelopeDefinition) -> u32 {
+        (self.shape_attenuation_fixed(definition)
+            + self.level_scaling
+            + self.tremolo
+            + self.velocity)
+            .min(ATTENUATION_MAX as u32 - 1)
+    }
+
+    /// The envelope and total level alone, without the extras which depend on the note.
+    fn shape_attenuation_fixed(&self, definition: &EnvelopeDefinition) -> u32 {
+        self.output_steps() + definition.total_level_attenuation(self.mode)
+    }
+
+    /// The envelope's own level, ignoring total level. From 0.0 (silent) to 1.0 (full).
+    pub fn level(&self) -> f32 {
+        match self.render_mode {
+            RenderMode::Float => self.output_attenuation::<f32>().level(),
+            RenderMode::FixedPoint => self.output_attenuation::<u32>().level(),
+        }
+    }
+
+    /// Where the envelope is, for showing it on a graph. None once it has finished.
+    pub fn position(&self) -> Option<EnvelopePosition> {
+        if self.current_phase == EnvelopePhase::Off {
+            return None;
         }
+
+        let attenuation = self
+            .shape_attenuation_fixed(&self.definition.read())
+            .min(ATTENUATION_MAX as u32 - 1);
+        Some(EnvelopePosition {
+            phase: self.current_phase,
+            elapsed: self.phase_ticks as f32 / self.tick_rate as f32,
+            level: 1.0 - attenuation as f32 / (ATTENUATION_MAX - 1) as f32,
+            key_code: self.key_code,
+        })
     }
 
-    pub fn attenuation(&self) -> f32 {
-        attenuation_table_u10(self.current_attenuation as u16)
-            * attenuation_table_u8(u8::MAX - self.definition.read().total_level)
+    /// Silences the envelope immediately, so the next attack starts from nothing.
+    pub fn reset(&mut self) {
+        self.current_attenuation = ENVELOPE_MAX;
+        self.fixed_attenuation = FIXED_ENVELOPE_MAX;
+        self.attenuation_rate = 0;
+        self.current_phase = EnvelopePhase::Off;
+        self.stage_ticks = 0;
+        self.phase_ticks = 0;
+        self.ssg_inverted = false;
     }
 
     pub fn key_on(&mut self) {
-        self.current_phase = EnvelopePhase::Attack;
-        self.attenuation_rate = self.definition.read().get_attack_rate();
+        let definition = self.definition.read();
+        self.ssg_inverted = definition.ssg_eg.invert();
+        self.phase_ticks = 0;
+
+        // A delay holds the current level, so a soft retrigger doesn't drop out
+        self.stage_ticks = definition.delay_ticks(self.tick_rate);
+        if self.stage_ticks > 0 && self.mode == EnvelopeMode::Classic {
+            self.current_phase = EnvelopePhase::Delay;
+            self.attenuation_rate = 0;
+        } else {
+            self.current_phase = EnvelopePhase::Attack;
+            self.attenuation_rate = definition.get_attack_rate(self.key_code);
+        }
     }
 
     pub fn key_off(&mut self) {
+        // Release from the level being heard, as the inversion stops here
+        match self.render_mode {
+            RenderMode::Float => self.current_attenuation = self.output_attenuation(),
+            RenderMode::FixedPoint => self.fixed_attenuation = self.output_attenuation(),
+        }
+        self.ssg_inverted = false;
+
+        self.phase_ticks = 0;
         self.current_phase = EnvelopePhase::Release;
-        self.attenuation_rate = self.definition.read().get_release_rate();
+        self.attenuation_rate = self.definition.read().get_release_rate(self.key_code);
     }
 
-    fn next_phase(&mut self) {
+    /// Called when an SSG-EG cycle has decayed to its end, to loop, flip or hold the envelope.
+    fn end_ssg_eg_cycle<A: Attenuation>(
+        &mut self,
+        mode: SsgEgMode,
+        definition: &EnvelopeDefinition,
+    ) {
+        // Don't let the last step overshoot, or inverting it would wrap around to silence
+        if A::get(self) > A::SSG_EG_END {
+            A::SSG_EG_END.set(self);
+        }
+
+        if mode.hold() {
+            if mode.alternate() {
+                self.ssg_inverted = !mode.invert();
+            }
+            // Holding low jumps straight to silence
+            if !self.ssg_inverted {
+                A::SILENT.set(self);
+            }
+        } else {
+            if mode.alternate() {
+                self.ssg_inverted = !self.ssg_inverted;
+            }
+            // Start again from the attack, like a new key on
+            self.current_phase = EnvelopePhase::Attack;
+            self.attenuation_rate = definition.get_attack_rate(self.key_code);
+        }
+    }
+
+    fn next_phase(&mut self, definition: &EnvelopeDefinition) {
         match self.current_phase {
-            EnvelopePhase::Attack => {
-                self.attenuation_rate = self.definition.read().get_decay_rate();
+            EnvelopePhase::Delay => {
+                self.attenuation_rate = definition.get_attack_rate(self.key_code);
+                self.current_phase = EnvelopePhase::Attack;
+            }
+            EnvelopePhase::Attack if definition.hold_ticks(self.tick_rate) > 0 => {
+                self.stage_ticks = definition.hold_ticks(self.tick_rate);
+                self.attenuation_rate = 0;
+                self.current_phase = EnvelopePhase::Hold;
+            }
+            EnvelopePhase::Attack | EnvelopePhase::Hold => {
+                self.attenuation_rate = definition.get_decay_rate(self.key_code);
                 self.current_phase = EnvelopePhase::Decay;
             }
             EnvelopePhase::Decay => {
-                self.attenuation_rate = self.definition.read().get_sustain_rate();
+                self.attenuation_rate = definition.get_sustain_rate(self.key_code);
                 self.current_phase = EnvelopePhase::Sustain;
             }
             EnvelopePhase::Sustain => {
-                self.attenuation_rate = self.definition.read().get_release_rate();
+                self.attenuation_rate = definition.get_release_rate(self.key_code);
                 self.current_phase = EnvelopePhase::Release;
             }
             EnvelopePhase::Release => {
-                self.attenuation_rate = 0.0;
+                self.attenuation_rate = 0;
                 self.current_phase = EnvelopePhase::Off;
             }
             EnvelopePhase::Off => panic!("Called Next phase on Off"),
         };
     }
 
-    pub(crate) fn tick(&mut self) {
+    /// Advances the envelope by one tick. Takes the definition so the
+    /// caller can read it once for a whole block. The YM2612 mode moves on
+    /// the chip's envelope clock, with the count from the patch's `EgCounter`
+    /// given on the ticks it was clocked.
+    pub(crate) fn tick(&mut self, definition: &EnvelopeDefinition, eg_count: Option<u16>) {
+        let phase = self.current_phase;
+        match self.render_mode {
+            RenderMode::Float => self.advance::<f32>(definition, eg_count),
+            RenderMode::FixedPoint => self.advance::<u32>(definition, eg_count),
+        }
+
+        self.phase_ticks = match self.current_phase == phase {
+            true => self.phase_ticks.saturating_add(1),
+            false => 0,
+        };
+    }
+
+    fn advance<A: Attenuation>(&mut self, definition: &EnvelopeDefinition, eg_count: Option<u16>) {
+        if self.mode == EnvelopeMode::Ym2612 {
+            if let Some(counter) = eg_count {
+                self.clock_ym2612::<A>(definition, counter);
+            }
+            return;
+        }
+
         match self.current_phase {
+            EnvelopePhase::Delay | EnvelopePhase::Hold => {
+                self.stage_ticks = self.stage_ticks.saturating_sub(1);
+                if self.stage_ticks == 0 {
+                    self.next_phase(definition);
+                }
+            }
             EnvelopePhase::Attack => {
-                self.current_attenuation -= self.attenuation_rate;
+                let attenuation = A::get(self);
+                let step = A::curve_step(
+                    definition.attack_curve,
+                    A::rate_step(self.attenuation_rate, self.tick_rate),
+                    attenuation,
+                    attenuation,
+                );
+                let attenuation = attenuation.saturating_sub(step);
+                attenuation.set(self);
 
-                if self.current_attenuation <= 0.0 {
-                    self.current_attenuation = 0.0;
-                    self.next_phase();
+                if attenuation <= A::NONE {
+                    self.next_phase(definition);
+                }
+            }
+            EnvelopePhase::Decay | EnvelopePhase::Sustain if definition.ssg_eg.is_enabled() => {
+                // The envelope stops moving once the cycle is over, so held modes stay put
+                let mut attenuation = A::get(self);
+                if attenuation < A::SSG_EG_END {
+                    attenuation = attenuation
+                        + A::rate_step(self.attenuation_rate, self.tick_rate)
+                            .times(SSG_EG_RATE_MULTIPLIER);
+                }
+
+                let sustain_attenuation = definition.sustain_attenuation();
+                if self.current_phase == EnvelopePhase::Decay && attenuation >= sustain_attenuation
+                {
+                    attenuation = sustain_attenuation;
+                    self.next_phase(definition);
+                }
+
+                attenuation.set(self);
+                if attenuation >= A::SSG_EG_END {
+                    self.end_ssg_eg_cycle::<A>(definition.ssg_eg, definition);
                 }
             }
             EnvelopePhase::Decay => {
-                self.current_attenuation += self.attenuation_rate;
-                let sustain_level = self.definition.read().sustain_level;
+                let attenuation = A::get(self);
+                let sustain_attenuation: A = definition.sustain_attenuation();
+                let mut attenuation = attenuation
+                    + A::curve_step(
+                        definition.decay_curve,
+                        A::rate_step(self.attenuation_rate, self.tick_rate),
+                        attenuation,
+                        sustain_attenuation.saturating_sub(attenuation),
+                    );
 
-                if self.current_attenuation >= (u8::MAX - sustain_level) as f32 {
-                    self.current_attenuation = (u8::MAX - sustain_level) as f32;
-                    self.next_phase();
+                if attenuation >= sustain_attenuation {
+                    attenuation = sustain_attenuation;
+                    self.next_phase(definition);
                 }
+                attenuation.set(self);
             }
             EnvelopePhase::Sustain | EnvelopePhase::Release => {
-                self.current_attenuation += self.attenuation_rate;
-                if self.current_attenuation >= ATTENUATION_MAX as f32 {
+                let curve = match self.current_phase {
+                    EnvelopePhase::Sustain => definition.sustain_curve,
+                    _ => definition.release_curve,
+                };
+                let attenuation = A::get(self);
+                let attenuation = attenuation
+                    + A::curve_step(
+                        curve,
+                        A::rate_step(self.attenuation_rate, self.tick_rate),
+                        attenuation,
+                        A::SILENT.saturating_sub(attenuation),
+                    );
+
+                if attenuation >= A::SILENT {
                     self.current_phase = EnvelopePhase::Off;
-                    self.attenuation_rate = 0.0;
-                    self.current_attenuation = ATTENUATION_MAX as f32;
+                    self.attenuation_rate = 0;
+                    A::SILENT.set(self);
+                } else {
+                    attenuation.set(self);
                 }
             }
             EnvelopePhase::Off => (),
         }
     }
+
+    /// One clock of the YM2612's envelope generator, which runs at about 17.7 khz.
+    /// Attenuation stays in whole 10 bit steps here.
+    fn clock_ym2612<A: Attenuation>(&mut self, definition: &EnvelopeDefinition, counter: u16) {
+        let mut volume = A::get(self).steps() as i32;
+        let ssg_eg = definition.ssg_eg;
+
+        match self.current_phase {
+            // The chip has neither, so move on if the mode changed during one
+            EnvelopePhase::Delay => {
+                self.current_phase = EnvelopePhase::Attack;
+                return;
+            }
+            EnvelopePhase::Hold => {
+                self.current_phase = EnvelopePhase::Decay;
+                return;
+            }
+            EnvelopePhase::Attack => {
+                let rate = definition.register_attack_rate(self.key_code);
+                if rate >= EG_INSTANT_ATTACK_RATE {
+                    volume = 0;
+                } else if let Some(increment) = eg_increment(rate, counter) {
+                    // Attack curves towards 0, moving less as it gets louder
+                    volume += (!volume * increment) >> 4;
+                }
+
+                if volume <= 0 {
+                    volume = 0;
+                    self.current_phase = EnvelopePhase::Decay;
+                }
+            }
+            EnvelopePhase::Decay | EnvelopePhase::Sustain => {
+                let rate = match self.current_phase {
+                    EnvelopePhase::Decay => definition.register_decay_rate(self.key_code),
+                    _ => definition.register_sustain_rate(self.key_code),
+                };
+
+                if let Some(increment) = eg_increment(rate, counter) {
+                    if !ssg_eg.is_enabled() {
+                        volume += increment;
+                    } else if volume < SSG_EG_MAX as i32 {
+                        volume += increment * SSG_EG_RATE_MULTIPLIER as i32;
+                    }
+                }
+
+                // Sustain holds at the quietest level rather than ending, as on the chip
+                volume = volume.min(EG_ATTENUATION_MAX);
+                if self.current_phase == EnvelopePhase::Decay
+                    && volume >= definition.register_sustain_attenuation()
+                {
+                    self.current_phase = EnvelopePhase::Sustain;
+                }
+            }
+            EnvelopePhase::Release => {
+                let rate = definition.register_release_rate(self.key_code);
+                if let Some(increment) = eg_increment(rate, counter) {
+                    volume += increment;
+                }
+
+                if volume >= EG_ATTENUATION_MAX {
+                    self.current_phase = EnvelopePhase::Off;
+                    A::SILENT.set(self);
+                    return;
+                }
+            }
+            EnvelopePhase::Off => return,
+        }
+
+        let attenuation = A::from_steps(volume as u32);
+        attenuation.set(self);
+
+        let ssg_cycle_over = attenuation >= A::SSG_EG_END
+            && matches!(
+                self.current_phase,
+                EnvelopePhase::Decay | EnvelopePhase::Sustain
+            );
+        if ssg_eg.is_enabled() && ssg_cycle_over {
+            self.end_ssg_eg_cycle::<A>(ssg_eg, definition);
+        }
+    }
 }
 
 impl Default for EnvelopeInstance {
     fn default() -> Self {
-        Self::new(Arc::new(RwLock::new(EnvelopeDefinition::default())))
+        Self::new(
+            Arc::new(RwLock::new(EnvelopeDefinition::default())),
+            TARGET_SAMPLE_RATE,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patches::{EgCounter, EG_RATE_MAX};
+
+    /// Fast enough for several SSG-EG cycles in a few thousand ticks.
+    fn envelope(ssg_eg: SsgEgMode) -> (EnvelopeInstance, EnvelopeDefinition) {
+        let definition = EnvelopeDefinition {
+            ssg_eg,
+            ..EnvelopeDefinition::new(u8::MAX, u8::MAX, u8::MAX, 0, u8::MAX, u8::MAX)
+        };
+        let mut instance = EnvelopeInstance::new(
+            Arc::new(RwLock::new(definition.clone())),
+            TARGET_SAMPLE_RATE,
+        );
+        instance.key_on();
+        (instance, definition)
+    }
+
+    fn levels(instance: &mut EnvelopeInstance, definition: &EnvelopeDefinition) -> Vec<f32> {
+        (0..4000)
+            .map(|_| {
+                instance.tick(definition, None);
+                instance.level()
+            })
+            .collect()
+    }
+
+    /// How many times the level jumps or turns back up after falling.
+    fn rises(levels: &[f32]) -> usize {
+        levels
+            .windows(2)
+            .filter(|pair| pair[1] > pair[0] && pair[0] < 0.6)
+            .count()
+    }
+
+    #[test]
+    fn timing_is_the_same_at_any_tick_rate() {
+        let definition = EnvelopeDefinition {
+            delay: 10,
+            hold: 20,
+            ..EnvelopeDefinition::new(u8::MAX, 140, 120, 100, 0, u8::MAX)
+        };
+        let delay = 0.01;
+        let attack = delay + definition.attack_time().unwrap();
+        let hold = attack + 0.02;
+        let decay = hold + definition.decay_time().unwrap();
+
+        [RenderMode::Float, RenderMode::FixedPoint]
+            .into_iter()
+            .for_each(|render_mode| {
+                [44_100, 48_000, 96_000].into_iter().for_each(|tick_rate| {
+                    let mut instance =
+                        EnvelopeInstance::new(Arc::new(RwLock::new(definition.clone())), tick_rate);
+                    instance.set_render_mode(render_mode);
+                    instance.key_on();
+
+                    // Seconds from key on to the end of each phase
+                    let mut ends = vec![];
+                    let mut phase = instance.current_phase;
+                    (1..tick_rate).for_each(|tick| {
+                        instance.tick(&definition, None);
+                        if instance.current_phase != phase {
+                            phase = instance.current_phase;
+                            ends.push(tick as f32 / tick_rate as f32);
+                        }
+                    });
+
+                    assert_eq!(ends.len(), 4, "{:?} {}", render_mode, tick_rate);
+                    ends.iter()
+                        .zip([delay, attack, hold, decay])
+                        .for_each(|(actual, expected)| {
+                            assert!(
+                                (actual - expected).abs() < 0.0005,
+                                "{:?} {}: {:?}",
+                                render_mode,
+                                tick_rate,
+                                ends
+                            );
+                        });
+                });
+            });
+
+        assert_eq!(rate_to_seconds(0, ATTENUATION_MAX as u32), None);
+        let fastest = rate_to_seconds(u8::MAX, ATTENUATION_MAX as u32).unwrap();
+        assert!((fastest - 0.021_333).abs() < 1e-4, "{}", fastest);
+    }
+
+    #[test]
+    fn key_codes_follow_the_octave() {
+        assert_eq!(key_code(0.0), 0);
+        assert_eq!(key_code(KEY_CODE_BASE_FREQUENCY * 1.01), 0);
+        // A4 is block 4 with a high F-number, as on the hardware
+        assert_eq!(key_code(440.0), 18);
+        assert_eq!(key_code(880.0), 22);
+        assert_eq!(key_code(20_000.0), KEY_CODE_MAX);
+    }
+
+    #[test]
+    fn key_scale_speeds_up_higher_notes() {
+        let ticks_to_silence = |key_scale, frequency| {
+            let definition = EnvelopeDefinition {
+                key_scale,
+                ..EnvelopeDefinition::new(u8::MAX, u8::MAX, 100, 0, 100, 100)
+            };
+            let mut instance = EnvelopeInstance::new(
+                Arc::new(RwLock::new(definition.clone())),
+                TARGET_SAMPLE_RATE,
+            );
+            instance.set_frequency(frequency);
+            instance.key_on();
+
+            (1..)
+                .find(|_| {
+                    instance.tick(&definition, None);
+                    instance.current_phase == EnvelopePhase::Off
+                })
+                .unwrap()
+        };
+
+        let low = ticks_to_silence(3, 65.0);
+        let high = ticks_to_silence(3, 2000.0);
+        assert!(high * 2 < low, "{} {}", high, low);
+
+        // Less key scaling changes less across the keyboard
+        let gentle_low = ticks_to_silence(0, 65.0);
+        let gentle_high = ticks_to_silence(0, 2000.0);
+        assert!(high < gentle_high && gentle_high < gentle_low);
+        assert!(
+            gentle_high * 2 > gentle_low,
+            "{} {}",
+            gentle_high,
+            gentle_low
+        );
+    }
+
+    #[test]
+    fn registers_convert_back_exactly() {
+        let definition = EnvelopeDefinition::from_registers(0x20, 31, 10, 5, 3, 7, 2);
+
+        assert_eq!(definition.register_attack_rate(0), 62);
+        assert_eq!(definition.register_decay_rate(0), 20);
+        assert_eq!(definition.register_sustain_rate(0), 6);
+        assert_eq!(definition.register_release_rate(0), 30);
+        assert_eq!(definition.register_sustain_attenuation(), 5 << 5);
+        assert_eq!(
+            definition.total_level_attenuation(EnvelopeMode::Ym2612),
+            0x20 << 3
+        );
+
+        // Key scale 2 adds the key code shifted down once
+        assert_eq!(definition.register_attack_rate(20), EG_RATE_MAX);
+        assert_eq!(definition.register_decay_rate(20), 30);
+    }
+
+    #[test]
+    fn parses_register_listings() {
+        assert_eq!(
+            EnvelopeDefinition::parse_registers("32, 31 10 5\t3 7,2"),
+            Ok(EnvelopeDefinition::from_registers(0x20, 31, 10, 5, 3, 7, 2))
+        );
+        assert_eq!(
+            EnvelopeDefinition::parse_registers("32 31 10 5 3 7 2 13"),
+            Ok(EnvelopeDefinition {
+                ssg_eg: SsgEgMode::InvertedOnce,
+                ..EnvelopeDefinition::from_registers(0x20, 31, 10, 5, 3, 7, 2)
+            })
+        );
+        assert_eq!(
+            EnvelopeDefinition::parse_registers("32 31 10 5 3 7"),
+            Err(RegisterError::Count(6))
+        );
+        assert_eq!(
+            EnvelopeDefinition::parse_registers("32 31 10 5 3 7 -2"),
+            Err(RegisterError::InvalidValue("-2".into()))
+        );
+
+        let error = EnvelopeDefinition::parse_registers("32 31 10 16 3 7 2").unwrap_err();
+        assert_eq!(error.to_string(), "SL is 16, but can be at most 15");
+    }
+
+    #[test]
+    fn ym2612_mode_follows_the_chip_timing() {
+        // An instant attack, then a decay of one step per envelope clock to the lowest sustain level
+        let definition = EnvelopeDefinition::from_registers(0, 31, 24, 15, 0, 15, 0);
+        let mut instance = EnvelopeInstance::new(
+            Arc::new(RwLock::new(definition.clone())),
+            TARGET_SAMPLE_RATE,
+        );
+        instance.set_mode(EnvelopeMode::Ym2612);
+        instance.key_on();
+        let mut eg_counter = EgCounter::default();
+
+        let ticks = (1..)
+            .find(|_| {
+                instance.tick(&definition, eg_counter.tick());
+                instance.current_phase == EnvelopePhase::Sustain
+            })
+            .unwrap();
+
+        // 992 steps at 7.67 MHz / 144 / 3, plus the clock spent attacking
+        let expected = 993.0 * TARGET_SAMPLE_RATE as f32 / 17_756.0;
+        assert!(
+            (ticks as f32 - expected).abs() < 4.0,
+            "{} {}",
+            ticks,
+            expected
+        );
+        assert_eq!(instance.attenuation_fixed_with(&definition), 992);
+
+        // With no sustain rate, it stays there until released
+        (0..10_000).for_each(|_| instance.tick(&definition, eg_counter.tick()));
+        assert_eq!(instance.current_phase, EnvelopePhase::Sustain);
+
+        instance.key_off();
+        (0..1_000).for_each(|_| instance.tick(&definition, eg_counter.tick()));
+        assert_eq!(instance.current_phase, EnvelopePhase::Off);
+    }
+
+    #[test]
+    fn positions_leave_out_the_per_note_attenuation() {
+        let definition = EnvelopeDefinition::new(u8::MAX, u8::MAX, 200, 0, 0, 200);
+        let mut instance = EnvelopeInstance::new(
+            Arc::new(RwLock::new(definition.clone())),
+            TARGET_SAMPLE_RATE,
+        );
+        instance.key_on();
+        while instance.current_phase == EnvelopePhase::Attack {
+            instance.tick(&definition, None);
+        }
+        let position = instance.position().unwrap();
+        let attenuation = instance.attenuation_fixed_with(&definition);
+
+        instance.set_level_scaling(64);
+        instance.set_velocity(32);
+        instance.set_tremolo(16);
+        assert_eq!(
+            instance.attenuation_fixed_with(&definition),
+            attenuation + 112
+        );
+        assert_eq!(instance.position(), Some(position));
+    }
+
+    #[test]
+    fn delay_and_hold_extend_the_envelope() {
+        let definition = EnvelopeDefinition {
+            delay: 10,
+            hold: 20,
+            ..EnvelopeDefinition::new(u8::MAX, u8::MAX, 200, 0, 0, 200)
+        };
+        let mut instance = EnvelopeInstance::new(
+            Arc::new(RwLock::new(definition.clone())),
+            TARGET_SAMPLE_RATE,
+        );
+        instance.key_on();
+
+        let phase_ticks = |instance: &mut EnvelopeInstance, phase| {
+            let mut ticks = 0;
+            while instance.current_phase == phase {
+                instance.tick(&definition, None);
+                ticks += 1;
+            }
+            ticks
+        };
+
+        assert_eq!(
+            phase_ticks(&mut instance, EnvelopePhase::Delay),
+            TARGET_SAMPLE_RATE / 100
+        );
+        phase_ticks(&mut instance, EnvelopePhase::Attack);
+        assert_eq!(instance.current_phase, EnvelopePhase::Hold);
+        assert_eq!(instance.level(), 1.0);
+        assert_eq!(
+            phase_ticks(&mut instance, EnvelopePhase::Hold),
+            TARGET_SAMPLE_RATE / 50
+        );
+        assert_eq!(instance.current_phase, EnvelopePhase::Decay);
+        instance.tick(&definition, None);
+        assert!(instance.level() < 1.0);
+    }
+
+    #[test]
+    fn curves_shape_the_attack() {
+        crate::patches::init_attenuation_table();
+
+        let attack = |attack_curve| {
+            let definition = EnvelopeDefinition {
+                attack_curve,
+                ..EnvelopeDefinition::new(u8::MAX, 100, 0, u8::MAX, 0, 100)
+            };
+            let mut instance = EnvelopeInstance::new(
+                Arc::new(RwLock::new(definition.clone())),
+                TARGET_SAMPLE_RATE,
+            );
+            instance.key_on();
+            let amplitudes: Vec<f32> = (0..100_000)
+                .map(|_| {
+                    instance.tick(&definition, None);
+                    instance.attenuation_with(&definition)
+                })
+                .collect();
+            amplitudes
+        };
+
+        let linear_db = attack(EnvelopeCurve::LinearDb);
+        let length = linear_db
+            .iter()
+            .position(|amplitude| *amplitude == 1.0)
+            .unwrap();
+        let halfway = |amplitudes: &[f32]| amplitudes[length / 2];
+
+        // Linear amplitude is about half way up, while the exponential attack has
+        // already jumped most of the way in dB
+        let exponential = attack(EnvelopeCurve::Exponential);
+        let linear_amplitude = attack(EnvelopeCurve::LinearAmplitude);
+        assert!(halfway(&linear_db) < 0.01);
+        assert!((halfway(&linear_amplitude) - 0.5).abs() < 0.05);
+        assert!((0.1..0.4).contains(&halfway(&exponential)));
+
+        // Every curve gets there in the end
+        [exponential, linear_amplitude]
+            .iter()
+            .for_each(|amplitudes| {
+                assert!(amplitudes.windows(2).all(|pair| pair[1] >= pair[0]));
+                assert_eq!(amplitudes.last(), Some(&1.0));
+            });
+    }
+
+    #[test]
+    fn fixed_point_envelopes_follow_the_float_ones() {
+        crate::patches::init_attenuation_table();
+
+        EnvelopeCurve::ALL.into_iter().for_each(|curve| {
+            let definition = EnvelopeDefinition {
+                attack_curve: curve,
+                decay_curve: curve,
+                release_curve: curve,
+                ..EnvelopeDefinition::new(u8::MAX, 100, 100, 120, 0, 100)
+            };
+            let amplitudes = |render_mode| {
+                let mut instance = EnvelopeInstance::new(
+                    Arc::new(RwLock::new(definition.clone())),
+                    TARGET_SAMPLE_RATE,
+                );
+                instance.set_render_mode(render_mode);
+                instance.key_on();
+                (0..60_000)
+                    .map(|tick| {
+                        if tick == 30_000 {
+                            instance.key_off();
+                        }
+                        instance.tick(&definition, None);
+                        instance.attenuation_with(&definition)
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            // Compared as amplitudes, as the end of a linear amplitude release
+            // is a long way in dB
+            let float = amplitudes(RenderMode::Float);
+            let fixed = amplitudes(RenderMode::FixedPoint);
+            float
+                .iter()
+                .zip(&fixed)
+                .enumerate()
+                .for_each(|(tick, (float, fixed))| {
+                    assert!(
+                        (float - fixed).abs() < 0.02,
+                        "{:?} {}: {} {}",
+                        curve,
+                        tick,
+                        float,
+                        fixed
+                    );
+                });
+            // And both have finished
+            assert_eq!(fixed.last(), float.last());
+        });
+    }
+
+    #[test]
+    fn ssg_eg_registers_round_trip() {
+        SsgEgMode::ALL.into_iter().for_each(|mode| {
+            assert_eq!(SsgEgMode::from_register(mode.register()), mode);
+        });
+        assert_eq!(SsgEgMode::from_register(0b0111), SsgEgMode::Off);
+    }
+
+    #[test]
+    fn ssg_eg_repeats_until_key_off() {
+        let (mut instance, definition) = envelope(SsgEgMode::Repeat);
+        let levels = levels(&mut instance, &definition);
+
+        // After the first attack, the level never decays past halfway
+        let attacked = levels.iter().position(|level| *level == 1.0).unwrap();
+        assert!(rises(&levels) > 2);
+        assert!(levels[attacked..].iter().all(|level| *level >= 0.5));
+
+        instance.key_off();
+        (0..2000).for_each(|_| instance.tick(&definition, None));
+        assert_eq!(instance.level(), 0.0);
+    }
+
+    #[test]
+    fn ssg_eg_holds() {
+        let (mut instance, definition) = envelope(SsgEgMode::Once);
+        assert_eq!(*levels(&mut instance, &definition).last().unwrap(), 0.0);
+
+        let (mut instance, definition) = envelope(SsgEgMode::OnceHoldHigh);
+        assert_eq!(*levels(&mut instance, &definition).last().unwrap(), 1.0);
+
+        let (mut instance, definition) = envelope(SsgEgMode::InvertedOnce);
+        assert_eq!(*levels(&mut instance, &definition).last().unwrap(), 1.0);
+
+        let (mut instance, definition) = envelope(SsgEgMode::InvertedOnceHoldLow);
+        assert_eq!(*levels(&mut instance, &definition).last().unwrap(), 0.0);
+
+        // Releasing a held high envelope fades from full, rather than jumping
+        let (mut instance, definition) = envelope(SsgEgMode::OnceHoldHigh);
+        levels(&mut instance, &definition);
+        instance.key_off();
+        instance.tick(&definition, None);
+        assert!(instance.level() > 0.99);
+    }
+
+    #[test]
+    fn ssg_eg_inverted_rises_while_decaying() {
+        let (mut instance, definition) = envelope(SsgEgMode::InvertedRepeat);
+        let levels = levels(&mut instance, &definition);
+
+        // Once the attack is over, the inverted decay rises towards full level
+        let decay_start = levels.iter().position(|level| *level == 0.5).unwrap();
+        assert!(levels[decay_start + 1] > levels[decay_start]);
+        assert!(levels.contains(&1.0));
     }
 }
@@ -0,0 +1,236 @@
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use super::{
+    EgCounter, EnvelopeDefinition, EnvelopeInstance, EnvelopeMode, EnvelopePhase, EnvelopePosition,
+};
+use crate::TARGET_SAMPLE_RATE;
+
+/// Ticks between each point of the plot, 1 ms
+const TICKS_PER_POINT: u32 = TARGET_SAMPLE_RATE / 1000;
+
+/// How long the key is held once the envelope reaches its sustain phase
+const SUSTAIN_TICKS: u32 = TARGET_SAMPLE_RATE / 2;
+
+/// Slow envelopes are released after being held this long, and slow releases
+/// are cut short, so phases that never end still fit on the plot.
+const PHASE_MAX_TICKS: u32 = TARGET_SAMPLE_RATE * 4;
+
+/// Where one phase appears on the plot, in seconds.
+#[derive(Clone, Debug)]
+struct PlotSegment {
+    phase: EnvelopePhase,
+    start: f32,
+    length: f32,
+}
+
+/// The shape of an envelope over a single note, drawn by playing it through.
+/// Levels are from 0.0 (silent) to 1.0 (full) in decibels, after total level.
+#[derive(Clone, Debug, Default)]
+pub struct EnvelopePlot {
+    /// Seconds and level of each point
+    pub(crate) points: Vec<[f32; 2]>,
+    segments: Vec<PlotSegment>,
+    /// What was drawn, to tell when it needs drawing again
+    source: Option<(EnvelopeDefinition, EnvelopeMode)>,
+    /// The note drawn, as a key code for key scaling
+    key_code: u8,
+}
+
+impl EnvelopePlot {
+    pub fn new(definition: &EnvelopeDefinition, mode: EnvelopeMode, key_code: u8) -> Self {
+        let mut instance = EnvelopeInstance::new(
+            Arc::new(RwLock::new(definition.clone())),
+            TARGET_SAMPLE_RATE,
+        );
+        instance.set_mode(mode);
+        instance.set_key_code(key_code);
+        instance.key_on();
+        let mut eg_counter = EgCounter::default();
+
+        let mut plot = Self {
+            source: Some((definition.clone(), mode)),
+            key_code,
+            ..Self::default()
+        };
+
+        let mut released = false;
+        let mut tick = 0;
+        while let Some(position) = instance.position() {
+            plot.add(position, tick);
+
+            let phase_ticks = (position.elapsed * TARGET_SAMPLE_RATE as f32).round() as u32;
+            if released && phase_ticks >= PHASE_MAX_TICKS {
+                break;
+            }
+
+            // SSG-EG can loop forever, so the whole note is limited as well
+            let sustained =
+                position.phase == EnvelopePhase::Sustain && phase_ticks >= SUSTAIN_TICKS;
+            if !released && (sustained || tick >= PHASE_MAX_TICKS) {
+                instance.key_off();
+                released = true;
+            }
+
+            instance.tick(definition, eg_counter.tick());
+            tick += 1;
+        }
+
+        // Finish on silence, or where the release was cut short
+        plot.add_point(
+            tick,
+            instance.position().map_or(0.0, |position| position.level),
+        );
+        plot
+    }
+
+    /// Whether this plot was drawn from these settings. Key codes only matter
+    /// when key scaling treats them differently.
+    pub fn matches(
+        &self,
+        definition: &EnvelopeDefinition,
+        mode: EnvelopeMode,
+        key_code: u8,
+    ) -> bool {
+        self.source.as_ref().is_some_and(|(source, source_mode)| {
+            source == definition && *source_mode == mode && self.same_key_scaling(key_code)
+        })
+    }
+
+    /// The key code the plot was drawn for.
+    pub fn key_code(&self) -> u8 {
+        self.key_code
+    }
+
+    fn same_key_scaling(&self, key_code: u8) -> bool {
+        self.source.as_ref().is_some_and(|(source, _)| {
+            source.key_scale_offset(key_code) == source.key_scale_offset(self.key_code)
+        })
+    }
+
+    // is_multiple_of would need Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    fn add(&mut self, position: EnvelopePosition, tick: u32) {
+        let seconds = tick as f32 / TARGET_SAMPLE_RATE as f32;
+
+        match self.segments.last_mut() {
+            Some(segment) if segment.phase == position.phase => {
+                segment.length = seconds - segment.start
+            }
+            _ => {
+                self.segments.push(PlotSegment {
+                    phase: position.phase,
+                    start: seconds,
+                    length: 0.0,
+                });
+                // Always mark where a phase starts, so sudden jumps are drawn
+                self.add_point(tick, position.level);
+                return;
+            }
+        }
+
+        if tick % TICKS_PER_POINT == 0 {
+            self.add_point(tick, position.level);
+        }
+    }
+
+    fn add_point(&mut self, tick: u32, level: f32) {
+        self.points
+            .push([tick as f32 / TARGET_SAMPLE_RATE as f32, level]);
+    }
+
+    /// Where a playing envelope falls on the plot. The marker stops at the end of
+    /// a phase that is held longer than the plot shows. Notes which key scaling
+    /// times differently to the plotted one have no marker.
+    pub fn marker(&self, position: &EnvelopePosition) -> Option<[f32; 2]> {
+        if !self.same_key_scaling(position.key_code) {
+            return None;
+        }
+
+        self.segments
+            .iter()
+            .find(|segment| segment.phase == position.phase)
+            .map(|segment| {
+                [
+                    segment.start + position.elapsed.min(segment.length),
+                    position.level,
+                ]
+            })
+    }
+
+    /// How long the plot lasts, in seconds.
+    pub fn length(&self) -> f32 {
+        self.points.last().map_or(0.0, |point| point[0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plots_each_phase_in_turn() {
+        let definition = EnvelopeDefinition {
+            hold: 10,
+            ..EnvelopeDefinition::new(u8::MAX, 200, 150, 128, 0, 150)
+        };
+        let plot = EnvelopePlot::new(&definition, EnvelopeMode::Classic, 0);
+
+        let phases: Vec<_> = plot.segments.iter().map(|segment| segment.phase).collect();
+        assert_eq!(
+            phases,
+            [
+                EnvelopePhase::Attack,
+                EnvelopePhase::Hold,
+                EnvelopePhase::Decay,
+                EnvelopePhase::Sustain,
+                EnvelopePhase::Release,
+            ]
+        );
+
+        // The sustain phase doesn't decay, so the key is held at the sustain level
+        let sustain = &plot.segments[3];
+        assert!((sustain.length - 0.5).abs() < 0.01);
+        let level = plot
+            .marker(&EnvelopePosition {
+                phase: EnvelopePhase::Sustain,
+                elapsed: 10.0,
+                level: 0.5,
+                key_code: 0,
+            })
+            .unwrap();
+        assert_eq!(level[0], sustain.start + sustain.length);
+
+        assert_eq!(plot.points.first(), Some(&[0.0, 0.0]));
+        assert_eq!(plot.points.last().unwrap()[1], 0.0);
+        assert!(plot.points.windows(2).all(|pair| pair[1][0] >= pair[0][0]));
+        assert!(plot.matches(&definition, EnvelopeMode::Classic, 0));
+        assert!(!plot.matches(&definition, EnvelopeMode::Ym2612, 0));
+        // Nearby notes share a key scaling step, and so the same curve
+        assert!(plot.matches(&definition, EnvelopeMode::Classic, 7));
+        assert!(!plot.matches(&definition, EnvelopeMode::Classic, 31));
+    }
+
+    #[test]
+    fn plots_the_key_scaled_note() {
+        let definition = EnvelopeDefinition {
+            key_scale: 3,
+            ..EnvelopeDefinition::new(u8::MAX, 120, 120, 128, 0, 120)
+        };
+        let low = EnvelopePlot::new(&definition, EnvelopeMode::Classic, 0);
+        let high = EnvelopePlot::new(&definition, EnvelopeMode::Classic, 31);
+        assert!(high.length() < low.length());
+        assert!(!low.matches(&definition, EnvelopeMode::Classic, 31));
+
+        // Only notes with the plotted key's timing get a marker
+        let position = |key_code| EnvelopePosition {
+            phase: EnvelopePhase::Attack,
+            elapsed: 0.0,
+            level: 0.0,
+            key_code,
+        };
+        assert!(high.marker(&position(31)).is_some());
+        assert!(high.marker(&position(0)).is_none());
+    }
+}
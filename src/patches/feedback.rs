@@ -1,6 +1,11 @@
 use std::f32::consts::PI;
 
-#[derive(Clone, Copy, Debug, Default)]
+use serde::{Deserialize, Serialize};
+
+/// The highest feedback level, which modulates by 128π.
+pub const FEEDBACK_MAX: usize = 15;
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
 pub struct FeedbackLevel(pub usize);
 
 impl FeedbackLevel {
@@ -25,4 +30,15 @@ impl FeedbackLevel {
             _ => panic!("invalid feedback level"),
         }
     }
+
+    /// Scales the sum of the last two fixed point outputs into a phase offset,
+    /// matching the depth of `as_multiplier`.
+    pub(crate) fn apply_fixed(self, sum: i32) -> i32 {
+        match self.0 {
+            0 => 0,
+            level @ 1..=13 => sum >> (13 - level),
+            level @ 14..=15 => sum << (level - 13),
+            _ => panic!("invalid feedback level"),
+        }
+    }
 }
@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Waveform;
+
+/// How a patch generates its samples.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RenderMode {
+    /// Floating point math, with the full range of waveforms.
+    #[default]
+    Float,
+    /// Integer math with log-sin and exp tables, as on the YM2612. The phases,
+    /// envelopes and mixing give the same output on every platform, and need no
+    /// floating point trig. The LFO, level scaling, waveform morphing and
+    /// waveforms without a log-sin form are still worked out in float.
+    FixedPoint,
+}
+
+/// Bits of the phase accumulator used to index a full sine cycle.
+pub const FIXED_PHASE_BITS: u32 = 10;
+
+/// Divides the fixed point output down to the range of the float path.
+pub const FIXED_OUTPUT_SCALE: f32 = 8192.0;
+
+/// Shifts a 32 bit phase accumulator down to a sine table index.
+pub(crate) const fn fixed_phase_index(phase: u32) -> u32 {
+    phase >> (32 - FIXED_PHASE_BITS)
+}
+
+/// Converts a modulation in table steps into an offset on the phase accumulator.
+pub(crate) const fn fixed_phase_offset(modulation: i32) -> u32 {
+    (modulation as u32) << (32 - FIXED_PHASE_BITS)
+}
+
+/// A waveform value in the log domain, as the chip stores it before the exp table.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct LogSample {
+    /// Attenuation in 1/256ths of an octave (about 0.0235 dB).
+    pub(crate) attenuation: u32,
+    pub(crate) negative: bool,
+}
+
+impl LogSample {
+    /// Applies a 10 bit envelope attenuation, and converts to a linear value.
+    pub(crate) fn to_linear(self, envelope_attenuation: u32) -> i32 {
+        let volume = attenuation_to_volume(self.attenuation + (envelope_attenuation << 2));
+
+        if self.negative {
+            -volume
+        } else {
+            volume
+        }
+    }
+}
+
+/// Looks up a quarter sine wave, returning its attenuation.
+fn sine_attenuation(index: u32) -> u32 {
+    LOG_SIN_TABLE[(index & 0xFF) as usize] as u32
+}
+
+/// Converts an attenuation in 1/256ths of an octave into a 13 bit linear volume.
+pub(crate) fn attenuation_to_volume(attenuation: u32) -> i32 {
+    let shift = attenuation >> 8;
+    if shift >= 13 {
+        return 0;
+    }
+
+    // The table holds the mantissa, with an implied leading bit
+    let mantissa = EXP_TABLE[(attenuation & 0xFF) as usize] as i32 | 0x400;
+    (mantissa << 2) >> shift
+}
+
+/// Generates a full sine wave from the quarter table, by mirroring the index and the sign.
+fn sine(index: u32) -> LogSample {
+    let mirrored = if index & 0x100 != 0 { !index } else { index };
+
+    LogSample {
+        attenuation: sine_attenuation(mirrored),
+        negative: index & 0x200 != 0,
+    }
+}
+
+impl Waveform {
+    /// Generates the waveforms which can be built from the log-sin table, as the OPL3
+    /// does. Returns None for waveforms which need the float path, and Some(None) for
+    /// silent parts of the cycle.
+    pub(crate) fn fixed_func(&self, index: u32) -> Option<Option<LogSample>> {
+        let index = index & 0x3FF;
+        let first_half = index & 0x200 == 0;
+
+        let output = match self {
+            Self::Sine => Some(sine(index)),
+            Self::HalfSine => first_half.then(|| sine(index)),
+            Self::AbsoluteSine => Some(LogSample {
+                negative: false,
+                ..sine(index)
+            }),
+            Self::QuarterSine => (index & 0x100 == 0).then(|| sine(index & 0xFF)),
+            Self::AlternatingSine => first_half.then(|| sine(index << 1)),
+            Self::CamelSine => first_half.then(|| LogSample {
+                negative: false,
+                ..sine(index << 1)
+            }),
+            Self::Square => Some(LogSample {
+                attenuation: 0,
+                negative: !first_half,
+            }),
+            _ => return None,
+        };
+
+        Some(output)
+    }
+}
+
+/// -log2(sin(x)) over a quarter cycle, in 1/256ths of an octave.
+/// The same values as the ROM in the OPN and OPL chips.
+#[rustfmt::skip]
+const LOG_SIN_TABLE: [u16; 256] = [
+    0x859, 0x6c3, 0x607, 0x58b, 0x52e, 0x4e4, 0x4a6, 0x471, 0x443, 0x41a, 0x3f5, 0x3d3, 0x3b5, 0x398, 0x37e, 0x365,
+    0x34e, 0x339, 0x324, 0x311, 0x2ff, 0x2ed, 0x2dc, 0x2cd, 0x2bd, 0x2af, 0x2a0, 0x293, 0x286, 0x279, 0x26d, 0x261,
+    0x256, 0x24b, 0x240, 0x236, 0x22c, 0x222, 0x218, 0x20f, 0x206, 0x1fd, 0x1f5, 0x1ec, 0x1e4, 0x1dc, 0x1d4, 0x1cd,
+    0x1c5, 0x1be, 0x1b7, 0x1b0, 0x1a9, 0x1a2, 0x19b, 0x195, 0x18f, 0x188, 0x182, 0x17c, 0x177, 0x171, 0x16b, 0x166,
+    0x160, 0x15b, 0x155, 0x150, 0x14b, 0x146, 0x141, 0x13c, 0x137, 0x133, 0x12e, 0x129, 0x125, 0x121, 0x11c, 0x118,
+    0x114, 0x10f, 0x10b, 0x107, 0x103, 0x0ff, 0x0fb, 0x0f8, 0x0f4, 0x0f0, 0x0ec, 0x0e9, 0x0e5, 0x0e2, 0x0de, 0x0db,
+    0x0d7, 0x0d4, 0x0d1, 0x0cd, 0x0ca, 0x0c7, 0x0c4, 0x0c1, 0x0be, 0x0bb, 0x0b8, 0x0b5, 0x0b2, 0x0af, 0x0ac, 0x0a9,
+    0x0a7, 0x0a4, 0x0a1, 0x09f, 0x09c, 0x099, 0x097, 0x094, 0x092, 0x08f, 0x08d, 0x08a, 0x088, 0x086, 0x083, 0x081,
+    0x07f, 0x07d, 0x07a, 0x078, 0x076, 0x074, 0x072, 0x070, 0x06e, 0x06c, 0x06a, 0x068, 0x066, 0x064, 0x062, 0x060,
+    0x05e, 0x05c, 0x05b, 0x059, 0x057, 0x055, 0x053, 0x052, 0x050, 0x04e, 0x04d, 0x04b, 0x04a, 0x048, 0x046, 0x045,
+    0x043, 0x042, 0x040, 0x03f, 0x03e, 0x03c, 0x03b, 0x039, 0x038, 0x037, 0x035, 0x034, 0x033, 0x031, 0x030, 0x02f,
+    0x02e, 0x02d, 0x02b, 0x02a, 0x029, 0x028, 0x027, 0x026, 0x025, 0x024, 0x023, 0x022, 0x021, 0x020, 0x01f, 0x01e,
+    0x01d, 0x01c, 0x01b, 0x01a, 0x019, 0x018, 0x017, 0x017, 0x016, 0x015, 0x014, 0x014, 0x013, 0x012, 0x011, 0x011,
+    0x010, 0x00f, 0x00f, 0x00e, 0x00d, 0x00d, 0x00c, 0x00c, 0x00b, 0x00a, 0x00a, 0x009, 0x009, 0x008, 0x008, 0x007,
+    0x007, 0x007, 0x006, 0x006, 0x005, 0x005, 0x005, 0x004, 0x004, 0x004, 0x003, 0x003, 0x003, 0x002, 0x002, 0x002,
+    0x002, 0x001, 0x001, 0x001, 0x001, 0x001, 0x001, 0x001, 0x000, 0x000, 0x000, 0x000, 0x000, 0x000, 0x000, 0x000,
+];
+
+/// 2^(-x) over one octave, in 1/256ths of an octave, missing the leading bit.
+/// The same values as the ROM in the OPN and OPL chips.
+#[rustfmt::skip]
+const EXP_TABLE: [u16; 256] = [
+    0x3fa, 0x3f5, 0x3ef, 0x3ea, 0x3e4, 0x3df, 0x3da, 0x3d4, 0x3cf, 0x3c9, 0x3c4, 0x3bf, 0x3b9, 0x3b4, 0x3ae, 0x3a9,
+    0x3a4, 0x39f, 0x399, 0x394, 0x38f, 0x38a, 0x384, 0x37f, 0x37a, 0x375, 0x370, 0x36a, 0x365, 0x360, 0x35b, 0x356,
+    0x351, 0x34c, 0x347, 0x342, 0x33d, 0x338, 0x333, 0x32e, 0x329, 0x324, 0x31f, 0x31a, 0x315, 0x310, 0x30b, 0x306,
+    0x302, 0x2fd, 0x2f8, 0x2f3, 0x2ee, 0x2e9, 0x2e5, 0x2e0, 0x2db, 0x2d6, 0x2d2, 0x2cd, 0x2c8, 0x2c4, 0x2bf, 0x2ba,
+    0x2b5, 0x2b1, 0x2ac, 0x2a8, 0x2a3, 0x29e, 0x29a, 0x295, 0x291, 0x28c, 0x288, 0x283, 0x27f, 0x27a, 0x276, 0x271,
+    0x26d, 0x268, 0x264, 0x25f, 0x25b, 0x257, 0x252, 0x24e, 0x249, 0x245, 0x241, 0x23c, 0x238, 0x234, 0x230, 0x22b,
+    0x227, 0x223, 0x21e, 0x21a, 0x216, 0x212, 0x20e, 0x209, 0x205, 0x201, 0x1fd, 0x1f9, 0x1f5, 0x1f0, 0x1ec, 0x1e8,
+    0x1e4, 0x1e0, 0x1dc, 0x1d8, 0x1d4, 0x1d0, 0x1cc, 0x1c8, 0x1c4, 0x1c0, 0x1bc, 0x1b8, 0x1b4, 0x1b0, 0x1ac, 0x1a8,
+    0x1a4, 0x1a0, 0x19c, 0x199, 0x195, 0x191, 0x18d, 0x189, 0x185, 0x181, 0x17e, 0x17a, 0x176, 0x172, 0x16f, 0x16b,
+    0x167, 0x163, 0x160, 0x15c, 0x158, 0x154, 0x151, 0x14d, 0x149, 0x146, 0x142, 0x13e, 0x13b, 0x137, 0x134, 0x130,
+    0x12c, 0x129, 0x125, 0x122, 0x11e, 0x11b, 0x117, 0x114, 0x110, 0x10c, 0x109, 0x106, 0x102, 0x0ff, 0x0fb, 0x0f8,
+    0x0f4, 0x0f1, 0x0ed, 0x0ea, 0x0e7, 0x0e3, 0x0e0, 0x0dc, 0x0d9, 0x0d6, 0x0d2, 0x0cf, 0x0cc, 0x0c8, 0x0c5, 0x0c2,
+    0x0be, 0x0bb, 0x0b8, 0x0b5, 0x0b1, 0x0ae, 0x0ab, 0x0a8, 0x0a4, 0x0a1, 0x09e, 0x09b, 0x098, 0x094, 0x091, 0x08e,
+    0x08b, 0x088, 0x085, 0x082, 0x07e, 0x07b, 0x078, 0x075, 0x072, 0x06f, 0x06c, 0x069, 0x066, 0x063, 0x060, 0x05d,
+    0x05a, 0x057, 0x054, 0x051, 0x04e, 0x04b, 0x048, 0x045, 0x042, 0x03f, 0x03c, 0x039, 0x036, 0x033, 0x030, 0x02d,
+    0x02a, 0x028, 0x025, 0x022, 0x01f, 0x01c, 0x019, 0x016, 0x014, 0x011, 0x00e, 0x00b, 0x008, 0x006, 0x003, 0x000,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine_is_symmetric() {
+        (0..0x200).for_each(|index| {
+            let positive = sine(index).to_linear(0);
+            let negative = sine(index + 0x200).to_linear(0);
+            assert_eq!(positive, -negative);
+            assert_eq!(positive, sine(0x1FF - index).to_linear(0));
+        });
+
+        // The loudest value the exp table gives
+        assert_eq!(sine(0x100).to_linear(0), 0x1FE8);
+    }
+
+    #[test]
+    fn sine_matches_the_float_sine() {
+        (0..0x400).for_each(|index| {
+            let expected = ((index as f32 + 0.5) / 1024.0 * std::f32::consts::TAU).sin();
+            let actual = sine(index).to_linear(0) as f32 / FIXED_OUTPUT_SCALE;
+            assert!(
+                (expected - actual).abs() < 5e-3,
+                "{}: {} {}",
+                index,
+                expected,
+                actual
+            );
+        });
+    }
+
+    #[test]
+    fn envelope_attenuation_halves_every_octave() {
+        // 6 dB is 64 steps of envelope attenuation
+        let full = sine(0x100).to_linear(0);
+        assert_eq!(sine(0x100).to_linear(64), full >> 1);
+        assert_eq!(sine(0x100).to_linear(1023), 0);
+    }
+}
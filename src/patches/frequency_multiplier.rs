@@ -1,4 +1,7 @@
-#[derive(Clone, Copy, Debug)]
+use serde::{Deserialize, Serialize};
+
+/// The original fixed ratios, now kept as presets for `FrequencyRatio`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct FrequencyMultiplier(pub u8);
 
 impl Default for FrequencyMultiplier {
@@ -40,29 +43,30 @@ impl FrequencyMultiplier {
         }
     }
 
-    pub fn multiply(self, phase: f32) -> f32 {
+    /// The preset's ratio as numerator and denominator.
+    pub fn fraction(self) -> (u16, u16) {
         match self.0 {
-            0 => phase * (1. / 4.),   // 4:1 0.25
-            1 => phase * (1. / 3.),   // 3:1 ~0.33333
-            2 => phase * (3. / 8.),   // 8:3 ~0.375
-            3 => phase * (1. / 2.),   // 2:1 0.5
-            4 => phase * (2. / 3.),   // 3:2 ~0.666
-            5 => phase * (3. / 4.),   // 4:3 ~0.75
-            6 => phase,               // 1:1
-            7 => phase * (5. / 4.),   // 4:5 1.25
-            8 => phase * (4. / 3.),   // 3:4  ~1.33
-            9 => phase * (3. / 2.),   // 2:3  1.5
-            10 => phase * (5. / 3.),  // 3:5  ~1.66
-            11 => phase * 2.0,        // 1:2 2.0
-            12 => phase * (5. / 2.),  // 2:5  2.5
-            13 => phase * (8. / 3.),  // 3:8  ~2.666
-            14 => phase * 3.0,        // 1:3 3.0
-            15 => phase * (10. / 3.), // 3:10 ~3.333
-            16 => phase * 4.0,        // 1:4 4.0
-            17 => phase * (5. / 1.),  // 1:5  5.0
-            18 => phase * (16. / 3.), // 3:16 ~5.333
-            19 => phase * (6. / 1.),  // 1:6  6.0
-            20 => phase * (20. / 3.), // 3:20 ~6.666
+            0 => (1, 4),   // 4:1 0.25
+            1 => (1, 3),   // 3:1 ~0.33333
+            2 => (3, 8),   // 8:3 ~0.375
+            3 => (1, 2),   // 2:1 0.5
+            4 => (2, 3),   // 3:2 ~0.666
+            5 => (3, 4),   // 4:3 ~0.75
+            6 => (1, 1),   // 1:1
+            7 => (5, 4),   // 4:5 1.25
+            8 => (4, 3),   // 3:4  ~1.33
+            9 => (3, 2),   // 2:3  1.5
+            10 => (5, 3),  // 3:5  ~1.66
+            11 => (2, 1),  // 1:2 2.0
+            12 => (5, 2),  // 2:5  2.5
+            13 => (8, 3),  // 3:8  ~2.666
+            14 => (3, 1),  // 1:3 3.0
+            15 => (10, 3), // 3:10 ~3.333
+            16 => (4, 1),  // 1:4 4.0
+            17 => (5, 1),  // 1:5  5.0
+            18 => (16, 3), // 3:16 ~5.333
+            19 => (6, 1),  // 1:6  6.0
+            20 => (20, 3), // 3:20 ~6.666
             _ => panic!("invalid frequency multiplier value"),
         }
     }
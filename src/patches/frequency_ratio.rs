@@ -0,0 +1,187 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::FrequencyMultiplier;
+
+/// Coarse ratios go from 0 (which is 0.5, as on the DX7) up to 31.
+pub const COARSE_MAX: u8 = 31;
+
+/// Fine ratios add up to 0.99 on top of the coarse ratio, in hundredths.
+pub const FINE_MAX: u8 = 99;
+
+/// An operator's frequency as a multiple of the note played. It's kept as a
+/// fraction, so presets such as 1/3 stay exact.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "SavedRatio")]
+pub struct FrequencyRatio {
+    numerator: u16,
+    denominator: u16,
+}
+
+/// Patches saved before ratios were added store the index of a preset.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SavedRatio {
+    Fraction { numerator: u16, denominator: u16 },
+    // RON reads a bare "(6)" as a tuple
+    Preset((u8,)),
+}
+
+impl From<SavedRatio> for FrequencyRatio {
+    fn from(saved: SavedRatio) -> Self {
+        match saved {
+            SavedRatio::Fraction {
+                numerator,
+                denominator,
+            } => Self::new(numerator, denominator),
+            SavedRatio::Preset((index,)) => {
+                FrequencyMultiplier(index.min(FrequencyMultiplier::max_value())).into()
+            }
+        }
+    }
+}
+
+impl From<FrequencyMultiplier> for FrequencyRatio {
+    fn from(preset: FrequencyMultiplier) -> Self {
+        let (numerator, denominator) = preset.fraction();
+        Self::new(numerator, denominator)
+    }
+}
+
+impl Default for FrequencyRatio {
+    fn default() -> Self {
+        FrequencyMultiplier::default().into()
+    }
+}
+
+impl FrequencyRatio {
+    /// A ratio of numerator / denominator. A denominator of 0 is treated as 1.
+    pub fn new(numerator: u16, denominator: u16) -> Self {
+        let denominator = denominator.max(1);
+        let divisor = gcd(numerator, denominator).max(1);
+
+        Self {
+            numerator: numerator / divisor,
+            denominator: denominator / divisor,
+        }
+    }
+
+    /// A whole ratio plus hundredths, like the TX81Z's coarse and fine ratios.
+    pub fn from_coarse_fine(coarse: u8, fine: u8) -> Self {
+        let coarse = coarse.min(COARSE_MAX);
+        Self::new(coarse_hundredths(coarse) + fine.min(FINE_MAX) as u16, 100)
+    }
+
+    pub fn ratio(self) -> f32 {
+        self.numerator as f32 / self.denominator as f32
+    }
+
+    pub fn multiply(self, frequency: f32) -> f32 {
+        frequency * self.numerator as f32 / self.denominator as f32
+    }
+
+    fn hundredths(self) -> u16 {
+        ((self.numerator as u32 * 100 + self.denominator as u32 / 2) / self.denominator as u32)
+            as u16
+    }
+
+    /// Whether coarse and fine can reach this ratio, which they can't below 0.5.
+    pub fn has_coarse_fine(self) -> bool {
+        self.numerator as u32 * 2 >= self.denominator as u32
+    }
+
+    /// The nearest coarse ratio. Ratios below 0.5 are shown as 0.
+    pub fn coarse(self) -> u8 {
+        match self.hundredths() {
+            hundredths if hundredths < 100 => 0,
+            hundredths => (hundredths / 100).min(COARSE_MAX as u16) as u8,
+        }
+    }
+
+    /// The nearest fine ratio on top of `coarse`.
+    pub fn fine(self) -> u8 {
+        let fine = self
+            .hundredths()
+            .saturating_sub(coarse_hundredths(self.coarse()));
+        fine.min(FINE_MAX as u16) as u8
+    }
+
+    /// Which preset this ratio is, if any.
+    pub fn preset(self) -> Option<FrequencyMultiplier> {
+        (0..=FrequencyMultiplier::max_value())
+            .map(FrequencyMultiplier)
+            .find(|preset| Self::from(*preset) == self)
+    }
+}
+
+impl fmt::Display for FrequencyRatio {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.preset() {
+            Some(preset) => write!(f, "{}", preset.as_ratio()),
+            None => write!(f, "{:.2}", self.ratio()),
+        }
+    }
+}
+
+fn coarse_hundredths(coarse: u8) -> u16 {
+    match coarse {
+        0 => 50,
+        coarse => coarse as u16 * 100,
+    }
+}
+
+fn gcd(a: u16, b: u16) -> u16 {
+    match b {
+        0 => a,
+        b => gcd(b, a % b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coarse_and_fine_make_any_ratio() {
+        let bell = FrequencyRatio::from_coarse_fine(1, 41);
+        assert_eq!(bell.ratio(), 1.41);
+        assert_eq!((bell.coarse(), bell.fine()), (1, 41));
+
+        assert_eq!(FrequencyRatio::from_coarse_fine(3, 50).ratio(), 3.5);
+        assert_eq!(FrequencyRatio::from_coarse_fine(0, 0).ratio(), 0.5);
+        assert!(FrequencyRatio::from_coarse_fine(0, 0).has_coarse_fine());
+        assert!(!FrequencyRatio::new(1, 3).has_coarse_fine());
+        assert_eq!(FrequencyRatio::from_coarse_fine(31, 99).ratio(), 31.99);
+        assert_eq!(FrequencyRatio::from_coarse_fine(40, 200).ratio(), 31.99);
+        assert_eq!(
+            FrequencyRatio::from_coarse_fine(2, 0).to_string(),
+            "1:2 2.0"
+        );
+    }
+
+    #[test]
+    fn presets_stay_exact() {
+        (0..=FrequencyMultiplier::max_value())
+            .map(FrequencyMultiplier)
+            .for_each(|preset| {
+                let ratio = FrequencyRatio::from(preset);
+                assert_eq!(ratio.preset(), Some(preset));
+                assert_eq!((ratio.numerator, ratio.denominator), preset.fraction());
+            });
+    }
+
+    #[test]
+    fn loads_saved_presets_and_ratios() {
+        let ratio: FrequencyRatio = ron::from_str("(numerator: 141, denominator: 100)").unwrap();
+        assert_eq!(ratio, FrequencyRatio::from_coarse_fine(1, 41));
+
+        let text = ron::to_string(&ratio).unwrap();
+        assert_eq!(ron::from_str::<FrequencyRatio>(&text).unwrap(), ratio);
+
+        // Older patches saved the preset's index
+        let preset = ron::to_string(&FrequencyMultiplier(11)).unwrap();
+        let ratio: FrequencyRatio = ron::from_str(&preset).unwrap();
+        assert_eq!(ratio.ratio(), 2.0);
+    }
+}
@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+
+use crate::notes;
+
+/// Middle C, in the notes table
+const DEFAULT_BREAKPOINT: usize = 36;
+
+/// Semitones from the breakpoint where a full depth is reached
+const FULL_DEPTH_DISTANCE: f32 = 48.0;
+
+/// Each step of depth is 4 envelope steps at full depth, the same as total level
+const DEPTH_TO_ATTENUATION: f32 = 4.0;
+
+const MAX_ATTENUATION: u32 = 1023;
+
+/// How attenuation grows with distance from the breakpoint.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScalingCurve {
+    /// Attenuation grows evenly with each semitone.
+    #[default]
+    Linear,
+    /// Gentle near the breakpoint, doubling every octave.
+    Exponential,
+}
+
+impl ScalingCurve {
+    /// Scales a distance of 0.0..=1.0 of the full depth distance.
+    fn apply(self, distance: f32) -> f32 {
+        match self {
+            Self::Linear => distance,
+            Self::Exponential => (2.0f32.powf(distance * 4.0) - 1.0) / 15.0,
+        }
+    }
+}
+
+/// Attenuates an operator further the further a note is from the breakpoint,
+/// like the DX7's level scaling or the OPL's KSL.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LevelScaling {
+    /// Note index where scaling starts
+    pub(crate) breakpoint: usize,
+    /// Below the breakpoint
+    pub(crate) left_depth: u8,
+    pub(crate) left_curve: ScalingCurve,
+    /// Above the breakpoint
+    pub(crate) right_depth: u8,
+    pub(crate) right_curve: ScalingCurve,
+}
+
+impl Default for LevelScaling {
+    fn default() -> Self {
+        Self {
+            breakpoint: DEFAULT_BREAKPOINT,
+            left_depth: 0,
+            left_curve: ScalingCurve::default(),
+            right_depth: 0,
+            right_curve: ScalingCurve::default(),
+        }
+    }
+}
+
+impl LevelScaling {
+    /// The extra attenuation for a note, in envelope steps.
+    pub fn attenuation(&self, frequency: f32) -> u32 {
+        if self.left_depth == 0 && self.right_depth == 0 {
+            return 0;
+        }
+
+        let breakpoint = notes::index_to_frequency(self.breakpoint);
+        if breakpoint <= 0.0 || frequency <= 0.0 {
+            return 0;
+        }
+
+        let semitones = 12.0 * (frequency / breakpoint).log2();
+        let (depth, curve) = if semitones < 0.0 {
+            (self.left_depth, self.left_curve)
+        } else {
+            (self.right_depth, self.right_curve)
+        };
+
+        let distance = (semitones.abs() / FULL_DEPTH_DISTANCE).min(1.0);
+        let attenuation = curve.apply(distance) * depth as f32 * DEPTH_TO_ATTENUATION;
+
+        (attenuation as u32).min(MAX_ATTENUATION)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attenuates_away_from_the_breakpoint() {
+        notes::generate();
+        let frequency = |note| notes::index_to_frequency(notes::parse_note(note).unwrap());
+
+        let scaling = LevelScaling {
+            left_depth: 100,
+            right_depth: 200,
+            right_curve: ScalingCurve::Exponential,
+            ..LevelScaling::default()
+        };
+
+        assert_eq!(scaling.attenuation(frequency("C4")), 0);
+        assert_eq!(scaling.attenuation(frequency("C2")), 200);
+        assert_eq!(scaling.attenuation(frequency("C8")), 800);
+
+        // Halfway there, the exponential curve is only a fifth of the depth
+        let halfway = scaling.attenuation(frequency("C6"));
+        assert!((150..=170).contains(&halfway), "{}", halfway);
+
+        assert_eq!(LevelScaling::default().attenuation(frequency("C8")), 0);
+    }
+}
@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    waveform::{wrap_phase, WaveformState},
+    Waveform, TARGET_SAMPLE_RATE,
+};
+
+/// Vibrato depth of each pitch modulation sensitivity, in cents either way,
+/// as on the YM2612.
+const PITCH_SENSITIVITY_CENTS: [f32; 8] = [0.0, 3.4, 6.7, 10.0, 14.0, 20.0, 40.0, 80.0];
+pub const PITCH_SENSITIVITY_MAX: u8 = PITCH_SENSITIVITY_CENTS.len() as u8 - 1;
+
+/// Tremolo depth of each amplitude modulation sensitivity, in envelope steps.
+/// These are the YM2612's 0, 1.4, 5.9 and 11.8 dB.
+const AMPLITUDE_SENSITIVITY_STEPS: [f32; 4] = [0.0, 15.0, 63.0, 126.0];
+pub const AMPLITUDE_SENSITIVITY_MAX: u8 = AMPLITUDE_SENSITIVITY_STEPS.len() as u8 - 1;
+
+pub const LFO_RATE_MIN: f32 = 0.05;
+pub const LFO_RATE_MAX: f32 = 50.0;
+/// Longest delay or fade in, in seconds
+pub const LFO_TIME_MAX: f32 = 10.0;
+
+/// A patch's low frequency oscillator, for vibrato and tremolo.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LfoDefinition {
+    pub(crate) waveform: Waveform,
+    /// In hz
+    pub(crate) rate: f32,
+    /// Seconds after key on before the LFO is heard
+    pub(crate) delay: f32,
+    /// Seconds to fade in to full depth, after the delay
+    pub(crate) fade_in: f32,
+    /// Vibrato depth for every operator, from 0 (off) to 7
+    pub(crate) pitch_sensitivity: u8,
+    /// Restart the LFO's cycle on key on, rather than leaving it running
+    pub(crate) retrigger: bool,
+}
+
+impl Default for LfoDefinition {
+    fn default() -> Self {
+        Self {
+            waveform: Waveform::Triangle,
+            rate: 5.0,
+            delay: 0.0,
+            fade_in: 0.0,
+            pitch_sensitivity: 0,
+            retrigger: false,
+        }
+    }
+}
+
+impl LfoDefinition {
+    /// The vibrato depth in cents either way.
+    pub fn pitch_depth(&self) -> f32 {
+        PITCH_SENSITIVITY_CENTS[self.pitch_sensitivity.min(PITCH_SENSITIVITY_MAX) as usize]
+    }
+
+    /// How much a fully faded in LFO is heard, from 0.0 to 1.0, some ticks after key on.
+    fn fade(&self, ticks: u32) -> f32 {
+        let seconds = ticks as f32 / TARGET_SAMPLE_RATE as f32 - self.delay;
+        if seconds < 0.0 {
+            0.0
+        } else if seconds >= self.fade_in {
+            1.0
+        } else {
+            seconds / self.fade_in
+        }
+    }
+}
+
+/// The largest tremolo, in envelope steps, for an operator's amplitude modulation sensitivity.
+/// 0 turns tremolo off for the operator, like the YM2612's AM enable bit.
+pub fn tremolo_depth(sensitivity: u8) -> f32 {
+    AMPLITUDE_SENSITIVITY_STEPS[sensitivity.min(AMPLITUDE_SENSITIVITY_MAX) as usize]
+}
+
+/// The LFO's output for one sample.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LfoOutput {
+    /// Multiplies each operator's frequency
+    pub(crate) pitch: f32,
+    /// From 0.0 to 1.0, scaled by each operator's tremolo depth
+    pub(crate) amplitude: f32,
+}
+
+/// Fractional bits of the vibrato's pitch multiplier in the fixed point path.
+const FIXED_PITCH_BITS: u32 = 16;
+
+impl LfoOutput {
+    /// Applies the vibrato to a fixed point phase increment. Without vibrato
+    /// the increment is left exactly as it is.
+    pub(crate) fn fixed_vibrato(&self, increment: u32) -> u32 {
+        let pitch = (self.pitch * (1 << FIXED_PITCH_BITS) as f32) as u64;
+        ((increment as u64 * pitch) >> FIXED_PITCH_BITS) as u32
+    }
+}
+
+impl Default for LfoOutput {
+    fn default() -> Self {
+        Self {
+            pitch: 1.0,
+            amplitude: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LfoInstance {
+    phase: f32,
+    /// Ticks since key on, for the delay and fade in
+    ticks: u32,
+    state: WaveformState,
+}
+
+impl LfoInstance {
+    pub fn key_on(&mut self, definition: &LfoDefinition) {
+        self.ticks = 0;
+        if definition.retrigger {
+            self.phase = 0.0;
+        }
+    }
+
+    /// Advances the LFO by some envelope ticks, and generates its output.
+    pub(crate) fn tick(&mut self, definition: &LfoDefinition, ticks: u32) -> LfoOutput {
+        let increment = definition.rate / TARGET_SAMPLE_RATE as f32;
+        self.phase = wrap_phase(self.phase + increment * ticks as f32);
+        self.ticks = self.ticks.saturating_add(ticks);
+
+        let fade = definition.fade(self.ticks);
+        if fade == 0.0 {
+            return LfoOutput::default();
+        }
+
+        let value = definition
+            .waveform
+            .func(self.phase, increment, &mut self.state)
+            * fade;
+        let cents = value * definition.pitch_depth();
+
+        LfoOutput {
+            pitch: 2.0f32.powf(cents / 1200.0),
+            // Tremolo only ever makes the operator quieter
+            amplitude: (value + fade) * 0.5,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_then_fades_in() {
+        crate::waveform::init_waveform_tables();
+
+        let definition = LfoDefinition {
+            waveform: Waveform::Square,
+            rate: 10.0,
+            delay: 0.1,
+            fade_in: 0.1,
+            pitch_sensitivity: PITCH_SENSITIVITY_MAX,
+            retrigger: true,
+        };
+        let mut lfo = LfoInstance::default();
+        lfo.key_on(&definition);
+
+        let outputs: Vec<_> = (0..TARGET_SAMPLE_RATE / 2)
+            .map(|_| lfo.tick(&definition, 1))
+            .collect();
+        let at = |seconds: f32| outputs[(seconds * TARGET_SAMPLE_RATE as f32) as usize];
+
+        assert_eq!(at(0.05), LfoOutput::default());
+
+        // Three quarters of the way through the fade, and at full depth after it
+        let cents = |output: LfoOutput| 1200.0 * output.pitch.log2();
+        assert!((cents(at(0.175)).abs() - 60.0).abs() < 0.5);
+        assert!((cents(at(0.225)).abs() - 80.0).abs() < 0.5);
+        assert!(outputs
+            .iter()
+            .all(|output| (0.0..=1.0).contains(&output.amplitude)));
+    }
+}
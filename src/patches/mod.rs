@@ -1,18 +1,41 @@
+use std::{ptr::addr_of_mut, sync::Once};
+
 mod algorithm;
 mod envelope;
+mod envelope_plot;
 mod feedback;
+mod fixed_point;
 mod frequency_multiplier;
+mod frequency_ratio;
+mod level_scaling;
+mod lfo;
 mod operator;
 mod patch_definition;
+mod patch_file;
 mod patch_instance;
+mod routing;
+mod trigger;
+mod velocity;
+mod waveform_morph;
+mod ym2612_envelope;
 
 pub use algorithm::*;
 pub use envelope::*;
+pub use envelope_plot::*;
 pub use feedback::*;
+pub use fixed_point::*;
 pub use frequency_multiplier::*;
+pub use frequency_ratio::*;
+pub use level_scaling::*;
+pub use lfo::*;
 pub use operator::*;
 pub use patch_definition::*;
 pub use patch_instance::*;
+pub use routing::*;
+pub use trigger::*;
+pub use velocity::*;
+pub use waveform_morph::*;
+pub use ym2612_envelope::*;
 
 pub const OPERATOR_COUNT: usize = 4;
 pub const AMPLIFICATION: f32 = 25.0;
@@ -25,20 +48,23 @@ fn calculate_attenuation(amount: usize, max: usize) -> f32 {
     10f32.powf(db / 20.0)
 }
 
+/// Only generates the tables the first time it's called.
 pub(crate) fn init_attenuation_table() {
-    unsafe {
-        ATTENUATION_TABLE_10
+    static INIT: Once = Once::new();
+
+    INIT.call_once(|| unsafe {
+        (*addr_of_mut!(ATTENUATION_TABLE_10))
             .iter_mut()
             .enumerate()
             .for_each(|(index, output)| {
                 *output = calculate_attenuation(index, ATTENUATION_MAX as usize)
             });
 
-        ATTENUATION_TABLE_8
+        (*addr_of_mut!(ATTENUATION_TABLE_8))
             .iter_mut()
             .enumerate()
             .for_each(|(index, output)| *output = calculate_attenuation(index, u8::MAX as usize))
-    }
+    });
 }
 
 pub(crate) fn attenuation_table_u10(index: u16) -> f32 {
@@ -1,47 +1,172 @@
 use std::sync::Arc;
 
 use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
 
-use crate::{Waveform, TARGET_SAMPLE_RATE};
+use crate::{
+    waveform::{modulated_phase, wrap_phase, WaveformState},
+    Waveform, TARGET_SAMPLE_RATE,
+};
 
-use super::{EnvelopeDefinition, EnvelopeInstance, FrequencyMultiplier};
+use super::{
+    attenuation_to_volume, fixed_phase_index, fixed_phase_offset, EnvelopeDefinition,
+    EnvelopeInstance, FrequencyRatio, LevelScaling, WaveformMorph,
+};
+
+/// Steps in one cycle of the fixed point phase accumulator.
+const FIXED_PHASE_CYCLE: f32 = 4_294_967_296.0;
+
+/// Where an operator starts when switched to a fixed frequency, in hz.
+pub const DEFAULT_FIXED_FREQUENCY: f32 = 440.0;
+pub const FIXED_FREQUENCY_MIN: f32 = 0.1;
+pub const FIXED_FREQUENCY_MAX: f32 = 20_000.0;
+
+/// Detune goes up to a semitone either way, in hundredths.
+pub const DETUNE_MAX: i8 = 100;
 
 // const ONE_SEMITONE: f32 = 2.0_f32.powf(1.0/12.0);
 
-#[derive(Default, Clone, Debug)]
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct OperatorDefinition {
     pub(crate) waveform: Waveform,
-    pub(crate) frequency_multiplier: FrequencyMultiplier,
+    /// Older patches saved a preset index here, which still loads
+    #[serde(alias = "frequency_multiplier")]
+    pub(crate) frequency_ratio: FrequencyRatio,
     pub(crate) detune: i8,
     pub(crate) envelope: Arc<RwLock<EnvelopeDefinition>>,
+    #[serde(default)]
+    pub(crate) morph: Option<WaveformMorph>,
+    #[serde(default)]
+    pub(crate) level_scaling: LevelScaling,
+    /// Runs at this many hz whatever note is played, instead of following
+    /// the frequency ratio. Detune still applies.
+    #[serde(default)]
+    pub(crate) fixed_frequency: Option<f32>,
+    /// How much the patch's LFO changes the operator's level, from 0 (off) to 3
+    #[serde(default)]
+    pub(crate) amplitude_sensitivity: u8,
+    /// How much softer notes quieten the operator, from 0 (not at all) to 7.
+    /// Modulators lose brightness, and carriers loudness.
+    #[serde(default)]
+    pub(crate) velocity_sensitivity: u8,
 }
 
 pub struct OperatorInstance {
-    pub(crate) definition: Arc<RwLock<OperatorDefinition>>,
     pub(crate) envelope: EnvelopeInstance,
-    pub(crate) clock: f32,
+    pub(crate) phase: f32,
+    pub(crate) fixed_phase: u32,
+    pub(crate) waveform_state: WaveformState,
+    pub(crate) morph_state: WaveformState,
+    pub(crate) morph_lfo_phase: f32,
 }
 
 impl OperatorInstance {
-    pub fn func(&mut self, base_frequency: f32, modulation: f32) -> f32 {
-        let definition = self.definition.read();
+    /// Advances the phase by one sample, and generates the output. Modulation is
+    /// a phase offset in radians. Takes already read definitions and a
+    /// precalculated increment, so a whole block can be generated while only
+    /// reading them once.
+    pub(crate) fn func_with(
+        &mut self,
+        definition: &OperatorDefinition,
+        envelope: &EnvelopeDefinition,
+        increment: f32,
+        modulation: f32,
+    ) -> f32 {
+        // Only the increment depends on the frequency, so changing it never jumps the phase
+        self.phase = wrap_phase(self.phase + increment);
+
+        let phase = modulated_phase(self.phase, modulation);
+        let mut output = definition
+            .waveform
+            .func(phase, increment, &mut self.waveform_state);
+
+        if let Some(morph) = &definition.morph {
+            let position = morph_position(morph, &mut self.morph_lfo_phase, &self.envelope);
+            let target = morph.waveform.func(phase, increment, &mut self.morph_state);
+            output += (target - output) * position;
+        }
+
+        output * self.envelope.attenuation_with(envelope)
+    }
 
-        let frequency =
-            definition.frequency_multiplier.multiply(base_frequency) * self.detune_as_multiplier();
-        let amt = TARGET_SAMPLE_RATE as f32 / frequency;
+    /// The fixed point version of `func_with`. The increment is from
+    /// `fixed_increment`, modulation is a phase offset in 1024ths of a cycle,
+    /// and the output is a 14 bit signed value.
+    pub(crate) fn func_fixed_with(
+        &mut self,
+        definition: &OperatorDefinition,
+        envelope: &EnvelopeDefinition,
+        increment: u32,
+        modulation: i32,
+    ) -> i32 {
+        self.fixed_phase = self.fixed_phase.wrapping_add(increment);
 
-        self.clock += 1.0;
-        if self.clock > amt {
-            self.clock -= amt
+        let phase = self
+            .fixed_phase
+            .wrapping_add(fixed_phase_offset(modulation));
+        let attenuation = self.envelope.attenuation_fixed_with(envelope);
+        let mut output = fixed_waveform(
+            &definition.waveform,
+            phase,
+            increment,
+            attenuation,
+            &mut self.waveform_state,
+        );
+
+        if let Some(morph) = &definition.morph {
+            let position = morph_position(morph, &mut self.morph_lfo_phase, &self.envelope);
+            let position = (position * 256.0) as i32;
+            let target = fixed_waveform(
+                &morph.waveform,
+                phase,
+                increment,
+                attenuation,
+                &mut self.morph_state,
+            );
+            output += ((target - output) * position) >> 8;
         }
 
-        definition.waveform.func(self.clock, frequency, modulation) * self.envelope.attenuation()
+        output
+    }
+}
+
+impl OperatorDefinition {
+    /// How far the phase advances each sample, in cycles.
+    pub(crate) fn increment(&self, base_frequency: f32) -> f32 {
+        self.frequency(base_frequency) / TARGET_SAMPLE_RATE as f32
+    }
+
+    /// How far the fixed point phase accumulator advances each sample. Worked
+    /// out once a block, so the samples themselves only add.
+    pub(crate) fn fixed_increment(&self, base_frequency: f32) -> u32 {
+        (self.increment(base_frequency) * FIXED_PHASE_CYCLE) as u32
+    }
+
+    /// The operator's frequency in hz, for a note at the base frequency.
+    pub fn frequency(&self, base_frequency: f32) -> f32 {
+        let frequency = match self.fixed_frequency {
+            Some(frequency) => frequency,
+            None => self.frequency_ratio.multiply(base_frequency),
+        };
+        frequency * self.detune_as_multiplier()
+    }
+
+    /// A copy for rendering a block, with its waveforms frozen.
+    pub(crate) fn frozen(&self) -> Self {
+        Self {
+            waveform: self.waveform.frozen(),
+            morph: self.morph.as_ref().map(|morph| WaveformMorph {
+                waveform: morph.waveform.frozen(),
+                ..morph.clone()
+            }),
+            ..self.clone()
+        }
     }
 
     fn detune_as_multiplier(&self) -> f32 {
-        let detune = self.definition.read().detune;
-        assert!(detune <= 100);
-        assert!(detune >= -100);
+        let detune = self.detune;
+        assert!(detune <= DETUNE_MAX);
+        assert!(detune >= -DETUNE_MAX);
         if detune >= 0 {
             1.0 + ((detune as f32 / 100.0) * 0.059_463_095)
         } else {
@@ -49,3 +174,78 @@ impl OperatorInstance {
         }
     }
 }
+
+/// Advances the morph LFO, and returns how far to crossfade to the morph waveform.
+fn morph_position(morph: &WaveformMorph, lfo_phase: &mut f32, envelope: &EnvelopeInstance) -> f32 {
+    *lfo_phase = wrap_phase(*lfo_phase + morph.lfo_rate() / TARGET_SAMPLE_RATE as f32);
+
+    morph.position(envelope.level(), *lfo_phase)
+}
+
+/// Generates a fixed point waveform value, with the envelope's attenuation applied.
+fn fixed_waveform(
+    waveform: &Waveform,
+    phase: u32,
+    increment: u32,
+    attenuation: u32,
+    state: &mut WaveformState,
+) -> i32 {
+    match waveform.fixed_func(fixed_phase_index(phase)) {
+        Some(Some(sample)) => sample.to_linear(attenuation),
+        Some(None) => 0,
+        None => {
+            // Waveforms without a log-sin form come from the float path. These
+            // aren't bit stable, but are still attenuated with the exp table
+            let phase = wrap_phase(phase as f32 / FIXED_PHASE_CYCLE);
+            let increment = increment as f32 / FIXED_PHASE_CYCLE;
+            let value = waveform.func(phase, increment, state);
+            (value * attenuation_to_volume(attenuation << 2) as f32) as i32
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changing_frequency_does_not_jump_the_phase() {
+        let definition = OperatorDefinition::default();
+        let envelope = EnvelopeDefinition::default();
+        let mut operator = OperatorInstance {
+            envelope: EnvelopeInstance::default(),
+            phase: 0.0,
+            fixed_phase: 0,
+            waveform_state: WaveformState::default(),
+            morph_state: WaveformState::default(),
+            morph_lfo_phase: 0.0,
+        };
+
+        (0..1000).for_each(|_| {
+            operator.func_with(&definition, &envelope, definition.increment(440.0), 0.0);
+        });
+
+        let before = operator.phase;
+        operator.func_with(&definition, &envelope, definition.increment(880.0), 100.0);
+        let expected = wrap_phase(before + 880.0 / TARGET_SAMPLE_RATE as f32);
+
+        assert!((operator.phase - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fixed_frequency_ignores_the_note() {
+        let mut definition = OperatorDefinition {
+            frequency_ratio: FrequencyRatio::new(2, 1),
+            ..OperatorDefinition::default()
+        };
+        assert_eq!(definition.frequency(440.0), 880.0);
+
+        definition.fixed_frequency = Some(1000.0);
+        assert_eq!(definition.frequency(440.0), 1000.0);
+        assert_eq!(definition.frequency(55.0), 1000.0);
+
+        // Detune still moves it
+        definition.detune = 100;
+        assert!(definition.frequency(55.0) > 1050.0);
+    }
+}
@@ -1,22 +1,93 @@
 use std::{mem::MaybeUninit, sync::Arc};
 
 use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
 
 use super::{
-    Algorithm, EnvelopeDefinition, EnvelopeInstance, FeedbackLevel, FrequencyMultiplier,
-    OperatorDefinition, OperatorInstance, OPERATOR_COUNT,
+    Algorithm, EnvelopeDefinition, EnvelopeInstance, EnvelopeMode, FeedbackLevel, FrequencyRatio,
+    LevelScaling, LfoDefinition, OperatorDefinition, OperatorInstance, RenderMode, RoutingError,
+    RoutingMatrix, TriggerMode, OPERATOR_COUNT,
 };
-use crate::Waveform;
+use crate::{waveform::WaveformState, Waveform, TARGET_SAMPLE_RATE};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PatchDefinition {
     pub(crate) operators: [Arc<RwLock<OperatorDefinition>>; OPERATOR_COUNT],
     pub(crate) algorithm: Algorithm,
+    /// Replaces the algorithm when set. Only set through `set_routing`, which checks for cycles.
+    #[serde(default)]
+    pub(crate) routing: Option<RoutingMatrix>,
     pub(crate) feedback: FeedbackLevel,
+    #[serde(default)]
+    pub(crate) render_mode: RenderMode,
+    #[serde(default)]
+    pub(crate) envelope_mode: EnvelopeMode,
+    #[serde(default)]
+    pub(crate) trigger_mode: TriggerMode,
+    #[serde(default)]
+    pub(crate) lfo: LfoDefinition,
+    #[serde(skip)]
     pub(crate) wall_tick_time: f32,
 }
 
+/// A copy of a patch's parameters, so a whole block can be rendered without taking any locks.
+pub(crate) struct PatchSnapshot {
+    pub(crate) routing: RoutingMatrix,
+    /// The order to generate the operators in, from the routing
+    pub(crate) order: [usize; OPERATOR_COUNT],
+    pub(crate) feedback: FeedbackLevel,
+    pub(crate) render_mode: RenderMode,
+    pub(crate) envelope_mode: EnvelopeMode,
+    pub(crate) lfo: LfoDefinition,
+    pub(crate) wall_tick_time: f32,
+    pub(crate) operators: [OperatorDefinition; OPERATOR_COUNT],
+    pub(crate) envelopes: [EnvelopeDefinition; OPERATOR_COUNT],
+}
+
 impl PatchDefinition {
+    pub(crate) fn snapshot(&self) -> PatchSnapshot {
+        let operators: [OperatorDefinition; OPERATOR_COUNT] =
+            std::array::from_fn(|index| self.operators[index].read().frozen());
+        let envelopes = std::array::from_fn(|index| operators[index].envelope.read().clone());
+
+        let routing = self.routing();
+        // The routing is checked whenever it's set, so this never falls back
+        let order = routing
+            .order()
+            .unwrap_or(std::array::from_fn(|index| index));
+
+        PatchSnapshot {
+            routing,
+            order,
+            feedback: self.feedback,
+            render_mode: self.render_mode,
+            envelope_mode: self.envelope_mode,
+            lfo: LfoDefinition {
+                waveform: self.lfo.waveform.frozen(),
+                ..self.lfo.clone()
+            },
+            wall_tick_time: self.wall_tick_time,
+            operators,
+            envelopes,
+        }
+    }
+
+    /// The custom routing if there is one, otherwise the algorithm's.
+    pub fn routing(&self) -> RoutingMatrix {
+        self.routing.unwrap_or_else(|| self.algorithm.routing())
+    }
+
+    /// Sets a custom routing, or goes back to the algorithm with `None`.
+    /// Routings with cycles are rejected, leaving the patch as it was.
+    pub fn set_routing(&mut self, routing: Option<RoutingMatrix>) -> Result<(), RoutingError> {
+        if let Some(routing) = &routing {
+            routing.order()?;
+        }
+
+        self.routing = routing;
+        Ok(())
+    }
+
     pub(crate) fn generate_new_operators(&self) -> [OperatorInstance; OPERATOR_COUNT] {
         let mut output: [MaybeUninit<OperatorInstance>; OPERATOR_COUNT] =
             unsafe { MaybeUninit::uninit().assume_init() };
@@ -26,9 +97,16 @@ impl PatchDefinition {
             .zip(output.iter_mut())
             .for_each(|(source, target)| {
                 target.write(OperatorInstance {
-                    definition: source.clone(),
-                    envelope: EnvelopeInstance::new(source.read().envelope.clone()),
-                    clock: 0.0,
+                    // Patch instances tick their envelopes once per TARGET_SAMPLE_TICK_TIME
+                    envelope: EnvelopeInstance::new(
+                        source.read().envelope.clone(),
+                        TARGET_SAMPLE_RATE,
+                    ),
+                    phase: 0.0,
+                    fixed_phase: 0,
+                    waveform_state: WaveformState::default(),
+                    morph_state: WaveformState::default(),
+                    morph_lfo_phase: 0.0,
                 });
             });
 
@@ -43,29 +121,49 @@ impl PatchDefinition {
             operators: [
                 Arc::new(RwLock::new(OperatorDefinition {
                     waveform: Waveform::default(),
-                    frequency_multiplier: FrequencyMultiplier::default(),
+                    frequency_ratio: FrequencyRatio::default(),
                     detune: 0,
                     envelope: Arc::new(RwLock::new(EnvelopeDefinition::default())),
+                    morph: None,
+                    level_scaling: LevelScaling::default(),
+                    fixed_frequency: None,
+                    amplitude_sensitivity: 0,
+                    velocity_sensitivity: 0,
                 })),
                 Arc::new(RwLock::new(OperatorDefinition {
                     waveform: Waveform::default(),
-                    frequency_multiplier: FrequencyMultiplier::default(),
+                    frequency_ratio: FrequencyRatio::default(),
                     detune: 0,
                     envelope: Arc::new(RwLock::new(EnvelopeDefinition::default())),
+                    morph: None,
+                    level_scaling: LevelScaling::default(),
+                    fixed_frequency: None,
+                    amplitude_sensitivity: 0,
+                    velocity_sensitivity: 0,
                 })),
                 Arc::new(RwLock::new(OperatorDefinition {
                     waveform: Waveform::default(),
-                    frequency_multiplier: FrequencyMultiplier::default(),
+                    frequency_ratio: FrequencyRatio::default(),
                     detune: 0,
                     envelope: Arc::new(RwLock::new(EnvelopeDefinition::default())),
+                    morph: None,
+                    level_scaling: LevelScaling::default(),
+                    fixed_frequency: None,
+                    amplitude_sensitivity: 0,
+                    velocity_sensitivity: 0,
                 })),
                 Arc::new(RwLock::new(OperatorDefinition {
                     waveform: Waveform::default(),
-                    frequency_multiplier: FrequencyMultiplier::default(),
+                    frequency_ratio: FrequencyRatio::default(),
                     detune: 0,
                     envelope: Arc::new(RwLock::new(EnvelopeDefinition::new(
                         255, 255, 0, 255, 0, 255,
                     ))),
+                    morph: None,
+                    level_scaling: LevelScaling::default(),
+                    fixed_frequency: None,
+                    amplitude_sensitivity: 0,
+                    velocity_sensitivity: 0,
                 })),
             ],
             // operators: [
@@ -95,7 +193,12 @@ impl PatchDefinition {
             //     }),
             // ],
             algorithm: Algorithm(0),
+            routing: None,
             feedback: FeedbackLevel(0),
+            render_mode: RenderMode::default(),
+            envelope_mode: EnvelopeMode::default(),
+            trigger_mode: TriggerMode::default(),
+            lfo: LfoDefinition::default(),
         }
     }
 }
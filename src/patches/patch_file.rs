@@ -0,0 +1,257 @@
+use std::{fmt, fs, io, path::Path};
+
+use ron::ser::PrettyConfig;
+
+use super::{
+    OperatorDefinition, PatchDefinition, RoutingError, DETUNE_MAX, FEEDBACK_MAX,
+    FIXED_FREQUENCY_MAX, FIXED_FREQUENCY_MIN, LFO_RATE_MAX, LFO_RATE_MIN,
+};
+use crate::{notes::TOTAL_NOTES, Waveform};
+
+#[derive(Debug)]
+pub enum PatchFileError {
+    Io(io::Error),
+    Format(ron::Error),
+    Routing(RoutingError),
+    /// A setting the engine can't play, such as a feedback level above `FEEDBACK_MAX`
+    OutOfRange {
+        setting: String,
+        value: String,
+    },
+}
+
+impl fmt::Display for PatchFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "patch file error: {}", error),
+            Self::Format(error) => write!(f, "invalid patch: {}", error),
+            Self::Routing(error) => write!(f, "invalid patch routing: {}", error),
+            Self::OutOfRange { setting, value } => {
+                write!(f, "invalid patch: {} of {} is out of range", setting, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PatchFileError {}
+
+impl From<io::Error> for PatchFileError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<ron::Error> for PatchFileError {
+    fn from(error: ron::Error) -> Self {
+        Self::Format(error)
+    }
+}
+
+impl From<RoutingError> for PatchFileError {
+    fn from(error: RoutingError) -> Self {
+        Self::Routing(error)
+    }
+}
+
+impl PatchDefinition {
+    /// Saves the patch, including any custom wavetables, as a RON file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), PatchFileError> {
+        let text = ron::ser::to_string_pretty(self, PrettyConfig::default())?;
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// Loads a patch file into this definition. The existing operator and
+    /// envelope handles are kept, so playing instances hear the new patch.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<(), PatchFileError> {
+        let text = fs::read_to_string(path)?;
+        let loaded: PatchDefinition = ron::from_str(&text)?;
+        if let Some(routing) = &loaded.routing {
+            routing.order()?;
+        }
+        loaded.check_ranges()?;
+
+        self.operators
+            .iter()
+            .zip(loaded.operators.iter())
+            .for_each(|(target, source)| {
+                let source = source.read();
+                let mut target = target.write();
+
+                let envelope = target.envelope.clone();
+                *envelope.write() = source.envelope.read().clone();
+
+                *target = OperatorDefinition {
+                    envelope,
+                    ..source.clone()
+                };
+            });
+
+        *self = PatchDefinition {
+            operators: self.operators.clone(),
+            wall_tick_time: self.wall_tick_time,
+            ..loaded
+        };
+
+        Ok(())
+    }
+
+    /// Checks every loaded setting which would panic, or can't be set from the gui.
+    fn check_ranges(&self) -> Result<(), PatchFileError> {
+        check_range(
+            "feedback",
+            &self.feedback.0,
+            self.feedback.0 <= FEEDBACK_MAX,
+        )?;
+        check_waveform("LFO waveform", &self.lfo.waveform)?;
+        check_range(
+            "LFO rate",
+            &self.lfo.rate,
+            (LFO_RATE_MIN..=LFO_RATE_MAX).contains(&self.lfo.rate),
+        )?;
+
+        self.operators
+            .iter()
+            .enumerate()
+            .try_for_each(|(index, operator)| {
+                let operator = operator.read();
+                let setting = |name| format!("operator {} {}", index + 1, name);
+
+                check_waveform(&setting("waveform"), &operator.waveform)?;
+                if let Some(morph) = &operator.morph {
+                    check_waveform(&setting("morph waveform"), &morph.waveform)?;
+                }
+                check_range(
+                    &setting("detune"),
+                    &operator.detune,
+                    (-DETUNE_MAX..=DETUNE_MAX).contains(&operator.detune),
+                )?;
+                if let Some(frequency) = operator.fixed_frequency {
+                    check_range(
+                        &setting("fixed frequency"),
+                        &frequency,
+                        (FIXED_FREQUENCY_MIN..=FIXED_FREQUENCY_MAX).contains(&frequency),
+                    )?;
+                }
+                let breakpoint = operator.level_scaling.breakpoint;
+                check_range(
+                    &setting("level scaling breakpoint"),
+                    &breakpoint,
+                    breakpoint < TOTAL_NOTES,
+                )
+            })
+    }
+}
+
+fn check_range(setting: &str, value: &dyn fmt::Debug, valid: bool) -> Result<(), PatchFileError> {
+    match valid {
+        true => Ok(()),
+        false => Err(PatchFileError::OutOfRange {
+            setting: setting.to_string(),
+            value: format!("{:?}", value),
+        }),
+    }
+}
+
+fn check_waveform(setting: &str, waveform: &Waveform) -> Result<(), PatchFileError> {
+    check_range(setting, waveform, waveform.is_valid())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{patches::RoutingMatrix, waveform::Wavetable, Waveform, TARGET_SAMPLE_RATE};
+
+    #[test]
+    fn saved_patches_load_the_same() {
+        let mut patch = PatchDefinition::new(TARGET_SAMPLE_RATE);
+        patch.feedback.0 = 5;
+        patch.lfo.pitch_sensitivity = 3;
+        patch.set_routing(Some(RoutingMatrix::additive())).unwrap();
+        {
+            let mut operator = patch.operators[1].write();
+            operator.waveform = Waveform::Custom(Wavetable::from_samples(vec![0.25, -0.5, 1.0]));
+            operator.fixed_frequency = Some(220.0);
+            operator.envelope.write().sustain_level = 40;
+        }
+
+        let path = std::env::temp_dir().join(format!("patch-{}.ron", std::process::id()));
+        patch.save(&path).unwrap();
+
+        let mut loaded = PatchDefinition::new(TARGET_SAMPLE_RATE);
+        let operator = loaded.operators[1].clone();
+        let envelope = operator.read().envelope.clone();
+        let result = loaded.load(&path);
+        fs::remove_file(&path).unwrap();
+        result.unwrap();
+
+        assert_eq!(
+            ron::to_string(&loaded).unwrap(),
+            ron::to_string(&patch).unwrap()
+        );
+
+        // Instances playing the patch keep their handles, and hear the loaded one
+        assert!(Arc::ptr_eq(&operator, &loaded.operators[1]));
+        assert_eq!(envelope.read().sustain_level, 40);
+    }
+
+    #[test]
+    fn patches_with_settings_out_of_range_are_not_loaded() {
+        let path = std::env::temp_dir().join(format!("range-{}.ron", std::process::id()));
+        let load = |edit: &dyn Fn(&mut PatchDefinition)| {
+            let mut patch = PatchDefinition::new(TARGET_SAMPLE_RATE);
+            edit(&mut patch);
+            patch.save(&path).unwrap();
+
+            let mut loaded = PatchDefinition::new(TARGET_SAMPLE_RATE);
+            let result = loaded.load(&path);
+            fs::remove_file(&path).unwrap();
+            result
+        };
+
+        let rejected = |edit: &dyn Fn(&mut PatchDefinition), expected: &str| match load(edit) {
+            Err(PatchFileError::OutOfRange { setting, .. }) => assert_eq!(setting, expected),
+            result => panic!("{} loaded: {:?}", expected, result),
+        };
+        rejected(&|patch| patch.feedback.0 = 16, "feedback");
+        rejected(
+            &|patch| patch.lfo.waveform = Waveform::PitchedNoise(0),
+            "LFO waveform",
+        );
+        rejected(
+            &|patch| patch.operators[1].write().waveform = Waveform::Pulse(1.5),
+            "operator 2 waveform",
+        );
+        rejected(
+            &|patch| patch.operators[0].write().detune = 101,
+            "operator 1 detune",
+        );
+        rejected(
+            &|patch| patch.operators[3].write().level_scaling.breakpoint = TOTAL_NOTES,
+            "operator 4 level scaling breakpoint",
+        );
+
+        load(&|patch| patch.operators[2].write().waveform = Waveform::PitchedNoise(32)).unwrap();
+    }
+
+    #[test]
+    fn patches_with_routing_cycles_are_not_loaded() {
+        let mut patch = PatchDefinition::new(TARGET_SAMPLE_RATE);
+        let mut routing = RoutingMatrix::additive();
+        routing.modulation[0][1] = 1.0;
+        routing.modulation[1][0] = 1.0;
+        patch.routing = Some(routing);
+
+        let path = std::env::temp_dir().join(format!("cycle-{}.ron", std::process::id()));
+        patch.save(&path).unwrap();
+
+        let mut loaded = PatchDefinition::new(TARGET_SAMPLE_RATE);
+        let result = loaded.load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(PatchFileError::Routing(_))));
+        assert_eq!(loaded.routing, None);
+    }
+}
@@ -2,7 +2,11 @@ use std::sync::Arc;
 
 use parking_lot::RwLock;
 
-use super::{ModulatedBy, OperatorInstance, PatchDefinition, AMPLIFICATION, OPERATOR_COUNT};
+use super::{
+    tremolo_depth, velocity_attenuation, Declick, EgCounter, EnvelopePosition, LfoInstance,
+    LfoOutput, OperatorInstance, PatchDefinition, PatchSnapshot, RenderMode, TriggerMode,
+    AMPLIFICATION, FIXED_OUTPUT_SCALE, OPERATOR_COUNT, VELOCITY_MAX,
+};
 use crate::{TARGET_SAMPLE_RATE, TARGET_SAMPLE_TICK_TIME};
 
 #[derive(Clone)]
@@ -17,14 +21,14 @@ impl PatchInstanceHandle {
         }
     }
 
-    pub fn set_frequency(&self, frequency: f32) {
-        self.patch.write().base_frequency = frequency
-    }
-
     pub fn set_active(&self, active: bool) {
         self.patch.write().set_active(active);
     }
 
+    pub fn note_on(&self, frequency: f32, velocity: u8) {
+        self.patch.write().note_on(frequency, velocity);
+    }
+
     pub fn write_to_buffer(&mut self, data: &mut [f32], channels: u16) {
         let mut lock = self.patch.write();
         lock.write_to_buffer(data, channels)
@@ -39,12 +43,41 @@ pub struct PatchInstance {
     pub(crate) base_frequency: f32,
     prev_feedback1: f32,
     prev_feedback2: f32,
+    prev_fixed_feedback1: i32,
+    prev_fixed_feedback2: i32,
     wall_clock: f32,
+    buffers: RenderBuffers,
+    /// The last sample output, to fade out from on a hard retrigger
+    last_output: f32,
+    declick: Declick,
+    lfo: LfoInstance,
+    /// Velocity of the note playing, from 0 to VELOCITY_MAX
+    velocity: u8,
+    /// The chip's single envelope counter, which every operator reads
+    eg_counter: EgCounter,
+}
+
+/// Scratch space for rendering, kept between blocks to avoid allocating.
+#[derive(Default)]
+struct RenderBuffers {
+    /// How many envelope ticks happen before each sample
+    ticks: Vec<u32>,
+    /// The envelope counter's value on each tick it was clocked, for every tick of the block
+    eg_counts: Vec<Option<u16>>,
+    /// The LFO's output for each sample
+    lfo: Vec<LfoOutput>,
+    outputs: [Vec<f32>; OPERATOR_COUNT],
+    fixed_outputs: [Vec<i32>; OPERATOR_COUNT],
+    mix: Vec<f32>,
 }
 
 impl PatchInstance {
     pub fn new(definition: Arc<RwLock<PatchDefinition>>, base_frequency: f32) -> Self {
-        let operators = definition.read().generate_new_operators();
+        let mut operators = definition.read().generate_new_operators();
+        operators
+            .iter_mut()
+            .for_each(|operator| operator.envelope.set_frequency(base_frequency));
+
         Self {
             operators,
             definition,
@@ -54,92 +87,263 @@ impl PatchInstance {
             base_frequency,
             prev_feedback1: 0.0,
             prev_feedback2: 0.0,
+            prev_fixed_feedback1: 0,
+            prev_fixed_feedback2: 0,
+            buffers: RenderBuffers::default(),
+            last_output: 0.0,
+            declick: Declick::default(),
+            lfo: LfoInstance::default(),
+            velocity: VELOCITY_MAX,
+            eg_counter: EgCounter::default(),
         }
     }
 
-    fn func(&mut self) -> f32 {
-        let mut outputs = [0.0f32; 4];
-        let mut final_output = 0.0f32;
+    /// Fills the output with mono samples. The definition is only read once,
+    /// so changes to it are heard from the next block.
+    pub fn render(&mut self, output: &mut [f32]) {
+        let snapshot = self.definition.read().snapshot();
+
+        self.buffers.ticks.clear();
+        (0..output.len()).for_each(|_| {
+            self.wall_clock += snapshot.wall_tick_time;
+
+            //TODO: Could optimize this with integer math?
+            let mut ticks = 0;
+            while self.wall_clock >= TARGET_SAMPLE_TICK_TIME {
+                self.advance_clock();
+                self.wall_clock -= TARGET_SAMPLE_TICK_TIME;
+                ticks += 1;
+            }
+            self.buffers.ticks.push(ticks);
+        });
 
-        let definition = self.definition.read();
+        self.render_with(&snapshot, output);
+    }
 
-        let algorithm = definition.algorithm.get_definition();
+    /// Renders with exactly one tick per sample, for the sequencer
+    pub(crate) fn force_render(&mut self, output: &mut [f32]) {
+        let snapshot = self.definition.read().snapshot();
 
-        // 1st Operator is always feedback
-        outputs[0] = self.operators[0].func(
-            self.base_frequency,
-            ((self.prev_feedback1 + self.prev_feedback2) / 2.0)
-                * definition.feedback.as_multiplier(),
-        );
+        self.buffers.ticks.clear();
+        self.buffers.ticks.resize(output.len(), 1);
+        (0..output.len()).for_each(|_| self.advance_clock());
 
-        // Handle feedback
-        self.prev_feedback2 = self.prev_feedback1;
-        self.prev_feedback1 = outputs[0];
+        self.render_with(&snapshot, output);
+    }
 
-        if algorithm.carriers[0] {
-            final_output += outputs[0] * AMPLIFICATION;
-        };
-        // End 1st Operator
+    fn render_with(&mut self, snapshot: &PatchSnapshot, output: &mut [f32]) {
+        self.operators
+            .iter_mut()
+            .zip(&snapshot.operators)
+            .for_each(|(operator, definition)| {
+                let attenuation = definition.level_scaling.attenuation(self.base_frequency);
+                operator.envelope.set_level_scaling(attenuation);
+                operator.envelope.set_velocity(velocity_attenuation(
+                    self.velocity,
+                    definition.velocity_sensitivity,
+                ));
+                operator.envelope.set_mode(snapshot.envelope_mode);
+                operator.envelope.set_render_mode(snapshot.render_mode);
+            });
+
+        let eg_counter = &mut self.eg_counter;
+        let tick_count = self.buffers.ticks.iter().sum::<u32>();
+        self.buffers.eg_counts.clear();
+        self.buffers
+            .eg_counts
+            .extend((0..tick_count).map(|_| eg_counter.tick()));
+
+        let lfo = &mut self.lfo;
+        self.buffers.lfo.clear();
+        self.buffers.lfo.extend(
+            self.buffers
+                .ticks
+                .iter()
+                .map(|ticks| lfo.tick(&snapshot.lfo, *ticks)),
+        );
 
-        (1..OPERATOR_COUNT).for_each(|i| {
-            let result = match algorithm.modulators[i - 1] {
-                ModulatedBy::None => self.operators[i].func(self.base_frequency, 0.0),
-                ModulatedBy::Single(modulator) => {
-                    self.operators[i].func(self.base_frequency, outputs[modulator])
-                }
-                ModulatedBy::Double(first, second) => {
-                    self.operators[i].func(self.base_frequency, outputs[first] + outputs[second])
-                }
-            };
+        match snapshot.render_mode {
+            RenderMode::Float => self.render_float(snapshot, output),
+            RenderMode::FixedPoint => self.render_fixed(snapshot, output),
+        }
 
-            let result = result * AMPLIFICATION;
+        self.declick.apply(output);
+        if let Some(last) = output.last() {
+            self.last_output = *last;
+        }
+    }
 
+    /// Each operator is generated for the whole block in turn, in the routing's
+    /// order, so modulators always come before the operators they modulate.
+    fn render_float(&mut self, snapshot: &PatchSnapshot, output: &mut [f32]) {
+        let buffers = &mut self.buffers;
+        let ticks = &buffers.ticks;
+        let eg_counts = &buffers.eg_counts;
+        let lfo = &buffers.lfo;
+        let outputs = &mut buffers.outputs;
+        let routing = &snapshot.routing;
+
+        outputs
+            .iter_mut()
+            .for_each(|buffer| buffer.resize(output.len(), 0.0));
+
+        snapshot.order.iter().for_each(|&i| {
+            let operator = &mut self.operators[i];
+            let definition = &snapshot.operators[i];
+            let envelope = &snapshot.envelopes[i];
+            let increment = definition.increment(self.base_frequency);
+            let tremolo = tremolo_depth(definition.amplitude_sensitivity);
+            let feedback = i == routing.feedback;
+            let feedback_level = snapshot.feedback.as_multiplier();
+            let modulation = &routing.modulation[i];
+
+            // Taken out while it's written, so the modulators can still be read
+            let mut result = std::mem::take(&mut outputs[i]);
+            let mut eg_counts = eg_counts.iter().copied();
+            result
+                .iter_mut()
+                .zip(ticks.iter().zip(lfo))
+                .enumerate()
+                .for_each(|(sample, (result, (ticks, lfo)))| {
+                    (0..*ticks)
+                        .for_each(|_| operator.envelope.tick(envelope, eg_counts.next().flatten()));
+                    operator
+                        .envelope
+                        .set_tremolo((tremolo * lfo.amplitude) as u32);
+
+                    // A full output is a 4 cycle phase offset, as in the fixed point path
+                    let mut modulation = (0..OPERATOR_COUNT)
+                        .filter(|&source| source != i && modulation[source] > 0.0)
+                        .map(|source| outputs[source][sample] * modulation[source])
+                        .sum::<f32>()
+                        * AMPLIFICATION;
+                    if feedback {
+                        modulation +=
+                            ((self.prev_feedback1 + self.prev_feedback2) / 2.0) * feedback_level;
+                    }
+
+                    *result =
+                        operator.func_with(definition, envelope, increment * lfo.pitch, modulation);
+
+                    if feedback {
+                        self.prev_feedback2 = self.prev_feedback1;
+                        self.prev_feedback1 = *result;
+                    }
+                });
             outputs[i] = result;
+        });
 
-            if algorithm.carriers[i] {
-                final_output += result;
-            }
+        output.iter_mut().enumerate().for_each(|(sample, output)| {
+            *output = (0..OPERATOR_COUNT)
+                .filter(|&i| routing.is_carrier(i))
+                .map(|i| outputs[i][sample] * routing.output[i])
+                .sum();
+        });
+    }
+
+    /// Generates the output with integer math, as the YM2612 does. The LFO,
+    /// level scaling and waveform morphing are still worked out in float.
+    fn render_fixed(&mut self, snapshot: &PatchSnapshot, output: &mut [f32]) {
+        let buffers = &mut self.buffers;
+        let ticks = &buffers.ticks;
+        let eg_counts = &buffers.eg_counts;
+        let lfo = &buffers.lfo;
+        let outputs = &mut buffers.fixed_outputs;
+        let routing = &snapshot.routing;
+
+        outputs
+            .iter_mut()
+            .for_each(|buffer| buffer.resize(output.len(), 0));
+
+        let levels = routing.output.map(fixed_level);
+
+        snapshot.order.iter().for_each(|&i| {
+            let operator = &mut self.operators[i];
+            let definition = &snapshot.operators[i];
+            let envelope = &snapshot.envelopes[i];
+            let increment = definition.fixed_increment(self.base_frequency);
+            let tremolo = tremolo_depth(definition.amplitude_sensitivity);
+            let feedback = i == routing.feedback;
+            let depths = routing.modulation[i].map(fixed_level);
+
+            let mut result = std::mem::take(&mut outputs[i]);
+            let mut eg_counts = eg_counts.iter().copied();
+            result
+                .iter_mut()
+                .zip(ticks.iter().zip(lfo))
+                .enumerate()
+                .for_each(|(sample, (result, (ticks, lfo)))| {
+                    (0..*ticks)
+                        .for_each(|_| operator.envelope.tick(envelope, eg_counts.next().flatten()));
+                    operator
+                        .envelope
+                        .set_tremolo((tremolo * lfo.amplitude) as u32);
+
+                    // Modulators are halved, so a full output is a 4 cycle phase offset
+                    let mut modulation = (0..OPERATOR_COUNT)
+                        .filter(|&source| source != i && depths[source] > 0)
+                        .map(|source| outputs[source][sample] * depths[source])
+                        .sum::<i32>()
+                        >> (FIXED_LEVEL_BITS + 1);
+                    if feedback {
+                        modulation += snapshot
+                            .feedback
+                            .apply_fixed(self.prev_fixed_feedback1 + self.prev_fixed_feedback2);
+                    }
+
+                    *result = operator.func_fixed_with(
+                        definition,
+                        envelope,
+                        lfo.fixed_vibrato(increment),
+                        modulation,
+                    );
+
+                    if feedback {
+                        self.prev_fixed_feedback2 = self.prev_fixed_feedback1;
+                        self.prev_fixed_feedback1 = *result;
+                    }
+                });
+            outputs[i] = result;
         });
 
-        final_output / AMPLIFICATION
+        output.iter_mut().enumerate().for_each(|(sample, output)| {
+            let final_output = (0..OPERATOR_COUNT)
+                .filter(|&i| levels[i] > 0)
+                .map(|i| (outputs[i][sample] * levels[i]) >> FIXED_LEVEL_BITS)
+                .sum::<i32>();
+
+            *output = final_output as f32 / FIXED_OUTPUT_SCALE;
+        });
     }
 
     //TODO: Potentially add left/right scaling here?
     //Would it be better to do each operator and combine them later?
     pub(crate) fn write_to_buffer(&mut self, data: &mut [f32], channels: u16) {
-        data.chunks_exact_mut(channels as usize)
-            .zip(self)
-            .for_each(|(frame, sample)| frame.iter_mut().for_each(|data| *data += sample))
-    }
+        let mut mix = std::mem::take(&mut self.buffers.mix);
+        mix.resize(data.len() / channels as usize, 0.0);
+        self.render(&mut mix);
 
-    /// Forcefully tick
-    pub(crate) fn force_tick(&mut self) -> f32 {
-        self.tick();
+        data.chunks_exact_mut(channels as usize)
+            .zip(mix.iter())
+            .for_each(|(frame, sample)| frame.iter_mut().for_each(|data| *data += sample));
 
-        self.func()
+        self.buffers.mix = mix;
     }
 
-    fn tick(&mut self) {
+    fn advance_clock(&mut self) {
         let amt = TARGET_SAMPLE_RATE as f32 / self.base_frequency;
 
         self.clock += 1.0;
         if self.clock > amt {
             self.clock -= amt
         };
-
-        self.operators
-            .iter_mut()
-            .for_each(|operator| operator.envelope.tick());
     }
 
     pub fn set_active(&mut self, active: bool) {
         if active != self.active {
             self.active = active;
             match active {
-                true => self
-                    .operators
-                    .iter_mut()
-                    .for_each(|operator| operator.envelope.key_on()),
+                true => self.trigger(),
                 false => self
                     .operators
                     .iter_mut()
@@ -148,24 +352,425 @@ impl PatchInstance {
         }
     }
 
+    /// Plays a new note, following the patch's trigger mode if a note is already held.
+    /// Legato notes keep the velocity of the note they follow.
+    pub fn note_on(&mut self, frequency: f32, velocity: u8) {
+        self.set_frequency(frequency);
+
+        let legato = self.definition.read().trigger_mode == TriggerMode::Legato;
+        if self.active && legato {
+            return;
+        }
+
+        self.velocity = velocity.min(VELOCITY_MAX);
+        self.active = true;
+        self.trigger();
+    }
+
+    /// Starts the envelopes' attack, and the LFO's delay.
+    fn trigger(&mut self) {
+        let definition = self.definition.read();
+        self.lfo.key_on(&definition.lfo);
+
+        if definition.trigger_mode == TriggerMode::HardRetrigger {
+            self.declick.start(self.last_output);
+            self.operators
+                .iter_mut()
+                .for_each(|operator| operator.envelope.reset());
+        }
+
+        self.operators
+            .iter_mut()
+            .for_each(|operator| operator.envelope.key_on());
+    }
+
     pub fn set_frequency(&mut self, frequency: f32) {
-        println!("Setting frequency: {}", frequency);
-        self.base_frequency = frequency
+        self.base_frequency = frequency;
+        self.operators
+            .iter_mut()
+            .for_each(|operator| operator.envelope.set_frequency(frequency));
+    }
+
+    /// Where each operator's envelope is, for the envelope graphs.
+    pub fn envelope_positions(&self) -> [Option<EnvelopePosition>; OPERATOR_COUNT] {
+        std::array::from_fn(|index| self.operators[index].envelope.position())
     }
 }
 
-impl Iterator for PatchInstance {
-    type Item = f32;
+/// Routing levels in fixed point, so a level of 1.0 leaves a value as it is.
+const FIXED_LEVEL_BITS: u32 = 8;
+
+fn fixed_level(level: f32) -> i32 {
+    (level.clamp(0.0, 1.0) * (1 << FIXED_LEVEL_BITS) as f32) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.wall_clock += self.definition.read().wall_tick_time;
+    use super::*;
+    use crate::patches::{
+        EnvelopeCurve, EnvelopeDefinition, RoutingMatrix, VELOCITY_SENSITIVITY_MAX,
+    };
+
+    /// Renders the next samples as one block.
+    fn render(patch: &mut PatchInstance, samples: usize) -> Vec<f32> {
+        let mut output = vec![0.0; samples];
+        patch.render(&mut output);
+        output
+    }
+
+    fn fixed_point_patch() -> Arc<RwLock<PatchDefinition>> {
+        let mut definition = PatchDefinition::new(TARGET_SAMPLE_RATE);
+        definition.render_mode = RenderMode::FixedPoint;
+        Arc::new(RwLock::new(definition))
+    }
+
+    #[test]
+    fn fixed_point_carrier_is_a_sine() {
+        let mut patch = PatchInstance::new(fixed_point_patch(), 440.0);
+        patch.set_active(true);
+
+        // Let the attack finish
+        render(&mut patch, 2000);
+
+        let increment = (440.0 / TARGET_SAMPLE_RATE as f32 * 4_294_967_296.0) as u32;
+        let output = render(&mut patch, 99);
+        (2001..2100u32).zip(output).for_each(|(sample, actual)| {
+            let phase = increment.wrapping_mul(sample) as f32 / 4_294_967_296.0;
+            let expected = (phase * TAU).sin();
+
+            assert!(
+                (expected - actual).abs() < 0.01,
+                "{}: {} {}",
+                sample,
+                expected,
+                actual
+            );
+        });
+    }
 
-        //TODO: Could optimize this with integer math?
-        while self.wall_clock >= TARGET_SAMPLE_TICK_TIME {
-            self.tick();
-            self.wall_clock -= TARGET_SAMPLE_TICK_TIME;
+    #[test]
+    fn block_size_does_not_change_the_output() {
+        crate::patches::init_attenuation_table();
+        crate::waveform::init_waveform_tables();
+
+        [RenderMode::Float, RenderMode::FixedPoint]
+            .into_iter()
+            .for_each(|render_mode| {
+                let definition = fixed_point_patch();
+                {
+                    let mut definition = definition.write();
+                    definition.render_mode = render_mode;
+                    definition.feedback.0 = 5;
+                    definition.algorithm.0 = 4;
+                    definition.operators.iter().for_each(|operator| {
+                        let operator = operator.read();
+                        let mut envelope = operator.envelope.write();
+                        envelope.total_level = 220;
+                        envelope.attack_rate = 40;
+                        envelope.decay_attack_rate = 60;
+                    });
+                }
+
+                let mut sample_by_sample = PatchInstance::new(definition.clone(), 330.0);
+                let mut blocks = PatchInstance::new(definition, 330.0);
+                sample_by_sample.set_active(true);
+                blocks.set_active(true);
+
+                let mut expected = vec![0.0; 4000];
+                expected
+                    .chunks_mut(1)
+                    .for_each(|sample| sample_by_sample.render(sample));
+                let mut actual = vec![0.0; 4000];
+                actual
+                    .chunks_mut(333)
+                    .for_each(|block| blocks.render(block));
+
+                expected
+                    .iter()
+                    .zip(actual.iter())
+                    .for_each(|(expected, actual)| {
+                        assert_eq!(expected.to_bits(), actual.to_bits());
+                    });
+            });
+    }
+
+    #[test]
+    fn velocity_quietens_sensitive_operators() {
+        crate::patches::init_attenuation_table();
+        crate::waveform::init_waveform_tables();
+
+        let peak = |sensitivity, velocity| {
+            let definition = fixed_point_patch();
+            definition.read().operators[3].write().velocity_sensitivity = sensitivity;
+
+            let mut patch = PatchInstance::new(definition, 440.0);
+            patch.note_on(440.0, velocity);
+            render(&mut patch, 4000)
+                .into_iter()
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+        };
+
+        let loudest = peak(VELOCITY_SENSITIVITY_MAX, VELOCITY_MAX);
+        assert_eq!(peak(0, 0), loudest);
+        assert!(peak(VELOCITY_SENSITIVITY_MAX, 0) < loudest / 100.0);
+        assert!(peak(VELOCITY_SENSITIVITY_MAX / 2, 64) < loudest);
+    }
+
+    /// A sine carrier held long enough to decay to its sustain level.
+    fn held_patch(trigger_mode: TriggerMode) -> PatchInstance {
+        crate::patches::init_attenuation_table();
+        crate::waveform::init_waveform_tables();
+
+        let definition = fixed_point_patch();
+        {
+            let mut definition = definition.write();
+            definition.trigger_mode = trigger_mode;
+            let carrier = definition.operators[3].read();
+            let mut envelope = carrier.envelope.write();
+            envelope.attack_rate = 150;
+            envelope.decay_attack_rate = 120;
+            envelope.sustain_level = 100;
         }
 
-        Some(self.func())
+        let mut patch = PatchInstance::new(definition, 440.0);
+        patch.note_on(440.0, VELOCITY_MAX);
+        render(&mut patch, 20_000);
+        patch
+    }
+
+    #[test]
+    fn hard_retrigger_restarts_without_clicking() {
+        let mut patch = held_patch(TriggerMode::HardRetrigger);
+        while patch.last_output.abs() < 0.1 {
+            render(&mut patch, 1);
+        }
+        let last = patch.last_output;
+
+        patch.note_on(440.0, VELOCITY_MAX);
+        let next = render(&mut patch, 1)[0];
+        assert!((next - last).abs() < 0.1, "{} {}", last, next);
+        assert!(patch.operators[3].envelope.level() < 0.01);
+    }
+
+    #[test]
+    fn soft_retrigger_and_legato_keep_the_level() {
+        let level = |patch: &PatchInstance| patch.operators[3].envelope.level();
+
+        // Soft retriggering attacks again, up from the sustain level
+        let mut patch = held_patch(TriggerMode::SoftRetrigger);
+        let sustain = level(&patch);
+        assert!(sustain < 0.9);
+        patch.note_on(440.0, VELOCITY_MAX);
+        render(&mut patch, 100);
+        assert!(level(&patch) > sustain);
+
+        // Legato only changes the pitch
+        let mut patch = held_patch(TriggerMode::Legato);
+        patch.note_on(660.0, VELOCITY_MAX);
+        render(&mut patch, 100);
+        assert_eq!(patch.base_frequency, 660.0);
+        assert!(level(&patch) <= sustain);
+    }
+
+    /// Every 250th sample of `fixed_point_output_matches_the_golden_buffer`
+    /// with dB curves, as the chip's integers. Recorded once, and must match on
+    /// every platform.
+    #[rustfmt::skip]
+    const FIXED_POINT_GOLDEN: [i32; 60] = [
+        0, 0, 0, 0, 2, -64, 344, 419, -766, 319, -136, -40, 160, -291, 368, 49, -453, 478,
+        -24, -439, 346, -118, -107, 177, -265, 324, 63, -434, 482, -14, -461, 372, -102,
+        -165, 199, -241, 273, 76, -414, 481, -4, -337, 250, -78, -46, 86, -64, 14, 20, -28,
+        16, 0, -8, 8, -2, 0, 2, -2, 0, 0,
+    ];
+    /// FNV-1a of every sample's bits with each of `EnvelopeCurve::ALL`, so the
+    /// whole buffer is checked.
+    const FIXED_POINT_GOLDEN_HASHES: [u64; 3] = [
+        0xf2bf_a0ec_1e12_b505,
+        0x81e5_7e9e_187c_b505,
+        0x750a_534c_7a85_9505,
+    ];
+
+    #[test]
+    fn fixed_point_output_matches_the_golden_buffer() {
+        let output = |curve| {
+            let definition = fixed_point_patch();
+            {
+                let mut definition = definition.write();
+                definition.feedback.0 = 7;
+                definition.algorithm.0 = 4;
+                definition.operators.iter().for_each(|operator| {
+                    let operator = operator.read();
+                    let mut envelope = operator.envelope.write();
+                    envelope.total_level = 200;
+                    envelope.attack_rate = 210;
+                    envelope.decay_attack_rate = 150;
+                    envelope.sustain_level = 150;
+                    envelope.release_rate = 120;
+                    envelope.attack_curve = curve;
+                    envelope.decay_curve = curve;
+                    envelope.release_curve = curve;
+                });
+            }
+
+            // Held through the attack and decay, then released
+            let mut patch = PatchInstance::new(definition, 261.6);
+            patch.set_active(true);
+            let mut output = vec![0.0; 15_000];
+            let (held, released) = output.split_at_mut(10_000);
+            held.chunks_mut(256).for_each(|block| patch.render(block));
+            patch.set_active(false);
+            released
+                .chunks_mut(256)
+                .for_each(|block| patch.render(block));
+            output
+        };
+        let hash = |output: &[f32]| {
+            output
+                .iter()
+                .fold(0xcbf2_9ce4_8422_2325u64, |hash, sample| {
+                    (hash ^ sample.to_bits() as u64).wrapping_mul(0x100_0000_01b3)
+                })
+        };
+
+        let outputs = EnvelopeCurve::ALL.map(output);
+        let samples = outputs[0]
+            .iter()
+            .step_by(250)
+            .map(|sample| (sample * FIXED_OUTPUT_SCALE) as i32)
+            .collect::<Vec<_>>();
+
+        assert_eq!(samples, FIXED_POINT_GOLDEN);
+        assert_eq!(
+            outputs.map(|output| hash(&output)),
+            FIXED_POINT_GOLDEN_HASHES
+        );
+    }
+
+    /// Times a second of a four operator patch rendered a sample at a time, and
+    /// in blocks. Run with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn blocks_render_faster_than_single_samples() {
+        crate::patches::init_attenuation_table();
+        crate::waveform::init_waveform_tables();
+
+        let time = |block_size: usize| {
+            let definition = Arc::new(RwLock::new(PatchDefinition::new(TARGET_SAMPLE_RATE)));
+            let mut patch = PatchInstance::new(definition, 440.0);
+            patch.set_active(true);
+
+            let mut output = vec![0.0; TARGET_SAMPLE_RATE as usize];
+            let start = std::time::Instant::now();
+            output
+                .chunks_mut(block_size)
+                .for_each(|block| patch.render(block));
+            start.elapsed()
+        };
+
+        let single = time(1);
+        let blocks = time(256);
+        println!(
+            "one second of audio: {:?} a sample at a time, {:?} in blocks of 256, {:.1}x faster",
+            single,
+            blocks,
+            single.as_secs_f64() / blocks.as_secs_f64()
+        );
+        assert!(blocks < single);
+    }
+
+    #[test]
+    fn routing_can_run_in_any_order() {
+        crate::patches::init_attenuation_table();
+        crate::waveform::init_waveform_tables();
+
+        // The same chain as algorithm 0, from the 4th operator to the 1st
+        let mut reversed = RoutingMatrix {
+            modulation: [[0.0; OPERATOR_COUNT]; OPERATOR_COUNT],
+            output: [1.0, 0.0, 0.0, 0.0],
+            feedback: 3,
+        };
+        (1..OPERATOR_COUNT).for_each(|target| reversed.modulation[target - 1][target] = 1.0);
+
+        [RenderMode::Float, RenderMode::FixedPoint]
+            .into_iter()
+            .for_each(|render_mode| {
+                let patch = |routing: Option<RoutingMatrix>| {
+                    let mut definition = PatchDefinition::new(TARGET_SAMPLE_RATE);
+                    definition.render_mode = render_mode;
+                    definition.feedback.0 = 6;
+                    definition.set_routing(routing).unwrap();
+                    definition.operators.iter().for_each(|operator| {
+                        let operator = operator.read();
+                        *operator.envelope.write() =
+                            EnvelopeDefinition::new(255, 255, 0, 255, 0, 255);
+                    });
+
+                    let mut patch = PatchInstance::new(Arc::new(RwLock::new(definition)), 220.0);
+                    patch.set_active(true);
+                    render(&mut patch, 2000)
+                        .into_iter()
+                        .map(f32::to_bits)
+                        .collect::<Vec<_>>()
+                };
+
+                assert_eq!(patch(Some(reversed)), patch(None));
+            });
+
+        let mut definition = PatchDefinition::new(TARGET_SAMPLE_RATE);
+        reversed.modulation[3][0] = 0.5;
+        assert!(definition.set_routing(Some(reversed)).is_err());
+        assert_eq!(definition.routing, None);
+    }
+
+    #[test]
+    fn float_and_fixed_point_agree_with_feedback_on_any_operator() {
+        crate::patches::init_attenuation_table();
+        crate::waveform::init_waveform_tables();
+
+        // The 3rd operator modulates the 4th, and has the feedback
+        let mut routing = RoutingMatrix {
+            modulation: [[0.0; OPERATOR_COUNT]; OPERATOR_COUNT],
+            output: [0.0, 0.0, 0.0, 1.0],
+            feedback: 2,
+        };
+        routing.modulation[3][2] = 0.1;
+
+        let patch = |render_mode| {
+            let mut definition = PatchDefinition::new(TARGET_SAMPLE_RATE);
+            definition.render_mode = render_mode;
+            definition.feedback.0 = 3;
+            definition.set_routing(Some(routing)).unwrap();
+            definition.operators.iter().for_each(|operator| {
+                let operator = operator.read();
+                *operator.envelope.write() = EnvelopeDefinition::new(255, 255, 0, 255, 0, 255);
+            });
+
+            let mut patch = PatchInstance::new(Arc::new(RwLock::new(definition)), 220.0);
+            patch.set_active(true);
+            render(&mut patch, 2000)
+        };
+
+        // The modulation is deep enough to take the carrier well away from a sine
+        let float = patch(RenderMode::Float);
+        let fixed = patch(RenderMode::FixedPoint);
+        assert!(float.iter().enumerate().any(|(sample, actual)| {
+            let phase = (sample + 1) as f32 * 220.0 / TARGET_SAMPLE_RATE as f32;
+            (actual - (phase * TAU).sin()).abs() > 0.5
+        }));
+        float
+            .iter()
+            .zip(fixed.iter())
+            .enumerate()
+            .for_each(|(sample, (float, fixed))| {
+                assert!(
+                    (float - fixed).abs() < 0.1,
+                    "{}: {} {}",
+                    sample,
+                    float,
+                    fixed
+                );
+            });
     }
 }
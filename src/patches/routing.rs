@@ -0,0 +1,161 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::OPERATOR_COUNT;
+
+/// Which operators modulate which, and how much of each is heard. Any
+/// operator can modulate any other, as long as no operator ends up
+/// modulating itself through the others.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RoutingMatrix {
+    /// How much each operator modulates each other, from 0.0 to 1.0, as `modulation[target][source]`
+    pub(crate) modulation: [[f32; OPERATOR_COUNT]; OPERATOR_COUNT],
+    /// How loud each operator is in the output, from 0.0 (only a modulator) to 1.0
+    pub(crate) output: [f32; OPERATOR_COUNT],
+    /// The operator which modulates itself, by the patch's feedback level
+    pub(crate) feedback: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RoutingError {
+    /// Operators which modulate each other in a loop, each modulating the next
+    Cycle(Vec<usize>),
+    InvalidFeedback(usize),
+}
+
+impl fmt::Display for RoutingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cycle(operators) => {
+                let loop_text = operators
+                    .iter()
+                    .chain(operators.first())
+                    .map(|operator| (operator + 1).to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                write!(f, "operators modulate each other in a loop: {}", loop_text)
+            }
+            Self::InvalidFeedback(operator) => write!(
+                f,
+                "feedback operator {} is out of range, there are {} operators",
+                operator + 1,
+                OPERATOR_COUNT
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RoutingError {}
+
+impl RoutingMatrix {
+    /// A routing where every operator is a carrier, with no modulation.
+    pub fn additive() -> Self {
+        Self {
+            modulation: [[0.0; OPERATOR_COUNT]; OPERATOR_COUNT],
+            output: [1.0; OPERATOR_COUNT],
+            feedback: 0,
+        }
+    }
+
+    pub fn is_carrier(&self, operator: usize) -> bool {
+        self.output[operator] > 0.0
+    }
+
+    fn modulates(&self, source: usize, target: usize) -> bool {
+        self.modulation[target][source] > 0.0
+    }
+
+    /// The order to generate the operators in, so modulators always come
+    /// before the operators they modulate. Operators keep their own order
+    /// where the routing allows it.
+    pub fn order(&self) -> Result<[usize; OPERATOR_COUNT], RoutingError> {
+        if self.feedback >= OPERATOR_COUNT {
+            return Err(RoutingError::InvalidFeedback(self.feedback));
+        }
+
+        let mut order = [0; OPERATOR_COUNT];
+        let mut done = [false; OPERATOR_COUNT];
+
+        for slot in order.iter_mut() {
+            let ready = (0..OPERATOR_COUNT).find(|&target| {
+                !done[target]
+                    && (0..OPERATOR_COUNT)
+                        .all(|source| done[source] || !self.modulates(source, target))
+            });
+
+            match ready {
+                Some(operator) => {
+                    *slot = operator;
+                    done[operator] = true;
+                }
+                None => return Err(RoutingError::Cycle(self.find_cycle(&done))),
+            }
+        }
+
+        Ok(order)
+    }
+
+    /// Every operator left is modulated by another one left, so following
+    /// modulators back from any of them must eventually repeat.
+    fn find_cycle(&self, done: &[bool; OPERATOR_COUNT]) -> Vec<usize> {
+        let mut path = Vec::new();
+        let mut operator = (0..OPERATOR_COUNT).find(|&operator| !done[operator]);
+
+        while let Some(current) = operator {
+            if let Some(start) = path.iter().position(|&visited| visited == current) {
+                let mut cycle = path.split_off(start);
+                cycle.reverse();
+                // Start from the first operator, so the same loop is always reported the same way
+                let first = (0..cycle.len())
+                    .min_by_key(|&index| cycle[index])
+                    .unwrap_or(0);
+                cycle.rotate_left(first);
+                return cycle;
+            }
+
+            path.push(current);
+            operator = (0..OPERATOR_COUNT)
+                .find(|&source| !done[source] && self.modulates(source, current));
+        }
+
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_modulators_first() {
+        let mut routing = RoutingMatrix::additive();
+        routing.modulation[0][3] = 0.5;
+        routing.modulation[3][2] = 1.0;
+        assert_eq!(routing.order(), Ok([1, 2, 3, 0]));
+
+        routing.feedback = OPERATOR_COUNT;
+        assert_eq!(routing.order(), Err(RoutingError::InvalidFeedback(4)));
+    }
+
+    #[test]
+    fn detects_cycles() {
+        let mut routing = RoutingMatrix::additive();
+        routing.modulation[1][0] = 1.0;
+        routing.modulation[2][1] = 1.0;
+        routing.modulation[0][2] = 0.25;
+        routing.modulation[3][2] = 1.0;
+
+        let error = routing.order().unwrap_err();
+        assert_eq!(error, RoutingError::Cycle(vec![0, 1, 2]));
+        assert_eq!(
+            error.to_string(),
+            "operators modulate each other in a loop: 1 -> 2 -> 3 -> 1"
+        );
+
+        // Operators can only modulate themselves through feedback
+        let mut routing = RoutingMatrix::additive();
+        routing.modulation[2][2] = 1.0;
+        assert_eq!(routing.order(), Err(RoutingError::Cycle(vec![2])));
+    }
+}
@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+
+use crate::TARGET_SAMPLE_RATE;
+
+/// How long a forced restart takes to fade out the old output, 2ms.
+pub const DECLICK_SAMPLES: u32 = TARGET_SAMPLE_RATE / 500;
+
+/// What a patch's envelopes do when a new note starts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TriggerMode {
+    /// Restart the attack from silence. The old output is faded out, so it doesn't click.
+    HardRetrigger,
+    /// Restart the attack from the current level.
+    #[default]
+    SoftRetrigger,
+    /// Only change the pitch if a note is already held, without restarting the envelopes.
+    Legato,
+}
+
+/// Smooths over a jump in the output, by fading the last output out over a few
+/// milliseconds on top of whatever plays next.
+#[derive(Clone, Debug, Default)]
+pub struct Declick {
+    offset: f32,
+    remaining: u32,
+}
+
+impl Declick {
+    /// Starts fading out from the last sample output before the jump.
+    pub fn start(&mut self, last_output: f32) {
+        // The last output already includes any fade still in progress
+        self.offset = last_output;
+        self.remaining = DECLICK_SAMPLES;
+    }
+
+    pub fn apply(&mut self, output: &mut [f32]) {
+        output
+            .iter_mut()
+            .take(self.remaining as usize)
+            .for_each(|output| {
+                *output += self.offset * self.remaining as f32 / DECLICK_SAMPLES as f32;
+                self.remaining -= 1;
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn declick_fades_to_nothing() {
+        let mut declick = Declick::default();
+        declick.start(0.5);
+
+        let mut output = vec![0.0; DECLICK_SAMPLES as usize + 10];
+        output.chunks_mut(7).for_each(|block| declick.apply(block));
+
+        assert_eq!(output[0], 0.5);
+        assert!(output.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert!(output[DECLICK_SAMPLES as usize..]
+            .iter()
+            .all(|sample| *sample == 0.0));
+    }
+}
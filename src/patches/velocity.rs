@@ -0,0 +1,44 @@
+/// The loudest velocity, as in MIDI. Notes play at this when no velocity is given.
+pub const VELOCITY_MAX: u8 = 127;
+
+/// Operator velocity sensitivity goes from 0 (ignores velocity) to 7, as on the DX7.
+pub const VELOCITY_SENSITIVITY_MAX: u8 = 7;
+
+/// Each step of sensitivity lets the softest notes be 6 dB quieter, in envelope steps.
+const STEPS_PER_SENSITIVITY: u32 = 64;
+
+/// How much quieter an operator plays a note, in envelope steps. The change
+/// is even in decibels, so it sounds even across the range of velocities.
+pub fn velocity_attenuation(velocity: u8, sensitivity: u8) -> u32 {
+    let softness = (VELOCITY_MAX - velocity.min(VELOCITY_MAX)) as u32;
+    let sensitivity = sensitivity.min(VELOCITY_SENSITIVITY_MAX) as u32;
+
+    softness * sensitivity * STEPS_PER_SENSITIVITY / VELOCITY_MAX as u32
+}
+
+/// The velocity as a linear volume, for instruments without operators.
+pub fn velocity_to_volume(velocity: u8) -> f32 {
+    velocity.min(VELOCITY_MAX) as f32 / VELOCITY_MAX as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn softer_notes_are_quieter_with_more_sensitivity() {
+        assert_eq!(
+            velocity_attenuation(VELOCITY_MAX, VELOCITY_SENSITIVITY_MAX),
+            0
+        );
+        assert_eq!(velocity_attenuation(0, 0), 0);
+        assert_eq!(velocity_attenuation(0, 1), STEPS_PER_SENSITIVITY);
+        assert_eq!(velocity_attenuation(0, VELOCITY_SENSITIVITY_MAX), 448);
+
+        let attenuations = (0..=VELOCITY_MAX).map(|velocity| velocity_attenuation(velocity, 4));
+        assert!(attenuations
+            .collect::<Vec<_>>()
+            .windows(2)
+            .all(|pair| pair[1] <= pair[0]));
+    }
+}
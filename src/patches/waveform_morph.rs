@@ -0,0 +1,95 @@
+use std::f32::consts::TAU;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Waveform;
+
+/// Crossfades an operator from its own waveform to a second one.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WaveformMorph {
+    pub(crate) waveform: Waveform,
+    /// How far towards the second waveform to go, 0.0..=1.0
+    pub(crate) amount: f32,
+    pub(crate) source: MorphSource,
+}
+
+/// What moves the morph position between the two waveforms.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum MorphSource {
+    /// Always at the morph amount.
+    #[default]
+    Fixed,
+    /// Follows the operator's own envelope, reaching the amount at full level.
+    Envelope,
+    /// Sweeps between the two waveforms at the given rate, in hz.
+    Lfo(f32),
+}
+
+impl Default for WaveformMorph {
+    fn default() -> Self {
+        Self {
+            waveform: Waveform::Triangle,
+            amount: 0.5,
+            source: MorphSource::default(),
+        }
+    }
+}
+
+impl WaveformMorph {
+    /// Where between the two waveforms to be, from 0.0 (first) to 1.0 (second).
+    /// Envelope level and lfo phase are both in the range 0.0..=1.0
+    pub(crate) fn position(&self, envelope_level: f32, lfo_phase: f32) -> f32 {
+        let position = match self.source {
+            MorphSource::Fixed => 1.0,
+            MorphSource::Envelope => envelope_level,
+            MorphSource::Lfo(_) => 0.5 - (lfo_phase * TAU).cos() * 0.5,
+        };
+
+        position * self.amount
+    }
+
+    pub(crate) fn lfo_rate(&self) -> f32 {
+        match self.source {
+            MorphSource::Lfo(rate) => rate,
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn morph(source: MorphSource, amount: f32) -> WaveformMorph {
+        WaveformMorph {
+            source,
+            amount,
+            ..WaveformMorph::default()
+        }
+    }
+
+    #[test]
+    fn amount_limits_how_far_to_morph() {
+        let fixed = morph(MorphSource::Fixed, 1.0);
+        assert_eq!(fixed.position(0.0, 0.0), 1.0);
+        assert_eq!(morph(MorphSource::Fixed, 0.0).position(1.0, 0.5), 0.0);
+        assert_eq!(morph(MorphSource::Fixed, 0.25).position(0.0, 0.75), 0.25);
+        assert_eq!(fixed.lfo_rate(), 0.0);
+    }
+
+    #[test]
+    fn envelope_and_lfo_move_the_position() {
+        let envelope = morph(MorphSource::Envelope, 0.5);
+        assert_eq!(envelope.position(0.0, 0.3), 0.0);
+        assert_eq!(envelope.position(0.5, 0.3), 0.25);
+        assert_eq!(envelope.position(1.0, 0.3), 0.5);
+
+        // The LFO starts on the first waveform, and reaches the amount half way through its cycle
+        let lfo = morph(MorphSource::Lfo(2.0), 0.8);
+        assert_eq!(lfo.lfo_rate(), 2.0);
+        assert_eq!(lfo.position(0.7, 0.0), 0.0);
+        assert!((lfo.position(0.7, 0.25) - 0.4).abs() < 1e-6);
+        assert!((lfo.position(0.7, 0.5) - 0.8).abs() < 1e-6);
+        assert!((lfo.position(0.7, 0.75) - 0.4).abs() < 1e-6);
+    }
+}
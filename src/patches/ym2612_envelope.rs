@@ -0,0 +1,223 @@
+//! Tables and timing of the YM2612's envelope generator, as measured from the
+//! hardware and documented by MAME and Genesis Plus GX.
+
+use crate::TARGET_SAMPLE_RATE;
+
+/// The YM2612's master clock on an NTSC Mega Drive, in hz.
+const MASTER_CLOCK: u64 = 7_670_453;
+
+/// The chip makes one sample every 144 master clocks, and clocks the
+/// envelope generator every third sample.
+const EG_CLOCK_DIVIDER: u64 = 144 * 3;
+
+const EG_TIMER_BITS: u32 = 16;
+const EG_TIMER_OVERFLOW: u32 = 1 << EG_TIMER_BITS;
+
+/// How far the envelope timer moves each tick, as a fraction of an envelope clock.
+const EG_TIMER_STEP: u32 =
+    ((MASTER_CLOCK << EG_TIMER_BITS) / (EG_CLOCK_DIVIDER * TARGET_SAMPLE_RATE as u64)) as u32;
+
+/// The counter is 12 bits, and skips 0 when it wraps.
+const EG_COUNTER_MAX: u16 = 4095;
+
+/// The highest effective rate, after doubling and adding key scaling.
+pub const EG_RATE_MAX: u8 = 63;
+
+/// Effective rates this high skip the attack entirely.
+pub const EG_INSTANT_ATTACK_RATE: u8 = 62;
+
+/// Largest 10 bit attenuation, where the envelope is silent.
+pub const EG_ATTENUATION_MAX: i32 = 0x3FF;
+
+/// Increments over 8 steps of the counter. Each rate uses one of these rows.
+const EG_INCREMENTS: [[u8; 8]; 18] = [
+    [0, 1, 0, 1, 0, 1, 0, 1], // rates 00..11 0
+    [0, 1, 0, 1, 1, 1, 0, 1], // rates 00..11 1
+    [0, 1, 1, 1, 0, 1, 1, 1], // rates 00..11 2
+    [0, 1, 1, 1, 1, 1, 1, 1], // rates 00..11 3
+    [1, 1, 1, 1, 1, 1, 1, 1], // rate 12 0
+    [1, 1, 1, 2, 1, 1, 1, 2], // rate 12 1
+    [1, 2, 1, 2, 1, 2, 1, 2], // rate 12 2
+    [1, 2, 2, 2, 1, 2, 2, 2], // rate 12 3
+    [2, 2, 2, 2, 2, 2, 2, 2], // rate 13 0
+    [2, 2, 2, 4, 2, 2, 2, 4], // rate 13 1
+    [2, 4, 2, 4, 2, 4, 2, 4], // rate 13 2
+    [2, 4, 4, 4, 2, 4, 4, 4], // rate 13 3
+    [4, 4, 4, 4, 4, 4, 4, 4], // rate 14 0
+    [4, 4, 4, 8, 4, 4, 4, 8], // rate 14 1
+    [4, 8, 4, 8, 4, 8, 4, 8], // rate 14 2
+    [4, 8, 8, 8, 4, 8, 8, 8], // rate 14 3
+    [8, 8, 8, 8, 8, 8, 8, 8], // rate 15
+    [0, 0, 0, 0, 0, 0, 0, 0], // never moves
+];
+
+/// Which row of increments each effective rate uses.
+const fn rate_select(rate: u8) -> usize {
+    match rate {
+        // The lowest rates were measured on hardware to differ from the pattern
+        0 | 1 => 17,
+        2 | 3 => 0,
+        4 | 5 => 0,
+        6 | 7 => 2,
+        8..=47 => (rate % 4) as usize,
+        48..=59 => (rate - 44) as usize,
+        _ => 16,
+    }
+}
+
+/// Lower rates only move on every 2^shift'th count.
+const fn rate_shift(rate: u8) -> u32 {
+    match rate {
+        0..=47 => 11 - rate as u32 / 4,
+        _ => 0,
+    }
+}
+
+/// Combines a 5 bit rate with the key scaling offset. Rate 0 never moves, at any pitch.
+pub fn effective_rate(rate: u8, key_scale_offset: u8) -> u8 {
+    if rate == 0 {
+        return 0;
+    }
+
+    (rate * 2 + key_scale_offset).min(EG_RATE_MAX)
+}
+
+/// Release only has 4 bits, which are placed above a set low bit.
+pub fn effective_release_rate(rate: u8, key_scale_offset: u8) -> u8 {
+    effective_rate(rate * 2 + 1, key_scale_offset)
+}
+
+/// The 4 bit sustain level as a 10 bit attenuation. The highest level is as quiet as possible.
+pub fn sustain_attenuation(level: u8) -> i32 {
+    match level {
+        15 => 31 << 5,
+        level => (level as i32) << 5,
+    }
+}
+
+/// How much an envelope at this rate moves on this count of the counter, if at all.
+pub fn eg_increment(rate: u8, counter: u16) -> Option<i32> {
+    let shift = rate_shift(rate);
+    if counter & ((1 << shift) - 1) != 0 {
+        return None;
+    }
+
+    let step = ((counter >> shift) & 7) as usize;
+    Some(EG_INCREMENTS[rate_select(rate)][step] as i32)
+}
+
+/// The chip's envelope counter, which every envelope reads to decide when to move.
+#[derive(Clone, Debug)]
+pub struct EgCounter {
+    timer: u32,
+    counter: u16,
+}
+
+impl Default for EgCounter {
+    fn default() -> Self {
+        Self {
+            timer: 0,
+            counter: 1,
+        }
+    }
+}
+
+impl EgCounter {
+    /// Advances by one tick at TARGET_SAMPLE_RATE, returning the new count if
+    /// the envelopes were clocked. The envelope clock is slower than the tick
+    /// rate, so there is at most one each tick.
+    pub fn tick(&mut self) -> Option<u16> {
+        self.timer += EG_TIMER_STEP;
+
+        let clocked = self.timer >= EG_TIMER_OVERFLOW;
+        self.timer &= EG_TIMER_OVERFLOW - 1;
+        clocked.then(|| self.next_count())
+    }
+
+    /// Moves to the next count, once for each envelope clock.
+    fn next_count(&mut self) -> u16 {
+        self.counter = match self.counter {
+            EG_COUNTER_MAX => 1,
+            counter => counter + 1,
+        };
+        self.counter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Envelope clocks to move across the full range at a constant rate.
+    fn clocks_to_decay(rate: u8) -> u32 {
+        let mut volume = 0;
+        let mut counter = EgCounter::default();
+        let mut clocks = 0;
+
+        while volume < EG_ATTENUATION_MAX {
+            clocks += 1;
+            volume += eg_increment(rate, counter.next_count()).unwrap_or(0);
+        }
+        clocks
+    }
+
+    #[test]
+    fn increments_match_the_hardware_tables() {
+        // Rate 12 0 moves by one on every clock
+        assert!((1..64).all(|counter| eg_increment(48, counter) == Some(1)));
+        // Rate 15 moves by 8 on every clock
+        assert!((1..64).all(|counter| eg_increment(63, counter) == Some(8)));
+        // Rates 0 and 1 never move
+        assert!((0..4096).all(|counter| eg_increment(1, counter).unwrap_or(0) == 0));
+
+        // Rate 11 1 follows its pattern on every clock, rate 10 1 on every other one
+        let pattern = |rate| (0..16).map(move |counter| eg_increment(rate, counter));
+        assert!(pattern(45).eq([0, 1, 0, 1, 1, 1, 0, 1].repeat(2).into_iter().map(Some)));
+        assert!(pattern(41)
+            .step_by(2)
+            .eq([0, 1, 0, 1, 1, 1, 0, 1].into_iter().map(Some)));
+        assert!(pattern(41)
+            .skip(1)
+            .step_by(2)
+            .all(|increment| increment.is_none()));
+    }
+
+    #[test]
+    fn each_rate_step_halves_the_time() {
+        assert_eq!(clocks_to_decay(48), 1023);
+        assert_eq!(clocks_to_decay(60), 128);
+
+        [44, 40, 20].into_iter().for_each(|rate| {
+            let ratio = clocks_to_decay(rate) as f32 / clocks_to_decay(rate + 4) as f32;
+            assert!((ratio - 2.0).abs() < 0.01, "{}: {}", rate, ratio);
+        });
+
+        // The 4 steps between each doubling get faster in turn
+        let times = [44, 45, 46, 47, 48].map(clocks_to_decay);
+        assert!(times.windows(2).all(|pair| pair[1] < pair[0]));
+    }
+
+    #[test]
+    fn counter_runs_at_the_chip_rate() {
+        let mut counter = EgCounter::default();
+        let clocks = (0..TARGET_SAMPLE_RATE)
+            .filter_map(|_| counter.tick())
+            .count() as u32;
+
+        // 7.67 MHz / 144 / 3
+        assert!((17_755..=17_757).contains(&clocks), "{}", clocks);
+    }
+
+    #[test]
+    fn maps_registers_to_rates() {
+        assert_eq!(effective_rate(0, 31), 0);
+        assert_eq!(effective_rate(31, 0), 62);
+        assert_eq!(effective_rate(31, 31), EG_RATE_MAX);
+        assert_eq!(effective_release_rate(15, 0), 62);
+        assert_eq!(effective_release_rate(0, 0), 2);
+
+        assert_eq!(sustain_attenuation(0), 0);
+        assert_eq!(sustain_attenuation(14), 448);
+        assert_eq!(sustain_attenuation(15), 992);
+    }
+}
@@ -0,0 +1,16 @@
+mod sample_bank;
+mod sample_definition;
+mod sample_instance;
+mod sample_mixer;
+mod wav;
+
+pub use sample_bank::*;
+pub use sample_definition::*;
+pub use sample_instance::*;
+pub use sample_mixer::*;
+pub use wav::*;
+
+/// Sample channels for music, which follow the sequencer's FM channels
+pub const MUSIC_SAMPLE_CHANNEL_COUNT: usize = 8;
+/// Sample channels for game sounds, played through a `SampleMixer`
+pub const GAME_SAMPLE_CHANNEL_COUNT: usize = 8;
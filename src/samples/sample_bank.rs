@@ -0,0 +1,151 @@
+use std::{fmt, path::Path, sync::Arc};
+
+use hashbrown::HashMap;
+use parking_lot::RwLock;
+
+use super::{load_wav, SampleDefinition, WavError};
+
+/// The most samples which can be loaded at once.
+pub const MAX_SAMPLES: usize = 512;
+
+#[derive(Debug)]
+pub enum SampleBankError {
+    Wav(WavError),
+    Full,
+    DuplicateName(String),
+}
+
+impl fmt::Display for SampleBankError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Wav(error) => error.fmt(f),
+            Self::Full => write!(
+                f,
+                "sample bank is full, at most {} samples can be loaded",
+                MAX_SAMPLES
+            ),
+            Self::DuplicateName(name) => write!(f, "a sample named \"{}\" is already loaded", name),
+        }
+    }
+}
+
+impl std::error::Error for SampleBankError {}
+
+impl From<WavError> for SampleBankError {
+    fn from(error: WavError) -> Self {
+        Self::Wav(error)
+    }
+}
+
+/// Holds every loaded sample, which can be looked up by index or by name.
+#[derive(Debug, Default)]
+pub struct SampleBank {
+    samples: Vec<Arc<RwLock<SampleDefinition>>>,
+    names: Vec<String>,
+    indices: HashMap<String, usize>,
+}
+
+impl SampleBank {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a sample to the end of the bank, returning its index.
+    pub fn insert(
+        &mut self,
+        name: impl Into<String>,
+        sample: SampleDefinition,
+    ) -> Result<usize, SampleBankError> {
+        let name = name.into();
+
+        if self.samples.len() >= MAX_SAMPLES {
+            return Err(SampleBankError::Full);
+        }
+        if self.indices.contains_key(&name) {
+            return Err(SampleBankError::DuplicateName(name));
+        }
+
+        let index = self.samples.len();
+        self.samples.push(Arc::new(RwLock::new(sample)));
+        self.indices.insert(name.clone(), index);
+        self.names.push(name);
+
+        Ok(index)
+    }
+
+    /// Loads a wav file into the bank, returning its index.
+    pub fn load_wav(
+        &mut self,
+        name: impl Into<String>,
+        path: impl AsRef<Path>,
+    ) -> Result<usize, SampleBankError> {
+        let name = name.into();
+
+        // Check before reading, so a full bank doesn't load the whole file
+        if self.samples.len() >= MAX_SAMPLES {
+            return Err(SampleBankError::Full);
+        }
+        if self.indices.contains_key(&name) {
+            return Err(SampleBankError::DuplicateName(name));
+        }
+
+        let sample = load_wav(path)?;
+        self.insert(name, sample)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Arc<RwLock<SampleDefinition>>> {
+        self.samples.get(index)
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.indices.get(name).copied()
+    }
+
+    /// Iterates over the index, name and definition of every sample.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &str, &Arc<RwLock<SampleDefinition>>)> {
+        self.names
+            .iter()
+            .zip(self.samples.iter())
+            .enumerate()
+            .map(|(index, (name, sample))| (index, name.as_str(), sample))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::samples::SampleChannels;
+
+    fn sample() -> SampleDefinition {
+        SampleDefinition::new(Box::new([0.0]), SampleChannels::Mono, 48_000)
+    }
+
+    #[test]
+    fn looks_up_samples_by_index_and_name() {
+        let mut bank = SampleBank::new();
+        assert_eq!(bank.insert("kick", sample()).unwrap(), 0);
+        assert_eq!(bank.insert("snare", sample()).unwrap(), 1);
+
+        assert_eq!(bank.index_of("snare"), Some(1));
+        assert_eq!(bank.iter().next().map(|(_, name, _)| name), Some("kick"));
+        assert!(bank.index_of("hat").is_none());
+        assert!(bank.get(2).is_none());
+        assert!(matches!(
+            bank.insert("kick", sample()),
+            Err(SampleBankError::DuplicateName(_))
+        ));
+    }
+
+    #[test]
+    fn enforces_the_sample_limit() {
+        let mut bank = SampleBank::new();
+        (0..MAX_SAMPLES).for_each(|index| {
+            bank.insert(index.to_string(), sample()).unwrap();
+        });
+
+        assert!(matches!(
+            bank.insert("one too many", sample()),
+            Err(SampleBankError::Full)
+        ));
+    }
+}
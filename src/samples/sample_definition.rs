@@ -0,0 +1,89 @@
+use crate::{notes, TARGET_SAMPLE_RATE};
+
+/// The note which plays a sample back at its original speed. C4
+pub const DEFAULT_ROOT_NOTE: usize = 36;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleChannels {
+    Mono,
+    Stereo,
+}
+
+impl SampleChannels {
+    pub fn count(self) -> usize {
+        match self {
+            Self::Mono => 1,
+            Self::Stereo => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoopMode {
+    /// Plays through once, and stops at the end.
+    OneShot,
+    /// Repeats the frames from start up to (but not including) end while held.
+    Loop { start: usize, end: usize },
+}
+
+/// PCM data, stored as interleaved frames in the range -1.0..=1.0
+#[derive(Clone, Debug)]
+pub struct SampleDefinition {
+    pub(crate) data: Box<[f32]>,
+    pub(crate) channels: SampleChannels,
+    pub(crate) sample_rate: u32,
+    pub(crate) root_note: usize,
+    pub(crate) loop_mode: LoopMode,
+}
+
+impl SampleDefinition {
+    pub fn new(data: Box<[f32]>, channels: SampleChannels, sample_rate: u32) -> Self {
+        assert_eq!(data.len() % channels.count(), 0);
+
+        Self {
+            data,
+            channels,
+            sample_rate,
+            root_note: DEFAULT_ROOT_NOTE,
+            loop_mode: LoopMode::OneShot,
+        }
+    }
+
+    /// How many frames (one value per channel) the sample has.
+    pub fn frame_count(&self) -> usize {
+        self.data.len() / self.channels.count()
+    }
+
+    /// Sets the note which plays the sample at its original speed.
+    pub fn set_root_note(&mut self, root_note: usize) {
+        self.root_note = root_note.min(notes::TOTAL_NOTES - 1)
+    }
+
+    /// Loop points outside of the sample are moved inside it, and a loop is always at least one frame.
+    pub fn set_loop_mode(&mut self, loop_mode: LoopMode) {
+        self.loop_mode = match loop_mode {
+            LoopMode::Loop { start, end } if self.frame_count() > 0 => {
+                let start = start.min(self.frame_count() - 1);
+                let end = end.clamp(start + 1, self.frame_count());
+                LoopMode::Loop { start, end }
+            }
+            _ => LoopMode::OneShot,
+        }
+    }
+
+    /// Returns the left and right values of a frame. Mono samples return the same value twice.
+    pub(crate) fn frame(&self, index: usize) -> (f32, f32) {
+        match self.channels {
+            SampleChannels::Mono => {
+                let value = self.data[index];
+                (value, value)
+            }
+            SampleChannels::Stereo => (self.data[index * 2], self.data[index * 2 + 1]),
+        }
+    }
+
+    /// How many frames to advance each output sample at the root note.
+    pub(crate) fn root_step(&self) -> f64 {
+        self.sample_rate as f64 / TARGET_SAMPLE_RATE as f64
+    }
+}
@@ -0,0 +1,212 @@
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use super::{LoopMode, SampleDefinition};
+use crate::notes;
+
+pub struct SampleInstance {
+    pub(crate) definition: Arc<RwLock<SampleDefinition>>,
+    pub(crate) position: f64,
+    pub(crate) frequency: f32,
+    pub(crate) volume: f32,
+    pub(crate) active: bool,
+    pub(crate) playing: bool,
+}
+
+impl SampleInstance {
+    /// Starts at the sample's root note, so it plays at its original speed until given a note.
+    pub fn new(definition: Arc<RwLock<SampleDefinition>>) -> Self {
+        let frequency = notes::index_to_frequency(definition.read().root_note);
+
+        Self {
+            definition,
+            position: 0.0,
+            frequency,
+            volume: 1.0,
+            active: false,
+            playing: false,
+        }
+    }
+
+    /// Sets the playback pitch. Playing the sample's root note frequency
+    /// plays it back at its original speed.
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume
+    }
+
+    /// Pressing restarts the sample from the beginning. Releasing lets
+    /// a looped sample play out past its loop end, and has no effect on
+    /// one shot samples.
+    pub fn set_active(&mut self, active: bool) {
+        if active {
+            self.position = 0.0;
+            self.playing = true;
+        }
+        self.active = active;
+    }
+
+    /// Stops the sample immediately.
+    pub fn stop(&mut self) {
+        self.active = false;
+        self.playing = false;
+    }
+
+    /// Whether the sample is still sounding, even if it has been released.
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Generates the next left and right output values, using an already read
+    /// definition and root note frequency.
+    fn next_frame_with(
+        &mut self,
+        definition: &SampleDefinition,
+        root_frequency: f32,
+    ) -> (f32, f32) {
+        if !self.playing {
+            return (0.0, 0.0);
+        }
+
+        let frame_count = definition.frame_count();
+
+        let index = self.position as usize;
+        if index >= frame_count {
+            self.playing = false;
+            return (0.0, 0.0);
+        }
+
+        // Linearly interpolate towards the next frame
+        let next_index = match definition.loop_mode {
+            LoopMode::Loop { start, end } if self.active && index + 1 == end => start,
+            _ => (index + 1).min(frame_count - 1),
+        };
+        let fraction = (self.position - index as f64) as f32;
+        let (first_left, first_right) = definition.frame(index);
+        let (second_left, second_right) = definition.frame(next_index);
+        let left = first_left + (second_left - first_left) * fraction;
+        let right = first_right + (second_right - first_right) * fraction;
+
+        // Unmapped root notes have no pitch, so play those at the original speed
+        let pitch = if root_frequency > 0.0 {
+            self.frequency / root_frequency
+        } else {
+            1.0
+        };
+        self.position += definition.root_step() * pitch as f64;
+
+        if let LoopMode::Loop { start, end } = definition.loop_mode {
+            if self.active && self.position >= end as f64 {
+                let length = (end - start) as f64;
+                self.position = start as f64 + (self.position - end as f64) % length;
+            }
+        }
+
+        (left * self.volume, right * self.volume)
+    }
+
+    //TODO: Potentially add left/right scaling here?
+    pub(crate) fn write_to_buffer(&mut self, data: &mut [f32], channels: u16) {
+        let definition = self.definition.clone();
+        let definition = definition.read();
+        let root_frequency = notes::index_to_frequency(definition.root_note);
+
+        data.chunks_exact_mut(channels as usize).for_each(|frame| {
+            let (left, right) = self.next_frame_with(&definition, root_frequency);
+            match frame {
+                [mono] => *mono += (left + right) / 2.0,
+                [first, second, ..] => {
+                    *first += left;
+                    *second += right;
+                }
+                [] => (),
+            }
+        })
+    }
+
+    /// Fills the output with samples mixed down to mono, for the sequencer.
+    pub fn render(&mut self, output: &mut [f32]) {
+        let definition = self.definition.clone();
+        let definition = definition.read();
+        let root_frequency = notes::index_to_frequency(definition.root_note);
+
+        output.iter_mut().for_each(|output| {
+            let (left, right) = self.next_frame_with(&definition, root_frequency);
+            *output = (left + right) / 2.0;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{samples::SampleChannels, TARGET_SAMPLE_RATE};
+
+    /// A mono sample whose frames count up from zero.
+    fn ramp(frames: usize) -> Arc<RwLock<SampleDefinition>> {
+        let data = (0..frames).map(|frame| frame as f32).collect::<Vec<_>>();
+        Arc::new(RwLock::new(SampleDefinition::new(
+            data.into_boxed_slice(),
+            SampleChannels::Mono,
+            TARGET_SAMPLE_RATE,
+        )))
+    }
+
+    fn play(sample: &mut SampleInstance, frames: usize) -> Vec<f32> {
+        let mut output = vec![0.0; frames];
+        sample.render(&mut output);
+        output
+    }
+
+    #[test]
+    fn new_samples_play_at_their_original_speed() {
+        notes::generate();
+
+        let mut sample = SampleInstance::new(ramp(4));
+        assert!(!sample.is_playing());
+        assert_eq!(play(&mut sample, 2), vec![0.0; 2]);
+
+        sample.set_active(true);
+        assert_eq!(play(&mut sample, 4), vec![0.0, 1.0, 2.0, 3.0]);
+
+        // One shot samples stop at the end, even while held
+        assert_eq!(play(&mut sample, 2), vec![0.0; 2]);
+        assert!(!sample.is_playing());
+
+        // An octave up skips every other frame
+        let root_note = sample.definition.read().root_note;
+        sample.set_frequency(notes::index_to_frequency(root_note + 12));
+        sample.set_active(true);
+        let output = play(&mut sample, 2);
+        assert!((output[1] - 2.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn loops_while_held_then_plays_out() {
+        notes::generate();
+
+        let definition = ramp(6);
+        definition
+            .write()
+            .set_loop_mode(LoopMode::Loop { start: 2, end: 4 });
+
+        let mut sample = SampleInstance::new(definition);
+        sample.set_active(true);
+        assert_eq!(
+            play(&mut sample, 8),
+            vec![0.0, 1.0, 2.0, 3.0, 2.0, 3.0, 2.0, 3.0]
+        );
+
+        sample.set_active(false);
+        assert_eq!(play(&mut sample, 5), vec![2.0, 3.0, 4.0, 5.0, 0.0]);
+        assert!(!sample.is_playing());
+
+        sample.set_active(true);
+        sample.stop();
+        assert_eq!(play(&mut sample, 2), vec![0.0; 2]);
+    }
+}
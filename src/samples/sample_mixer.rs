@@ -0,0 +1,181 @@
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use super::{SampleDefinition, SampleInstance, GAME_SAMPLE_CHANNEL_COUNT};
+
+#[derive(Clone)]
+pub struct SampleMixerHandle {
+    pub mixer: Arc<RwLock<SampleMixer>>,
+}
+
+impl SampleMixerHandle {
+    pub fn new(mixer: SampleMixer) -> Self {
+        Self {
+            mixer: Arc::new(RwLock::new(mixer)),
+        }
+    }
+
+    /// Plays a sample through once at its original speed, on the next free channel.
+    /// Looped samples play their loop once.
+    pub fn play(&self, definition: Arc<RwLock<SampleDefinition>>) -> usize {
+        let mut mixer = self.mixer.write();
+        let channel = mixer.play(definition, None, 1.0);
+        mixer.release(channel);
+        channel
+    }
+
+    pub fn stop_all(&self) {
+        self.mixer.write().stop_all()
+    }
+
+    pub fn write_to_buffer(&mut self, data: &mut [f32], channels: u16) {
+        let mut lock = self.mixer.write();
+        lock.write_to_buffer(data, channels)
+    }
+}
+
+/// Plays samples on a fixed number of channels, such as the game sound
+/// channels. Samples are mixed in stereo.
+pub struct SampleMixer {
+    channels: Vec<Option<SampleInstance>>,
+    /// The channel to replace next when every channel is playing
+    next_stolen: usize,
+}
+
+impl Default for SampleMixer {
+    fn default() -> Self {
+        Self::new(GAME_SAMPLE_CHANNEL_COUNT)
+    }
+}
+
+impl SampleMixer {
+    pub fn new(channel_count: usize) -> Self {
+        assert!(channel_count > 0);
+
+        Self {
+            channels: (0..channel_count).map(|_| None).collect(),
+            next_stolen: 0,
+        }
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// How many channels are still sounding.
+    pub fn playing_count(&self) -> usize {
+        self.channels
+            .iter()
+            .flatten()
+            .filter(|sample| sample.is_playing())
+            .count()
+    }
+
+    /// Starts a sample on the first free channel, returning it. When every
+    /// channel is playing, they take turns being replaced. Without a frequency
+    /// the sample plays at its original speed.
+    pub fn play(
+        &mut self,
+        definition: Arc<RwLock<SampleDefinition>>,
+        frequency: Option<f32>,
+        volume: f32,
+    ) -> usize {
+        let channel = match self
+            .channels
+            .iter()
+            .position(|sample| !sample.as_ref().is_some_and(SampleInstance::is_playing))
+        {
+            Some(channel) => channel,
+            None => {
+                let channel = self.next_stolen;
+                self.next_stolen = (channel + 1) % self.channels.len();
+                channel
+            }
+        };
+
+        self.play_on(channel, definition, frequency, volume);
+        channel
+    }
+
+    /// Starts a sample on a channel, replacing whatever it was playing.
+    pub fn play_on(
+        &mut self,
+        channel: usize,
+        definition: Arc<RwLock<SampleDefinition>>,
+        frequency: Option<f32>,
+        volume: f32,
+    ) {
+        let mut sample = SampleInstance::new(definition);
+        if let Some(frequency) = frequency {
+            sample.set_frequency(frequency);
+        }
+        sample.set_volume(volume);
+        sample.set_active(true);
+
+        self.channels[channel] = Some(sample);
+    }
+
+    /// Lets a looped sample play out past its loop.
+    pub fn release(&mut self, channel: usize) {
+        if let Some(sample) = &mut self.channels[channel] {
+            sample.set_active(false);
+        }
+    }
+
+    pub fn stop(&mut self, channel: usize) {
+        if let Some(sample) = &mut self.channels[channel] {
+            sample.stop();
+        }
+    }
+
+    pub fn stop_all(&mut self) {
+        (0..self.channels.len()).for_each(|channel| self.stop(channel));
+    }
+
+    pub(crate) fn write_to_buffer(&mut self, data: &mut [f32], channels: u16) {
+        self.channels
+            .iter_mut()
+            .flatten()
+            .filter(|sample| sample.is_playing())
+            .for_each(|sample| sample.write_to_buffer(data, channels));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{notes, samples::SampleChannels, TARGET_SAMPLE_RATE};
+
+    fn sample(frames: usize) -> Arc<RwLock<SampleDefinition>> {
+        Arc::new(RwLock::new(SampleDefinition::new(
+            vec![0.5; frames].into_boxed_slice(),
+            SampleChannels::Mono,
+            TARGET_SAMPLE_RATE,
+        )))
+    }
+
+    #[test]
+    fn plays_on_free_channels_then_takes_turns_replacing() {
+        notes::generate();
+
+        let mut mixer = SampleMixer::new(2);
+        assert_eq!(mixer.play(sample(100), None, 1.0), 0);
+        assert_eq!(mixer.play(sample(4), None, 1.0), 1);
+        assert_eq!(mixer.play(sample(100), None, 1.0), 0);
+        assert_eq!(mixer.play(sample(100), None, 1.0), 1);
+        assert_eq!(mixer.playing_count(), 2);
+
+        // Both channels are mixed into each output channel
+        let mut data = vec![0.0; 8];
+        mixer.write_to_buffer(&mut data, 2);
+        assert_eq!(data, vec![1.0; 8]);
+
+        mixer.stop(0);
+        assert_eq!(mixer.playing_count(), 1);
+        assert_eq!(mixer.play(sample(4), None, 0.5), 0);
+
+        mixer.stop_all();
+        assert_eq!(mixer.playing_count(), 0);
+    }
+}
@@ -0,0 +1,460 @@
+use std::{fmt, fs, io, path::Path};
+
+use super::{LoopMode, SampleChannels, SampleDefinition};
+use crate::{notes::midi_to_index, TARGET_SAMPLE_RATE};
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IEEE_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Chunks which change how the sample data is laid out, which we can't read.
+const UNSUPPORTED_CHUNKS: [&[u8; 4]; 2] = [b"wavl", b"slnt"];
+
+#[derive(Debug)]
+pub enum WavError {
+    Io(io::Error),
+    NotRiff,
+    NotWave,
+    MissingChunk(&'static str),
+    UnsupportedChunk(String),
+    UnsupportedFormat(u16),
+    UnsupportedBitDepth { format: u16, bits: u16 },
+    UnsupportedChannels(u16),
+    Malformed(&'static str),
+}
+
+impl fmt::Display for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "wav file error: {}", error),
+            Self::NotRiff => write!(f, "not a RIFF file"),
+            Self::NotWave => write!(f, "RIFF file is not a WAVE file"),
+            Self::MissingChunk(id) => write!(f, "wav file has no \"{}\" chunk", id),
+            Self::UnsupportedChunk(id) => write!(f, "unsupported wav chunk \"{}\"", id),
+            Self::UnsupportedFormat(format) => {
+                write!(
+                    f,
+                    "unsupported wav format {:#06x}, only PCM and float are supported",
+                    format
+                )
+            }
+            Self::UnsupportedBitDepth { format, bits } => {
+                write!(
+                    f,
+                    "unsupported {}-bit depth for wav format {:#06x}",
+                    bits, format
+                )
+            }
+            Self::UnsupportedChannels(channels) => {
+                write!(
+                    f,
+                    "unsupported channel count {}, only mono and stereo are supported",
+                    channels
+                )
+            }
+            Self::Malformed(reason) => write!(f, "malformed wav file: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for WavError {}
+
+impl From<io::Error> for WavError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+struct WavFormat {
+    format: u16,
+    channels: SampleChannels,
+    sample_rate: u32,
+    block_align: usize,
+    bits: u16,
+}
+
+/// Loads a PCM or float wav file, resampled to the engine's sample rate.
+pub fn load_wav(path: impl AsRef<Path>) -> Result<SampleDefinition, WavError> {
+    parse_wav(&fs::read(path)?)
+}
+
+/// Parses the bytes of a wav file, resampled to the engine's sample rate.
+/// Supports 8, 16, 24 and 32-bit integer and 32-bit float data, in mono or stereo.
+pub fn parse_wav(bytes: &[u8]) -> Result<SampleDefinition, WavError> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" {
+        return Err(WavError::NotRiff);
+    }
+    if &bytes[8..12] != b"WAVE" {
+        return Err(WavError::NotWave);
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut sampler = None;
+    let mut offset = 12;
+
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = read_u32(bytes, offset + 4) as usize;
+        let start = offset + 8;
+
+        // Some writers leave the size of the last chunk unset, so clamp to the file
+        let end = start.saturating_add(size).min(bytes.len());
+        let chunk = &bytes[start..end];
+
+        match id {
+            b"fmt " => format = Some(parse_format(chunk)?),
+            b"data" => data = Some(chunk),
+            b"smpl" => sampler = parse_sampler(chunk),
+            _ if UNSUPPORTED_CHUNKS
+                .iter()
+                .any(|unsupported| &unsupported[..] == id) =>
+            {
+                return Err(WavError::UnsupportedChunk(
+                    String::from_utf8_lossy(id).into_owned(),
+                ))
+            }
+            _ => (),
+        }
+
+        // Chunks are padded to an even length
+        offset = start.saturating_add(size).saturating_add(size & 1);
+    }
+
+    let format = format.ok_or(WavError::MissingChunk("fmt "))?;
+    let data = data.ok_or(WavError::MissingChunk("data"))?;
+
+    let samples = data
+        .chunks_exact(format.block_align)
+        .flat_map(|frame| {
+            let bytes_per_sample = format.block_align / format.channels.count();
+            frame
+                .chunks_exact(bytes_per_sample)
+                .map(|sample| decode_sample(&format, sample))
+        })
+        .collect::<Vec<_>>();
+
+    let samples = resample(
+        &samples,
+        format.channels.count(),
+        format.sample_rate,
+        TARGET_SAMPLE_RATE,
+    );
+
+    let mut sample = SampleDefinition::new(
+        samples.into_boxed_slice(),
+        format.channels,
+        TARGET_SAMPLE_RATE,
+    );
+
+    if let Some(sampler) = sampler {
+        if let Ok(root_note) = midi_to_index(sampler.unity_note as i32) {
+            sample.set_root_note(root_note);
+        }
+        if let Some((start, end)) = sampler.sustain_loop {
+            // Loop points are in frames at the file's rate, and the end frame is played
+            let scale = |frame: u32| {
+                (frame as u64 * TARGET_SAMPLE_RATE as u64 / format.sample_rate as u64) as usize
+            };
+            sample.set_loop_mode(LoopMode::Loop {
+                start: scale(start),
+                end: scale(end.saturating_add(1)),
+            });
+        }
+    }
+
+    Ok(sample)
+}
+
+/// The parts of a sampler chunk used for playback.
+struct SamplerInfo {
+    /// The midi note which plays the sample at its original speed
+    unity_note: u32,
+    /// The first loop's start and end frames, both inclusive
+    sustain_loop: Option<(u32, u32)>,
+}
+
+/// Sampler chunks which are too short are ignored, rather than failing the whole file.
+fn parse_sampler(chunk: &[u8]) -> Option<SamplerInfo> {
+    if chunk.len() < 36 {
+        return None;
+    }
+
+    let loop_count = read_u32(chunk, 28);
+    let sustain_loop = match loop_count > 0 && chunk.len() >= 60 {
+        true => Some((read_u32(chunk, 44), read_u32(chunk, 48))),
+        false => None,
+    };
+
+    Some(SamplerInfo {
+        unity_note: read_u32(chunk, 12),
+        sustain_loop,
+    })
+}
+
+fn parse_format(chunk: &[u8]) -> Result<WavFormat, WavError> {
+    if chunk.len() < 16 {
+        return Err(WavError::Malformed("fmt chunk is too short"));
+    }
+
+    let mut format = read_u16(chunk, 0);
+    let channels = read_u16(chunk, 2);
+    let sample_rate = read_u32(chunk, 4);
+    let block_align = read_u16(chunk, 12) as usize;
+    let bits = read_u16(chunk, 14);
+
+    // The real format is the first two bytes of the sub format GUID
+    if format == FORMAT_EXTENSIBLE {
+        if chunk.len() < 26 {
+            return Err(WavError::Malformed("extensible fmt chunk is too short"));
+        }
+        format = read_u16(chunk, 24);
+    }
+
+    match (format, bits) {
+        (FORMAT_PCM, 8 | 16 | 24 | 32) | (FORMAT_IEEE_FLOAT, 32) => (),
+        (FORMAT_PCM | FORMAT_IEEE_FLOAT, bits) => {
+            return Err(WavError::UnsupportedBitDepth { format, bits })
+        }
+        (format, _) => return Err(WavError::UnsupportedFormat(format)),
+    }
+
+    let channels = match channels {
+        1 => SampleChannels::Mono,
+        2 => SampleChannels::Stereo,
+        channels => return Err(WavError::UnsupportedChannels(channels)),
+    };
+
+    if sample_rate == 0 {
+        return Err(WavError::Malformed("sample rate is zero"));
+    }
+    if block_align != channels.count() * (bits / 8) as usize {
+        return Err(WavError::Malformed("block align does not match the format"));
+    }
+
+    Ok(WavFormat {
+        format,
+        channels,
+        sample_rate,
+        block_align,
+        bits,
+    })
+}
+
+fn decode_sample(format: &WavFormat, bytes: &[u8]) -> f32 {
+    match (format.format, format.bits) {
+        // 8-bit data is unsigned
+        (FORMAT_PCM, 8) => (bytes[0] as f32 - 128.0) / 128.0,
+        (FORMAT_PCM, 16) => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32_768.0,
+        (FORMAT_PCM, 24) => {
+            // Shift into the top of an i32 to sign extend
+            i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) as f32 / 2_147_483_648.0
+        }
+        (FORMAT_PCM, 32) => {
+            i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32 / 2_147_483_648.0
+        }
+        (FORMAT_IEEE_FLOAT, 32) => {
+            f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).clamp(-1.0, 1.0)
+        }
+        _ => unreachable!("format is checked when parsing the fmt chunk"),
+    }
+}
+
+/// Linearly resamples interleaved frames from one rate to another.
+fn resample(samples: &[f32], channels: usize, from: u32, to: u32) -> Vec<f32> {
+    if from == to {
+        return samples.to_vec();
+    }
+
+    let frames = samples.len() / channels;
+    if frames == 0 {
+        return Vec::new();
+    }
+
+    let output_frames = (frames as u64 * to as u64).div_ceil(from as u64) as usize;
+    let step = from as f64 / to as f64;
+
+    (0..output_frames)
+        .flat_map(|frame| {
+            let position = frame as f64 * step;
+            let index = (position as usize).min(frames - 1);
+            let next = (index + 1).min(frames - 1);
+            let fraction = (position - index as f64) as f32;
+
+            (0..channels).map(move |channel| {
+                let first = samples[index * channels + channel];
+                let second = samples[next * channels + channel];
+                first + (second - first) * fraction
+            })
+        })
+        .collect()
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav(format: u16, channels: u16, sample_rate: u32, bits: u16, data: &[u8]) -> Vec<u8> {
+        let block_align = channels * bits / 8;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WAVE");
+
+        bytes.extend_from_slice(b"fmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&format.to_le_bytes());
+        bytes.extend_from_slice(&channels.to_le_bytes());
+        bytes.extend_from_slice(&sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        bytes.extend_from_slice(&block_align.to_le_bytes());
+        bytes.extend_from_slice(&bits.to_le_bytes());
+
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn reads_every_supported_bit_depth() {
+        let sample_rate = TARGET_SAMPLE_RATE;
+        let cases = [
+            (FORMAT_PCM, 8, vec![0u8, 128, 255]),
+            (
+                FORMAT_PCM,
+                16,
+                [i16::MIN, 0, i16::MAX]
+                    .iter()
+                    .flat_map(|v| v.to_le_bytes())
+                    .collect(),
+            ),
+            (FORMAT_PCM, 24, vec![0, 0, 0x80, 0, 0, 0, 0xFF, 0xFF, 0x7F]),
+            (
+                FORMAT_PCM,
+                32,
+                [i32::MIN, 0, i32::MAX]
+                    .iter()
+                    .flat_map(|v| v.to_le_bytes())
+                    .collect(),
+            ),
+            (
+                FORMAT_IEEE_FLOAT,
+                32,
+                [-1.0f32, 0.0, 1.0]
+                    .iter()
+                    .flat_map(|v| v.to_le_bytes())
+                    .collect(),
+            ),
+        ];
+
+        cases.iter().for_each(|(format, bits, data)| {
+            let sample = parse_wav(&wav(*format, 1, sample_rate, *bits, data)).unwrap();
+            assert_eq!(sample.frame_count(), 3);
+            assert_eq!(sample.data[0], -1.0, "{}-bit", bits);
+            assert_eq!(sample.data[1], 0.0, "{}-bit", bits);
+            assert!((sample.data[2] - 1.0).abs() < 0.01, "{}-bit", bits);
+        });
+    }
+
+    #[test]
+    fn resamples_stereo_to_the_engine_rate() {
+        let data = [0.0f32, 1.0, 0.5, -1.0]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<_>>();
+        let sample = parse_wav(&wav(
+            FORMAT_IEEE_FLOAT,
+            2,
+            TARGET_SAMPLE_RATE / 2,
+            32,
+            &data,
+        ))
+        .unwrap();
+
+        assert_eq!(sample.channels, SampleChannels::Stereo);
+        assert_eq!(sample.sample_rate, TARGET_SAMPLE_RATE);
+        assert_eq!(sample.frame_count(), 4);
+        assert_eq!(sample.frame(1), (0.25, 0.0));
+    }
+
+    #[test]
+    fn reports_unsupported_files() {
+        assert!(matches!(
+            parse_wav(b"not a wav file"),
+            Err(WavError::NotRiff)
+        ));
+        assert!(matches!(
+            parse_wav(&wav(2, 1, 8_000, 4, &[0, 0])),
+            Err(WavError::UnsupportedFormat(2))
+        ));
+        assert!(matches!(
+            parse_wav(&wav(FORMAT_IEEE_FLOAT, 1, 8_000, 64, &[0; 8])),
+            Err(WavError::UnsupportedBitDepth { bits: 64, .. })
+        ));
+        assert!(matches!(
+            parse_wav(&wav(FORMAT_PCM, 6, 8_000, 16, &[0; 12])),
+            Err(WavError::UnsupportedChannels(6))
+        ));
+
+        let mut silent = wav(FORMAT_PCM, 1, 8_000, 16, &[0; 2]);
+        silent.extend_from_slice(b"slnt");
+        silent.extend_from_slice(&4u32.to_le_bytes());
+        silent.extend_from_slice(&[0; 4]);
+        assert!(matches!(
+            parse_wav(&silent),
+            Err(WavError::UnsupportedChunk(_))
+        ));
+
+        let mut no_data = wav(FORMAT_PCM, 1, 8_000, 16, &[]);
+        no_data.truncate(no_data.len() - 8);
+        assert!(matches!(
+            parse_wav(&no_data),
+            Err(WavError::MissingChunk("data"))
+        ));
+    }
+
+    #[test]
+    fn reads_the_root_note_and_loop_from_sampler_chunks() {
+        let data = [0u8; 400];
+        let mut looped = wav(FORMAT_PCM, 1, TARGET_SAMPLE_RATE / 2, 16, &data);
+
+        let mut sampler = vec![0u8; 60];
+        sampler[12..16].copy_from_slice(&69u32.to_le_bytes());
+        sampler[28..32].copy_from_slice(&1u32.to_le_bytes());
+        sampler[44..48].copy_from_slice(&50u32.to_le_bytes());
+        sampler[48..52].copy_from_slice(&149u32.to_le_bytes());
+        looped.extend_from_slice(b"smpl");
+        looped.extend_from_slice(&(sampler.len() as u32).to_le_bytes());
+        looped.extend_from_slice(&sampler);
+
+        let sample = parse_wav(&looped).unwrap();
+        assert_eq!(sample.root_note, midi_to_index(69).unwrap());
+        // The loop is moved to the engine's rate, and ends after its last frame
+        assert_eq!(
+            sample.loop_mode,
+            LoopMode::Loop {
+                start: 100,
+                end: 300
+            }
+        );
+
+        // Without a sampler chunk, samples play through once at the default root note
+        let plain = parse_wav(&wav(FORMAT_PCM, 1, TARGET_SAMPLE_RATE, 16, &data)).unwrap();
+        assert_eq!(plain.root_note, crate::samples::DEFAULT_ROOT_NOTE);
+        assert_eq!(plain.loop_mode, LoopMode::OneShot);
+    }
+}
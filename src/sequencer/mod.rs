@@ -4,5 +4,10 @@ mod sequence;
 pub use pattern::*;
 pub use sequence::*;
 
-pub const MUSIC_CHANNEL_COUNT: usize = 8;
+use crate::samples::MUSIC_SAMPLE_CHANNEL_COUNT;
+
+/// Channels playing patches. The sample channels come after them.
+pub const FM_CHANNEL_COUNT: usize = 8;
+/// Every channel of a sequence, FM and sample.
+pub const MUSIC_CHANNEL_COUNT: usize = FM_CHANNEL_COUNT + MUSIC_SAMPLE_CHANNEL_COUNT;
 pub const ENTRIES_PER_BEAT: usize = 4;
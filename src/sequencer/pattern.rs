@@ -1,3 +1,6 @@
+use super::{FM_CHANNEL_COUNT, MUSIC_CHANNEL_COUNT};
+use crate::{patches::VELOCITY_MAX, samples::SampleBank};
+
 #[derive(Debug, Clone)]
 pub struct Pattern {
     pub(crate) entires: Box<[PatternEntry]>,
@@ -11,10 +14,7 @@ impl Pattern {
     pub fn empty_pattern(len: usize) -> Self {
         Self {
             entires: (0..len)
-                .map(|_| PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Released,
-                })
+                .map(|_| PatternEntry::released())
                 .collect::<Vec<_>>()
                 .into_boxed_slice(),
         }
@@ -26,8 +26,84 @@ impl Pattern {
 
 #[derive(Debug, Clone)]
 pub(crate) struct PatternEntry {
-    pub(crate) patch_index: Option<usize>,
+    pub(crate) instrument: Option<Instrument>,
     pub(crate) key_state: KeyState,
+    /// How hard a pressed key is played, from 0 to VELOCITY_MAX
+    pub(crate) velocity: u8,
+}
+
+impl PatternEntry {
+    pub(crate) fn pressed(note: usize) -> Self {
+        Self {
+            instrument: None,
+            key_state: KeyState::Pressed(note),
+            velocity: VELOCITY_MAX,
+        }
+    }
+
+    pub(crate) fn held() -> Self {
+        Self {
+            instrument: None,
+            key_state: KeyState::Held,
+            velocity: VELOCITY_MAX,
+        }
+    }
+
+    /// Glides the playing note to another without starting it again.
+    pub(crate) fn slide(note: usize) -> Self {
+        Self {
+            instrument: None,
+            key_state: KeyState::Slide(note),
+            velocity: VELOCITY_MAX,
+        }
+    }
+
+    pub(crate) fn released() -> Self {
+        Self {
+            instrument: None,
+            key_state: KeyState::Released,
+            velocity: VELOCITY_MAX,
+        }
+    }
+
+    /// Switches the channel to a different instrument at this entry.
+    pub(crate) fn with_instrument(self, instrument: Instrument) -> Self {
+        Self {
+            instrument: Some(instrument),
+            ..self
+        }
+    }
+
+    /// Plays the pressed key softer than full velocity.
+    pub(crate) fn with_velocity(self, velocity: u8) -> Self {
+        Self {
+            velocity: velocity.min(VELOCITY_MAX),
+            ..self
+        }
+    }
+}
+
+/// Which of the sequence's patches or samples a channel plays.
+/// Patches only play on FM channels, and samples on sample channels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instrument {
+    Patch(usize),
+    Sample(usize),
+}
+
+impl Instrument {
+    /// Whether the instrument can play on one of the sequence's channels.
+    pub fn plays_on(self, channel: usize) -> bool {
+        match self {
+            Self::Patch(_) => channel < FM_CHANNEL_COUNT,
+            Self::Sample(_) => (FM_CHANNEL_COUNT..MUSIC_CHANNEL_COUNT).contains(&channel),
+        }
+    }
+
+    /// Looks up a sample in the bank by the name it was loaded with.
+    pub fn sample_named(bank: &SampleBank, name: &str) -> Option<Self> {
+        bank.index_of(name).map(Self::Sample)
+    }
 }
 
 #[derive(Debug, Clone)]
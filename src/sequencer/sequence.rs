@@ -4,11 +4,22 @@ use parking_lot::RwLock;
 
 use crate::{
     notes::{self},
+    patches::{velocity_to_volume, Declick},
+    samples::{SampleBank, SampleChannels, SampleDefinition, SampleInstance},
     sequencer::KeyState,
-    PatchDefinition, PatchInstance, TARGET_SAMPLE_RATE, TARGET_SAMPLE_TICK_TIME,
+    waveform::WaveformState,
+    PatchDefinition, PatchInstance, Waveform, TARGET_SAMPLE_RATE,
 };
 
-use super::{Pattern, PatternEntry, ENTRIES_PER_BEAT, MUSIC_CHANNEL_COUNT};
+use super::{
+    Instrument, Pattern, PatternEntry, ENTRIES_PER_BEAT, FM_CHANNEL_COUNT, MUSIC_CHANNEL_COUNT,
+};
+
+/// Frames rendered at a time when writing to an output buffer.
+const WRITE_BLOCK_FRAMES: usize = 256;
+
+/// The name of the sample the test pattern plays on its first sample channel.
+const TEST_HAT_NAME: &str = "test hat";
 
 #[derive(Clone)]
 pub struct SequenceInstanceHandle {
@@ -22,6 +33,18 @@ impl SequenceInstanceHandle {
         }
     }
 
+    pub fn set_playing(&self, playing: bool) {
+        self.sequence.write().set_playing(playing);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.sequence.read().playing
+    }
+
+    pub fn bpm(&self) -> f32 {
+        self.sequence.read().definition.read().bpm
+    }
+
     //TODO: Potentially add left/right scaling here?
     //Would it be better to do each operator and combine them later?
     pub(crate) fn write_to_buffer(&self, data: &mut [f32], channels: u16) {
@@ -34,6 +57,7 @@ impl SequenceInstanceHandle {
 pub struct SequenceDefinition {
     bpm: f32,
     patches: Box<[Arc<RwLock<PatchDefinition>>]>, // The available patches
+    samples: Arc<RwLock<SampleBank>>,             // The available samples
     patterns: Arc<[Pattern; MUSIC_CHANNEL_COUNT]>, // The notes played
     ticks_per_pattern_step: u32, // How many ticks until we need to advance to the next pattern
 }
@@ -42,6 +66,7 @@ impl SequenceDefinition {
     pub fn new(
         bpm: f32,
         patches: Box<[Arc<RwLock<PatchDefinition>>]>,
+        samples: Arc<RwLock<SampleBank>>,
         patterns: Arc<[Pattern; MUSIC_CHANNEL_COUNT]>,
     ) -> Self {
         println!("generating sequence definition");
@@ -56,150 +81,83 @@ impl SequenceDefinition {
         Self {
             bpm,
             patches,
+            samples,
             patterns,
             ticks_per_pattern_step: ticks_per_beat as u32,
         }
     }
 
-    pub fn test_pattern(sample_rate: u32) -> Self {
+    pub fn test_pattern(sample_rate: u32, samples: Arc<RwLock<SampleBank>>) -> Self {
         let patches = PatchDefinition::new(sample_rate);
+        let note = |name| notes::parse_note(name).unwrap();
         let mut patterns = vec![Pattern {
             entires: vec![
-                PatternEntry {
-                    patch_index: Some(0),
-                    key_state: KeyState::Pressed(25),
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Held,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Held,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Released,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Pressed(25),
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Held,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Held,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Released,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Pressed(21),
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Held,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Held,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Released,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Pressed(21),
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Released,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Pressed(23),
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Held,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Held,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Released,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Pressed(23),
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Held,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Held,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Released,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Pressed(23),
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Held,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Held,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Released,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Pressed(23),
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Held,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Held,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Released,
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Pressed(23),
-                },
-                PatternEntry {
-                    patch_index: None,
-                    key_state: KeyState::Released,
-                },
+                PatternEntry::pressed(note("C#3")).with_instrument(Instrument::Patch(0)),
+                PatternEntry::held(),
+                PatternEntry::held(),
+                PatternEntry::released(),
+                PatternEntry::pressed(note("C#3")),
+                PatternEntry::held(),
+                PatternEntry::held(),
+                PatternEntry::released(),
+                PatternEntry::pressed(note("A2")),
+                PatternEntry::held(),
+                PatternEntry::held(),
+                PatternEntry::released(),
+                PatternEntry::pressed(note("A2")).with_velocity(80),
+                PatternEntry::released(),
+                PatternEntry::pressed(note("B2")),
+                PatternEntry::held(),
+                PatternEntry::held(),
+                PatternEntry::released(),
+                PatternEntry::pressed(note("B2")),
+                PatternEntry::held(),
+                PatternEntry::held(),
+                PatternEntry::released(),
+                PatternEntry::pressed(note("B2")),
+                PatternEntry::held(),
+                PatternEntry::held(),
+                PatternEntry::released(),
+                PatternEntry::pressed(note("B2")),
+                PatternEntry::held(),
+                PatternEntry::slide(note("C#3")),
+                PatternEntry::released(),
+                PatternEntry::pressed(note("B2")).with_velocity(64),
+                PatternEntry::released(),
             ]
             .into_boxed_slice(),
         }];
 
         let demo_length = patterns[0].pattern_length();
 
-        (1..MUSIC_CHANNEL_COUNT).for_each(|_| patterns.push(Pattern::empty_pattern(demo_length)));
+        (1..FM_CHANNEL_COUNT).for_each(|_| patterns.push(Pattern::empty_pattern(demo_length)));
+
+        // A hi-hat on every beat, on the first sample channel
+        let hat = {
+            let mut samples = samples.write();
+            if samples.index_of(TEST_HAT_NAME).is_none() {
+                samples.insert(TEST_HAT_NAME, test_hat()).unwrap();
+            }
+            Instrument::sample_named(&samples, TEST_HAT_NAME)
+        };
+        let hat_note = note("C4");
+        patterns.push(Pattern {
+            entires: (0..demo_length)
+                .map(|entry| {
+                    let pattern_entry = match entry % ENTRIES_PER_BEAT {
+                        0 => PatternEntry::pressed(hat_note).with_velocity(90),
+                        _ => PatternEntry::held(),
+                    };
+                    match hat {
+                        Some(hat) if entry == 0 => pattern_entry.with_instrument(hat),
+                        _ => pattern_entry,
+                    }
+                })
+                .collect(),
+        });
+
+        (FM_CHANNEL_COUNT + 1..MUSIC_CHANNEL_COUNT)
+            .for_each(|_| patterns.push(Pattern::empty_pattern(demo_length)));
 
         let patterns: Box<[Pattern; MUSIC_CHANNEL_COUNT]> =
             patterns.into_boxed_slice().try_into().unwrap();
@@ -208,131 +166,281 @@ impl SequenceDefinition {
         Self::new(
             120.0,
             vec![Arc::new(RwLock::new(patches))].into_boxed_slice(),
+            samples,
             Arc::new(*patterns),
         )
     }
 }
 
+/// A short burst of decaying noise, for the test pattern.
+fn test_hat() -> SampleDefinition {
+    let frames = TARGET_SAMPLE_RATE as usize / 20;
+    let mut state = WaveformState::default();
+    let data = (0..frames)
+        .map(|frame| {
+            let decay = 1.0 - frame as f32 / frames as f32;
+            Waveform::noise().func(0.0, 0.0, &mut state) * decay * decay * 0.25
+        })
+        .collect::<Vec<_>>();
+
+    SampleDefinition::new(
+        data.into_boxed_slice(),
+        SampleChannels::Mono,
+        TARGET_SAMPLE_RATE,
+    )
+}
+
+/// What a channel is currently playing.
+// Voices live in a fixed array per channel, so boxing patches would only add an indirection
+#[allow(clippy::large_enum_variant)]
+enum Voice {
+    Patch(PatchInstance),
+    Sample(SampleInstance),
+}
+
+impl Voice {
+    /// Patterns can name samples the bank doesn't have, which play nothing.
+    fn new(definition: &SequenceDefinition, instrument: Instrument) -> Option<Self> {
+        match instrument {
+            Instrument::Patch(index) => Some(Self::Patch(PatchInstance::new(
+                definition.patches[index].clone(),
+                0.0,
+            ))),
+            Instrument::Sample(index) => definition
+                .samples
+                .read()
+                .get(index)
+                .map(|sample| Self::Sample(SampleInstance::new(sample.clone()))),
+        }
+    }
+
+    fn plays(&self, definition: &SequenceDefinition, instrument: Instrument) -> bool {
+        match (self, instrument) {
+            (Self::Patch(patch), Instrument::Patch(index)) => {
+                Arc::ptr_eq(&patch.definition, &definition.patches[index])
+            }
+            (Self::Sample(sample), Instrument::Sample(index)) => definition
+                .samples
+                .read()
+                .get(index)
+                .is_some_and(|bank_sample| Arc::ptr_eq(&sample.definition, bank_sample)),
+            _ => false,
+        }
+    }
+
+    fn set_active(&mut self, active: bool) {
+        match self {
+            Self::Patch(patch) => patch.set_active(active),
+            Self::Sample(sample) => sample.set_active(active),
+        }
+    }
+
+    fn set_frequency(&mut self, frequency: f32) {
+        match self {
+            Self::Patch(patch) => patch.set_frequency(frequency),
+            Self::Sample(sample) => sample.set_frequency(frequency),
+        }
+    }
+
+    /// Starts a new note. Patches follow their trigger mode, samples always restart.
+    fn note_on(&mut self, frequency: f32, velocity: u8) {
+        match self {
+            Self::Patch(patch) => patch.note_on(frequency, velocity),
+            Self::Sample(sample) => {
+                sample.set_active(false);
+                sample.set_frequency(frequency);
+                sample.set_volume(velocity_to_volume(velocity));
+                sample.set_active(true);
+            }
+        }
+    }
+
+    /// Fills the output, ticking once per sample.
+    fn render(&mut self, output: &mut [f32]) {
+        match self {
+            Self::Patch(patch) => patch.force_render(output),
+            Self::Sample(sample) => sample.render(output),
+        }
+    }
+}
+
 pub struct SequenceInstance {
     definition: Arc<RwLock<SequenceDefinition>>,
-    output: [Option<PatchInstance>; MUSIC_CHANNEL_COUNT],
-    wall_clock: f32,
-    last_output: f32,
+    /// Whether the patterns are advancing. Stopped voices still play out their release
+    playing: bool,
+    output: [Option<Voice>; MUSIC_CHANNEL_COUNT],
     clock: u32,
     pattern_index: usize,
+    voice_buffer: Vec<f32>,
+    /// The last sample each channel output, to fade out when its voice is replaced
+    last_outputs: [f32; MUSIC_CHANNEL_COUNT],
+    declicks: [Declick; MUSIC_CHANNEL_COUNT],
 }
 
 impl SequenceInstance {
     pub fn new(definition: Arc<RwLock<SequenceDefinition>>) -> Self {
         Self {
             definition,
+            playing: false,
             output: empty_outputs(),
-            wall_clock: 0.0,
-            last_output: 0.0,
             clock: 0,
             pattern_index: 0,
+            voice_buffer: Vec::new(),
+            last_outputs: [0.0; MUSIC_CHANNEL_COUNT],
+            declicks: std::array::from_fn(|_| Declick::default()),
         }
     }
 
-    //TODO: Potentially add left/right scaling here?
-    //Would it be better to do each operator and combine them later?
-    pub(crate) fn write_to_buffer(&mut self, data: &mut [f32], channels: u16) {
-        data.chunks_exact_mut(channels as usize)
-            .zip(self)
-            .for_each(|(frame, sample)| frame.iter_mut().for_each(|data| *data += sample))
+    /// Starts playing from the beginning, or stops and releases every channel.
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+        self.clock = 0;
+        self.pattern_index = 0;
+
+        if !playing {
+            self.output
+                .iter_mut()
+                .flatten()
+                .for_each(|voice| voice.set_active(false));
+        }
     }
-}
 
-impl Iterator for SequenceInstance {
-    type Item = f32;
+    /// Fills the output with mono samples. Voices are rendered in blocks
+    /// between each step of the pattern.
+    pub fn render(&mut self, output: &mut [f32]) {
+        let definition = self.definition.clone();
+        let definition = definition.read();
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.wall_clock += TARGET_SAMPLE_TICK_TIME;
-        let definition = self.definition.read();
+        output.iter_mut().for_each(|output| *output = 0.0);
+
+        if !self.playing {
+            self.render_voices(output);
+            return;
+        }
 
-        //TODO: Could optimize this with integer math?
-        while self.wall_clock >= TARGET_SAMPLE_TICK_TIME {
-            self.wall_clock -= TARGET_SAMPLE_TICK_TIME;
+        let mut block_start = 0;
+        for sample in 0..output.len() {
             self.clock += 1;
 
             // If we should advance the pattern...
             if self.clock == definition.ticks_per_pattern_step {
-                println!("advancing the pattern");
+                self.render_voices(&mut output[block_start..sample]);
+                block_start = sample;
+
                 self.clock = 0;
+                self.advance_pattern(&definition);
+            }
+        }
 
-                // Wrap around if too long
-                if self.pattern_index == definition.patterns[0].pattern_length() {
-                    self.pattern_index = 0;
+        self.render_voices(&mut output[block_start..]);
+    }
+
+    fn render_voices(&mut self, output: &mut [f32]) {
+        if output.is_empty() {
+            return;
+        }
+        self.voice_buffer.resize(output.len(), 0.0);
+
+        (0..MUSIC_CHANNEL_COUNT).for_each(|channel| {
+            match &mut self.output[channel] {
+                Some(voice) => voice.render(&mut self.voice_buffer),
+                None => self.voice_buffer.fill(0.0),
+            }
+            self.declicks[channel].apply(&mut self.voice_buffer);
+            self.last_outputs[channel] = self.voice_buffer[output.len() - 1];
+
+            output
+                .iter_mut()
+                .zip(self.voice_buffer.iter())
+                .for_each(|(output, voice)| *output += voice);
+        });
+    }
+
+    /// Swaps the voice on a channel, fading out the old one so it doesn't click.
+    fn replace_voice(&mut self, channel: usize, voice: Option<Voice>) {
+        if self.output[channel].is_some() {
+            self.declicks[channel].start(self.last_outputs[channel]);
+        }
+        self.output[channel] = voice;
+    }
+
+    fn advance_pattern(&mut self, definition: &SequenceDefinition) {
+        // Wrap around if too long
+        if self.pattern_index == definition.patterns[0].pattern_length() {
+            self.pattern_index = 0;
+        }
+
+        // TODO: Read patterns and adjust accordingly
+        definition
+            .patterns
+            .iter()
+            .enumerate()
+            .for_each(|(channel, pattern)| {
+                let pattern = &pattern.entires[self.pattern_index];
+
+                // Instruments on the wrong kind of channel are ignored
+                let instrument = pattern
+                    .instrument
+                    .filter(|instrument| instrument.plays_on(channel));
+                match (instrument, self.output[channel].as_ref()) {
+                    (Some(instrument), Some(current_voice))
+                        if !current_voice.plays(definition, instrument) =>
+                    {
+                        self.replace_voice(channel, Voice::new(definition, instrument));
+                    }
+                    (Some(instrument), None) => {
+                        self.output[channel] = Voice::new(definition, instrument);
+                    }
+                    _ => (),
                 }
 
-                // TODO: Read patterns and adjust accordingly
-                definition
-                    .patterns
-                    .iter()
-                    .enumerate()
-                    .for_each(|(channel, pattern)| {
-                        let pattern = &pattern.entires[self.pattern_index];
-
-                        match (pattern.patch_index, self.output[channel].as_ref()) {
-                            (Some(new_patch_index), Some(current_patch)) => {
-                                if !Arc::ptr_eq(
-                                    &current_patch.definition,
-                                    &definition.patches[new_patch_index],
-                                ) {
-                                    self.output[channel] = Some(PatchInstance::new(
-                                        definition.patches[new_patch_index].clone(),
-                                        0.0,
-                                    ));
-                                } else {
-                                    println!("SAME PATCH, NO CHANGE!");
-                                }
-                            }
-                            (Some(new_patch_index), None) => {
-                                self.output[channel] = Some(PatchInstance::new(
-                                    definition.patches[new_patch_index].clone(),
-                                    0.0,
-                                ));
+                // Play the key
+                if let Some(ref mut output_voice) = self.output[channel] {
+                    match pattern.key_state {
+                        KeyState::Released => output_voice.set_active(false),
+                        KeyState::Pressed(index) if notes::is_mapped(index) => {
+                            // Samples jump back to their start, so fade out from where they were
+                            if matches!(output_voice, Voice::Sample(_)) {
+                                self.declicks[channel].start(self.last_outputs[channel]);
                             }
-                            _ => (),
+                            let frequency = notes::index_to_frequency(index);
+                            output_voice.note_on(frequency, pattern.velocity);
                         }
-
-                        // Play the key
-                        if let Some(ref mut output_patch) = self.output[channel] {
-                            match pattern.key_state {
-                                KeyState::Released => output_patch.set_active(false),
-                                KeyState::Pressed(index) => {
-                                    output_patch.set_active(false);
-                                    output_patch.set_frequency(notes::index_to_frequency(index));
-                                    output_patch.set_active(true);
-                                }
-                                KeyState::Held => (),
-                                KeyState::Slide(index) => {
-                                    output_patch.set_frequency(notes::index_to_frequency(index));
-                                }
-                            }
+                        // Keys the tuning leaves unmapped only release the last note
+                        KeyState::Pressed(_) => output_voice.set_active(false),
+                        KeyState::Held => (),
+                        KeyState::Slide(index) if notes::is_mapped(index) => {
+                            output_voice.set_frequency(notes::index_to_frequency(index));
                         }
-                    });
-
-                //Advance the pattern
-                self.pattern_index += 1;
-            }
-
-            // Produce sound
-            self.last_output = self.output.iter_mut().fold(0.0, |accumulator, patch| {
-                if let Some(patch) = patch {
-                    accumulator + patch.force_tick()
-                } else {
-                    accumulator
+                        KeyState::Slide(_) => (),
+                    }
                 }
             });
-        }
 
-        Some(self.last_output)
+        //Advance the pattern
+        self.pattern_index += 1;
+    }
+
+    //TODO: Potentially add left/right scaling here?
+    //Would it be better to do each operator and combine them later?
+    pub(crate) fn write_to_buffer(&mut self, data: &mut [f32], channels: u16) {
+        let mut mix = [0.0; WRITE_BLOCK_FRAMES];
+
+        data.chunks_mut(WRITE_BLOCK_FRAMES * channels as usize)
+            .for_each(|block| {
+                let mix = &mut mix[..block.len() / channels as usize];
+                self.render(mix);
+
+                block
+                    .chunks_exact_mut(channels as usize)
+                    .zip(mix.iter())
+                    .for_each(|(frame, sample)| frame.iter_mut().for_each(|data| *data += sample));
+            });
     }
 }
 
-fn empty_outputs() -> [Option<PatchInstance>; MUSIC_CHANNEL_COUNT] {
-    let mut output: [MaybeUninit<Option<PatchInstance>>; MUSIC_CHANNEL_COUNT] =
+fn empty_outputs() -> [Option<Voice>; MUSIC_CHANNEL_COUNT] {
+    let mut output: [MaybeUninit<Option<Voice>>; MUSIC_CHANNEL_COUNT] =
         unsafe { MaybeUninit::uninit().assume_init() };
 
     output.iter_mut().for_each(|target| {
@@ -341,3 +449,122 @@ fn empty_outputs() -> [Option<PatchInstance>; MUSIC_CHANNEL_COUNT] {
 
     unsafe { std::mem::transmute(output) }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern_plays_a_sample_channel() {
+        notes::generate();
+
+        let bank = Arc::new(RwLock::new(SampleBank::new()));
+        let definition = SequenceDefinition::test_pattern(TARGET_SAMPLE_RATE, bank.clone());
+        let hat = Instrument::sample_named(&bank.read(), TEST_HAT_NAME).unwrap();
+        assert!(hat.plays_on(FM_CHANNEL_COUNT));
+        assert!(!hat.plays_on(0));
+
+        // The sample is only added to the bank once
+        SequenceDefinition::test_pattern(TARGET_SAMPLE_RATE, bank.clone());
+        assert_eq!(bank.read().iter().count(), 1);
+
+        let steps = definition.ticks_per_pattern_step as usize;
+        let mut sequence = SequenceInstance::new(Arc::new(RwLock::new(definition)));
+        sequence.set_playing(true);
+        let mut output = vec![0.0; steps + 100];
+        sequence.render(&mut output);
+
+        assert!(matches!(
+            sequence.output[FM_CHANNEL_COUNT],
+            Some(Voice::Sample(ref sample)) if sample.is_playing()
+        ));
+        assert!(sequence.output[FM_CHANNEL_COUNT + 1].is_none());
+        assert!(output[steps..].iter().any(|sample| *sample != 0.0));
+    }
+
+    #[test]
+    fn retriggered_samples_do_not_click() {
+        notes::generate();
+
+        // A rising ramp, which is far from zero when it's pressed again
+        let ramp = (0..TARGET_SAMPLE_RATE)
+            .map(|frame| frame as f32 / TARGET_SAMPLE_RATE as f32)
+            .collect::<Vec<_>>();
+        let bank = Arc::new(RwLock::new(SampleBank::new()));
+        let index = bank
+            .write()
+            .insert(
+                "ramp",
+                SampleDefinition::new(
+                    ramp.into_boxed_slice(),
+                    SampleChannels::Mono,
+                    TARGET_SAMPLE_RATE,
+                ),
+            )
+            .unwrap();
+
+        let note = notes::parse_note("C4").unwrap();
+        let patterns: Box<[Pattern; MUSIC_CHANNEL_COUNT]> = (0..MUSIC_CHANNEL_COUNT)
+            .map(|channel| match channel {
+                FM_CHANNEL_COUNT => Pattern {
+                    entires: Box::new([
+                        PatternEntry::pressed(note).with_instrument(Instrument::Sample(index)),
+                        PatternEntry::pressed(note),
+                    ]),
+                },
+                _ => Pattern::empty_pattern(2),
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice()
+            .try_into()
+            .unwrap();
+        let definition = SequenceDefinition::new(120.0, Box::new([]), bank, Arc::new(*patterns));
+        let steps = definition.ticks_per_pattern_step as usize;
+
+        let mut sequence = SequenceInstance::new(Arc::new(RwLock::new(definition)));
+        sequence.set_playing(true);
+        let mut output = vec![0.0; steps * 3];
+        output
+            .chunks_mut(256)
+            .for_each(|block| sequence.render(block));
+
+        // The ramp restarts at the 2nd step, well above zero
+        assert!(output[steps * 2 - 2] > 0.05);
+        output[steps..].windows(2).for_each(|pair| {
+            assert!((pair[1] - pair[0]).abs() < 0.01, "{:?}", pair);
+        });
+    }
+
+    #[test]
+    fn slides_keep_the_note_and_stopping_releases_it() {
+        notes::generate();
+        crate::patches::init_attenuation_table();
+        crate::waveform::init_waveform_tables();
+
+        let bank = Arc::new(RwLock::new(SampleBank::new()));
+        let definition = SequenceDefinition::test_pattern(TARGET_SAMPLE_RATE, bank);
+        let steps = definition.ticks_per_pattern_step as usize;
+        let mut sequence = SequenceInstance::new(Arc::new(RwLock::new(definition)));
+
+        // Stopped sequences don't start any notes
+        let mut output = vec![0.0; steps * 2];
+        sequence.render(&mut output);
+        assert!(sequence.output[0].is_none());
+
+        // The 29th step slides from B2 up to C#3, while the note is still held
+        sequence.set_playing(true);
+        let mut output = vec![0.0; steps * 29];
+        sequence.render(&mut output);
+        let c_sharp = notes::index_to_frequency(notes::parse_note("C#3").unwrap());
+        assert!(matches!(
+            sequence.output[0],
+            Some(Voice::Patch(ref patch)) if patch.base_frequency == c_sharp && patch.active
+        ));
+
+        sequence.set_playing(false);
+        assert!(matches!(
+            sequence.output[0],
+            Some(Voice::Patch(ref patch)) if !patch.active
+        ));
+    }
+}
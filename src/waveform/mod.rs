@@ -0,0 +1,750 @@
+use std::{
+    f32::consts::{FRAC_PI_2, PI, TAU},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+
+mod tables;
+mod wavetable;
+
+pub use tables::*;
+pub use wavetable::*;
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum Waveform {
+    // Basics
+    #[default]
+    Sine,
+    Square,
+    Pulse(f32),
+    Saw,
+    Triangle,
+    Noise,
+
+    // Band limited
+    BandLimitedSquare,
+    BandLimitedPulse(f32),
+    BandLimitedSaw,
+
+    // OPL
+    HalfSine,
+    AbsoluteSine,
+    QuarterSine,
+    AlternatingSine,
+    CamelSine,
+    LogarithmicSaw,
+    // Other
+    PitchedNoise(u32),
+
+    // Tx81z
+    InvertedSine,
+    InvertedHalfSine,
+    InvertedAlternatingSine,
+    InvertedCamelSine,
+
+    // User drawn
+    Custom(Wavetable),
+}
+
+impl Waveform {
+    /// Generates a Sine wave oscilator
+    pub fn sine() -> Self {
+        Self::Sine
+    }
+
+    /// Generates a Pulse wave oscilator.
+    pub fn pulse(duty: f32) -> Self {
+        assert!(duty < 1.0);
+        assert!(duty > 0.0);
+
+        if duty == 0.5 {
+            Self::Square
+        } else {
+            Self::Pulse(duty)
+        }
+    }
+
+    /// Generates a Square wave oscilator. Equal to pulse(0.5)
+    pub fn square() -> Self {
+        Self::Square
+    }
+
+    /// Generates a Sawtooth wave oscilator
+    pub fn saw() -> Self {
+        Self::Saw
+    }
+
+    /// Generates an anti-aliased Pulse wave oscilator.
+    /// Unlike pulse(duty), the duty cycle is linear in phase.
+    pub fn band_limited_pulse(duty: f32) -> Self {
+        assert!(duty < 1.0);
+        assert!(duty > 0.0);
+
+        if duty == 0.5 {
+            Self::BandLimitedSquare
+        } else {
+            Self::BandLimitedPulse(duty)
+        }
+    }
+
+    /// Generates an anti-aliased Square wave oscilator.
+    pub fn band_limited_square() -> Self {
+        Self::BandLimitedSquare
+    }
+
+    /// Generates an anti-aliased Sawtooth wave oscilator.
+    pub fn band_limited_saw() -> Self {
+        Self::BandLimitedSaw
+    }
+
+    /// Generates a Triangle wave oscilator
+    pub fn triangle() -> Self {
+        Self::Triangle
+    }
+
+    /// Generates white noise. Ignores frequency and modulation.
+    pub fn noise() -> Self {
+        Self::Noise
+    }
+
+    /// Generates periodic noise from a linear feedback shift register
+    /// of the given width in bits. The register is shifted 16 times per
+    /// cycle, so the noise follows the played note. Short registers
+    /// (ie, 7) repeat quickly and sound metallic, long ones (ie, 15) sound
+    /// closer to white noise.
+    pub fn pitched_noise(width: u32) -> Self {
+        assert!(width >= PITCHED_NOISE_WIDTH_MIN);
+        assert!(width <= PITCHED_NOISE_WIDTH_MAX);
+
+        Self::PitchedNoise(width)
+    }
+
+    /// Generates a Half Sine wave oscilator. Produces
+    /// a sound if the value is >= 0
+    pub fn half_sine() -> Self {
+        Self::HalfSine
+    }
+
+    /// Generates an Absolute Sine wave oscilator.
+    pub fn absolute_sine() -> Self {
+        Self::AbsoluteSine
+    }
+
+    /// Generates a Quarter Sine wave oscilator. Generates a sound
+    /// for the rising part of a sine wave.
+    pub fn quarter_sine() -> Self {
+        Self::QuarterSine
+    }
+
+    /// Generates an Alternating Sine wave oscilator. Produces a wave
+    /// with a half-period arch and trough, then silence. Similar to a
+    /// sine wave with half of the period.
+    pub fn alternating_sine() -> Self {
+        Self::AlternatingSine
+    }
+
+    /// Generates a Camel Sine wave oscilator. Produces a wave
+    /// with two half-period arches, then silence. Similar to
+    /// absolute value of an alternating sine oscilator.
+    pub fn camel_sine() -> Self {
+        Self::CamelSine
+    }
+
+    /// Generates a Logarithmic Sawtooth wave oscilator.
+    pub fn logarithmic_saw() -> Self {
+        Self::LogarithmicSaw
+    }
+
+    /// Generates a user drawn wavetable oscilator.
+    pub fn custom(wavetable: Wavetable) -> Self {
+        Self::Custom(wavetable)
+    }
+
+    /// Whether the parameters are in the ranges the constructors accept. Variants
+    /// built directly, or loaded from a file, skip those checks.
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Self::Pulse(duty) | Self::BandLimitedPulse(duty) => *duty > 0.0 && *duty < 1.0,
+            Self::PitchedNoise(width) => {
+                (PITCHED_NOISE_WIDTH_MIN..=PITCHED_NOISE_WIDTH_MAX).contains(width)
+            }
+            _ => true,
+        }
+    }
+
+    /// A copy for rendering a block, which reads any custom wavetable without locking.
+    pub(crate) fn frozen(&self) -> Self {
+        match self {
+            Self::Custom(wavetable) => Self::Custom(wavetable.frozen()),
+            waveform => waveform.clone(),
+        }
+    }
+
+    /// Generates the output for a normalized phase in the range 0.0..1.0.
+    /// Increment is how far the phase moves each sample, ie frequency / sample rate.
+    pub fn func(&self, phase: f32, increment: f32, state: &mut WaveformState) -> f32 {
+        let value = phase * TAU;
+
+        if waveform_tables_enabled() {
+            if let Some(output) = self.table_func(value) {
+                return output;
+            }
+        }
+
+        self.exact_func(value, increment, state)
+    }
+
+    /// Calculates the waveform directly, without the lookup tables.
+    pub(crate) fn exact_func(&self, value: f32, increment: f32, state: &mut WaveformState) -> f32 {
+        match self {
+            Self::Sine => value.sin(),
+            Self::Pulse(duty) => pulse(value, *duty),
+            Self::Square => square(value),
+            Self::Saw => ((value % TAU) / PI) - 1.0,
+            Self::BandLimitedSquare => band_limited_pulse(value, increment, 0.5, state),
+            Self::BandLimitedPulse(duty) => band_limited_pulse(value, increment, *duty, state),
+            Self::BandLimitedSaw => band_limited_saw(value, increment, state),
+            Self::Triangle => value.sin().asin() / FRAC_PI_2,
+            Self::Noise => state.white_noise(),
+            Self::HalfSine => half_sine(value),
+            Self::AbsoluteSine => value.sin().abs(),
+            Self::QuarterSine => quarter_sine(value),
+            Self::AlternatingSine => alternating_sine(value),
+            Self::CamelSine => camel_sine(value),
+            Self::LogarithmicSaw => logarithmic_saw(value),
+            Self::InvertedSine => inverted_sine(value),
+            Self::InvertedHalfSine => inverted_half_sine(value),
+            Self::InvertedAlternatingSine => inverted_alternating_sine(value),
+            Self::InvertedCamelSine => inverted_camel_sine(value),
+            Self::PitchedNoise(width) => state.pitched_noise(*width, increment),
+            Self::Custom(wavetable) => wavetable.func(value),
+        }
+    }
+}
+
+/// The narrowest and widest pitched noise registers, in bits.
+pub const PITCHED_NOISE_WIDTH_MIN: u32 = 2;
+pub const PITCHED_NOISE_WIDTH_MAX: u32 = 32;
+
+/// LFSR width of the "short" periodic noise mode, as on the Game Boy.
+pub const SHORT_NOISE_WIDTH: u32 = 7;
+
+/// LFSR width of the "long" noise mode, as on the SN76489 and Game Boy.
+pub const LONG_NOISE_WIDTH: u32 = 15;
+
+/// How many times the pitched noise register is shifted per cycle.
+const NOISE_STEPS_PER_CYCLE: f32 = 16.0;
+
+// Used to give each oscillator a different noise seed
+static NOISE_SEED: AtomicU32 = AtomicU32::new(0x9E37_79B9);
+
+/// Per oscillator state for waveforms which need to remember
+/// something between samples, such as the noise generators.
+#[derive(Clone, Debug)]
+pub struct WaveformState {
+    random: u32,
+    lfsr: u32,
+    lfsr_clock: f32,
+    /// The last phase of the band limited waveforms, to see how far it really moved
+    last_phase: Option<f32>,
+}
+
+impl Default for WaveformState {
+    fn default() -> Self {
+        let seed = NOISE_SEED.fetch_add(0x9E37_79B9, Ordering::Relaxed);
+        Self {
+            random: seed | 1,
+            lfsr: u32::MAX,
+            lfsr_clock: 0.0,
+            last_phase: None,
+        }
+    }
+}
+
+impl WaveformState {
+    /// Xorshift32, outputs in the range -1.0..=1.0
+    fn white_noise(&mut self) -> f32 {
+        let mut x = self.random;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.random = x;
+
+        ((x >> 8) as f32 / (1 << 23) as f32) - 1.0
+    }
+
+    /// How far the phase really moved since the last sample, either way. Under
+    /// modulation this is more than the increment, and the steps need smoothing
+    /// over more of the cycle.
+    fn phase_step(&mut self, phase: f32, increment: f32) -> f32 {
+        let step = match self.last_phase {
+            Some(last) => {
+                let step = (phase - last).rem_euclid(1.0);
+                step.min(1.0 - step)
+            }
+            None => 0.0,
+        };
+        self.last_phase = Some(phase);
+
+        step.max(increment).clamp(f32::EPSILON, 0.5)
+    }
+
+    /// Fibonacci LFSR with taps on the lowest two bits, feeding back into
+    /// the highest bit of the register. Widths outside 2..=32 are clamped,
+    /// since the variant can be built or loaded without `pitched_noise`.
+    fn pitched_noise(&mut self, width: u32, increment: f32) -> f32 {
+        let width = width.clamp(PITCHED_NOISE_WIDTH_MIN, PITCHED_NOISE_WIDTH_MAX);
+        let mask = u32::MAX >> (32 - width);
+
+        self.lfsr_clock += increment * NOISE_STEPS_PER_CYCLE;
+        while self.lfsr_clock >= 1.0 {
+            self.lfsr_clock -= 1.0;
+
+            let lfsr = self.lfsr & mask;
+            let feedback = (lfsr ^ (lfsr >> 1)) & 1;
+            self.lfsr = (lfsr >> 1) | (feedback << (width - 1));
+        }
+
+        if self.lfsr & 1 == 1 {
+            1.0
+        } else {
+            -1.0
+        }
+    }
+}
+
+fn pulse(value: f32, duty: f32) -> f32 {
+    pulse_from_sine(value.sin(), duty)
+}
+
+fn pulse_from_sine(sine: f32, duty: f32) -> f32 {
+    if (sine + 1.0) / 2.0 < duty {
+        -1.0
+    } else {
+        1.0
+    }
+}
+
+fn square(value: f32) -> f32 {
+    1.0_f32.copysign(value.sin())
+}
+
+/// Wraps a phase into the range 0.0..1.0
+pub(crate) fn wrap_phase(phase: f32) -> f32 {
+    let phase = phase.rem_euclid(1.0);
+
+    // rem_euclid can round up to exactly 1.0 for tiny negative values
+    if phase >= 1.0 {
+        0.0
+    } else {
+        phase
+    }
+}
+
+/// Applies phase modulation, in radians, to a normalized phase.
+pub(crate) fn modulated_phase(phase: f32, modulation: f32) -> f32 {
+    wrap_phase(phase + modulation / TAU)
+}
+
+/// Polynomial approximation of a band limited step, used to smooth out
+/// the discontinuity when the phase is within one sample of it.
+fn poly_blep(phase: f32, increment: f32) -> f32 {
+    if phase < increment {
+        let x = phase / increment;
+        x + x - x * x - 1.0
+    } else if phase > 1.0 - increment {
+        let x = (phase - 1.0) / increment;
+        x * x + x + x + 1.0
+    } else {
+        0.0
+    }
+}
+
+fn band_limited_pulse(value: f32, increment: f32, duty: f32, state: &mut WaveformState) -> f32 {
+    let phase = wrap_phase(value / TAU);
+    let increment = state.phase_step(phase, increment);
+    let naive = if phase < duty { 1.0 } else { -1.0 };
+
+    naive + poly_blep(phase, increment) - poly_blep((phase + 1.0 - duty) % 1.0, increment)
+}
+
+fn band_limited_saw(value: f32, increment: f32, state: &mut WaveformState) -> f32 {
+    let phase = wrap_phase(value / TAU);
+    let increment = state.phase_step(phase, increment);
+
+    (phase * 2.0 - 1.0) - poly_blep(phase, increment)
+}
+
+fn half_sine(value: f32) -> f32 {
+    if value < TAU * 0.5 {
+        value.sin()
+    } else {
+        0.0
+    }
+}
+
+fn quarter_sine(value: f32) -> f32 {
+    let output = value.sin().abs() * square(value * 2.0);
+    if output.is_sign_positive() {
+        output
+    } else {
+        0.0
+    }
+}
+
+fn alternating_sine(value: f32) -> f32 {
+    if value < TAU * 0.5 {
+        (value * 2.0).sin()
+    } else {
+        0.0
+    }
+}
+
+fn camel_sine(value: f32) -> f32 {
+    alternating_sine(value).abs()
+}
+
+fn logarithmic_saw(value: f32) -> f32 {
+    (((value % TAU) - PI) / PI).asin() / -FRAC_PI_2
+}
+
+fn inverted_sine(value: f32) -> f32 {
+    let cos = value.cos();
+    if value < TAU * 0.25 {
+        1.0 - cos
+    } else if value < TAU * 0.5 {
+        1.0 + cos
+    } else if value < TAU * 0.75 {
+        -1.0 - cos
+    } else {
+        -1.0 + cos
+    }
+}
+
+fn inverted_half_sine(value: f32) -> f32 {
+    if value < TAU * 0.5 {
+        inverted_sine(value)
+    } else {
+        0.0
+    }
+}
+fn inverted_alternating_sine(value: f32) -> f32 {
+    if value < TAU * 0.5 {
+        inverted_sine(value * 2.0)
+    } else {
+        0.0
+    }
+}
+fn inverted_camel_sine(value: f32) -> f32 {
+    inverted_alternating_sine(value).abs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INCREMENT: f32 = 0.0123;
+    const TOLERANCE: f32 = 1e-3;
+
+    fn init() {
+        init_waveform_tables();
+    }
+
+    fn every_waveform() -> Vec<Waveform> {
+        vec![
+            Waveform::Sine,
+            Waveform::Square,
+            Waveform::Pulse(0.25),
+            Waveform::Saw,
+            Waveform::Triangle,
+            Waveform::Noise,
+            Waveform::BandLimitedSquare,
+            Waveform::BandLimitedPulse(0.25),
+            Waveform::BandLimitedSaw,
+            Waveform::HalfSine,
+            Waveform::AbsoluteSine,
+            Waveform::QuarterSine,
+            Waveform::AlternatingSine,
+            Waveform::CamelSine,
+            Waveform::LogarithmicSaw,
+            Waveform::PitchedNoise(SHORT_NOISE_WIDTH),
+            Waveform::PitchedNoise(LONG_NOISE_WIDTH),
+            Waveform::InvertedSine,
+            Waveform::InvertedHalfSine,
+            Waveform::InvertedAlternatingSine,
+            Waveform::InvertedCamelSine,
+            Waveform::Custom(Wavetable::new(DEFAULT_WAVETABLE_SIZE)),
+        ]
+    }
+
+    fn is_noise(waveform: &Waveform) -> bool {
+        matches!(waveform, Waveform::Noise | Waveform::PitchedNoise(_))
+    }
+
+    // Heavy FM, many cycles away from the carrier phase in both directions
+    fn modulated_phases() -> impl Iterator<Item = f32> {
+        (-500..=500).map(|step| modulated_phase(step as f32 * INCREMENT, step as f32 * 0.37))
+    }
+
+    #[test]
+    fn modulated_phase_is_wrapped() {
+        modulated_phases().for_each(|phase| assert!((0.0..1.0).contains(&phase), "{}", phase));
+        assert_eq!(wrap_phase(-f32::EPSILON / 8.0), 0.0);
+    }
+
+    #[test]
+    fn every_waveform_is_bounded_under_modulation() {
+        init();
+
+        every_waveform().iter().for_each(|waveform| {
+            let mut state = WaveformState::default();
+
+            modulated_phases().for_each(|phase| {
+                let exact = waveform.exact_func(phase * TAU, INCREMENT, &mut state);
+                assert!(
+                    exact.is_finite() && exact.abs() <= 1.0 + TOLERANCE,
+                    "{:?} output {} at phase {}",
+                    waveform,
+                    exact,
+                    phase
+                );
+
+                if let Some(table) = waveform.table_func(phase * TAU) {
+                    assert!(
+                        table.abs() <= 1.0 + TOLERANCE,
+                        "{:?} table output {} at phase {}",
+                        waveform,
+                        table,
+                        phase
+                    );
+                }
+            });
+        });
+    }
+
+    #[test]
+    fn pitched_noise_repeats_after_every_register_state() {
+        [SHORT_NOISE_WIDTH, LONG_NOISE_WIDTH]
+            .into_iter()
+            .for_each(|width| {
+                let mut state = WaveformState::default();
+                let waveform = Waveform::pitched_noise(width);
+                // One shift of the register per sample
+                let mut step = || {
+                    let output = waveform.func(0.0, 1.0 / NOISE_STEPS_PER_CYCLE, &mut state);
+                    (output, state.lfsr)
+                };
+
+                // The taps give a maximal length sequence, through every state but zero
+                let period = (1 << width) - 1;
+                let (_, first) = step();
+                let mut high = 0;
+                for index in 1..=period {
+                    let (output, lfsr) = step();
+                    assert_ne!(lfsr, 0);
+                    assert_eq!(lfsr == first, index == period, "width {}", width);
+                    if output > 0.0 {
+                        high += 1;
+                    }
+                }
+                assert_eq!(high, (period + 1) / 2, "width {}", width);
+            });
+    }
+
+    #[test]
+    fn pitched_noise_clamps_unchecked_widths() {
+        [0, 1, 33, u32::MAX].into_iter().for_each(|width| {
+            let mut state = WaveformState::default();
+            (0..100).for_each(|_| {
+                let output = Waveform::PitchedNoise(width).func(0.0, 0.5, &mut state);
+                assert!(output.abs() == 1.0, "width {}: {}", width, output);
+            });
+        });
+    }
+
+    #[test]
+    fn modulating_by_whole_cycles_changes_nothing() {
+        every_waveform()
+            .iter()
+            .filter(|waveform| !is_noise(waveform))
+            .for_each(|waveform| {
+                let mut state = WaveformState::default();
+
+                // Offset from the discontinuities at quarter cycles
+                (0..100).for_each(|step| {
+                    let phase = (step as f32 + 0.37) / 100.0;
+                    let expected = waveform.exact_func(
+                        modulated_phase(phase, 0.3) * TAU,
+                        INCREMENT,
+                        &mut state,
+                    );
+
+                    (-8..=8).for_each(|cycles| {
+                        let modulation = 0.3 + cycles as f32 * TAU;
+                        let output = waveform.exact_func(
+                            modulated_phase(phase, modulation) * TAU,
+                            INCREMENT,
+                            &mut state,
+                        );
+                        assert!(
+                            (output - expected).abs() < TOLERANCE,
+                            "{:?} at phase {} modulated by {} cycles: {} != {}",
+                            waveform,
+                            phase,
+                            cycles,
+                            output,
+                            expected
+                        );
+                    });
+                });
+            });
+    }
+
+    #[test]
+    fn half_cycle_waveforms_are_silent_in_the_second_half_under_modulation() {
+        let waveforms = [
+            Waveform::HalfSine,
+            Waveform::AlternatingSine,
+            Waveform::CamelSine,
+            Waveform::InvertedHalfSine,
+            Waveform::InvertedAlternatingSine,
+            Waveform::InvertedCamelSine,
+        ];
+
+        waveforms.iter().for_each(|waveform| {
+            let mut state = WaveformState::default();
+
+            modulated_phases()
+                .filter(|phase| *phase >= 0.5)
+                .for_each(|phase| {
+                    assert_eq!(
+                        waveform.exact_func(phase * TAU, INCREMENT, &mut state),
+                        0.0,
+                        "{:?} at phase {}",
+                        waveform,
+                        phase
+                    )
+                });
+        });
+    }
+
+    #[test]
+    fn quarter_sine_is_silent_in_falling_quarters_under_modulation() {
+        let mut state = WaveformState::default();
+
+        modulated_phases()
+            .filter(|phase| (phase * 4.0) as usize % 2 == 1)
+            .for_each(|phase| {
+                assert_eq!(
+                    Waveform::QuarterSine.exact_func(phase * TAU, INCREMENT, &mut state),
+                    0.0,
+                    "phase {}",
+                    phase
+                )
+            });
+    }
+
+    #[test]
+    fn tables_match_exact_for_continuous_waveforms() {
+        init();
+
+        let waveforms = [
+            Waveform::Sine,
+            Waveform::Triangle,
+            Waveform::HalfSine,
+            Waveform::AbsoluteSine,
+            Waveform::AlternatingSine,
+            Waveform::CamelSine,
+            Waveform::InvertedSine,
+            Waveform::InvertedHalfSine,
+            Waveform::InvertedAlternatingSine,
+            Waveform::InvertedCamelSine,
+        ];
+
+        waveforms.iter().for_each(|waveform| {
+            let mut state = WaveformState::default();
+
+            modulated_phases().for_each(|phase| {
+                let exact = waveform.exact_func(phase * TAU, INCREMENT, &mut state);
+                let table = waveform.table_func(phase * TAU).unwrap();
+                assert!(
+                    (exact - table).abs() < TOLERANCE,
+                    "{:?} at phase {}: {} != {}",
+                    waveform,
+                    phase,
+                    table,
+                    exact
+                );
+            });
+        });
+    }
+
+    /// How much of the power isn't at the harmonics the note should have, with
+    /// exactly `cycles` cycles over the samples so each harmonic falls in one bin.
+    fn aliased_power(waveform: &Waveform, cycles: usize) -> f32 {
+        const SAMPLES: usize = 4800;
+
+        let mut state = WaveformState::default();
+        let increment = cycles as f32 / SAMPLES as f32;
+        let output: Vec<f32> = (0..SAMPLES)
+            .map(|sample| {
+                let phase = (sample * cycles % SAMPLES) as f32 / SAMPLES as f32;
+                waveform.exact_func(phase * TAU, increment, &mut state)
+            })
+            .collect();
+
+        let bin_power = |bin: usize| {
+            let (re, im) = output
+                .iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(re, im), (n, x)| {
+                    let angle = TAU * ((bin * n) % SAMPLES) as f32 / SAMPLES as f32;
+                    (re + x * angle.cos(), im - x * angle.sin())
+                });
+            (re * re + im * im) / SAMPLES as f32
+        };
+
+        // Parseval's theorem, counting each harmonic's negative frequency too
+        let total = output.iter().map(|x| x * x).sum::<f32>() - bin_power(0);
+        let harmonics = (1..)
+            .map(|harmonic| harmonic * cycles)
+            .take_while(|bin| *bin < SAMPLES / 2)
+            .map(|bin| bin_power(bin) * 2.0)
+            .sum::<f32>();
+
+        (total - harmonics) / total
+    }
+
+    #[test]
+    fn band_limited_waveforms_alias_less() {
+        // Around 5.2 kHz, so only the first four harmonics are below Nyquist
+        [
+            (Waveform::Saw, Waveform::BandLimitedSaw),
+            (Waveform::Square, Waveform::BandLimitedSquare),
+        ]
+        .iter()
+        .for_each(|(raw, band_limited)| {
+            let raw_power = aliased_power(raw, 517);
+            let band_limited_power = aliased_power(band_limited, 517);
+            assert!(
+                band_limited_power < raw_power / 10.0,
+                "{:?}: {} aliased, raw {}",
+                band_limited,
+                band_limited_power,
+                raw_power
+            );
+        });
+    }
+
+    #[test]
+    fn band_limiting_follows_modulation() {
+        let mut state = WaveformState::default();
+        assert_eq!(state.phase_step(0.1, 0.01), 0.01);
+        assert!((state.phase_step(0.3, 0.01) - 0.2).abs() < 1e-6);
+        // Modulation can move the phase backwards, and across the wrap
+        assert!((state.phase_step(0.2, 0.01) - 0.1).abs() < 1e-6);
+        assert!((state.phase_step(0.9, 0.01) - 0.3).abs() < 1e-6);
+        assert_eq!(state.phase_step(0.9, 0.01), 0.01);
+    }
+}
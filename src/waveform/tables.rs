@@ -0,0 +1,107 @@
+use std::{
+    f32::consts::TAU,
+    mem::discriminant,
+    ptr::addr_of_mut,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Once,
+    },
+};
+
+use super::{pulse_from_sine, Waveform, WaveformState};
+
+/// How many samples are stored for a single cycle of each waveform.
+pub const WAVEFORM_TABLE_SIZE: usize = 4096;
+
+/// Waveforms which only depend on their phase, and can be precomputed.
+/// Pulse is built from the Sine table, as it depends on its duty cycle.
+/// Band limited waveforms depend on their frequency, so are never tabled.
+/// Custom waveforms are already tables.
+/// Each waveform's table is at its position in this list.
+const TABLE_WAVEFORMS: [Waveform; TABLE_COUNT] = [
+    Waveform::Sine,
+    Waveform::Square,
+    Waveform::Saw,
+    Waveform::Triangle,
+    Waveform::HalfSine,
+    Waveform::AbsoluteSine,
+    Waveform::QuarterSine,
+    Waveform::AlternatingSine,
+    Waveform::CamelSine,
+    Waveform::LogarithmicSaw,
+    Waveform::InvertedSine,
+    Waveform::InvertedHalfSine,
+    Waveform::InvertedAlternatingSine,
+    Waveform::InvertedCamelSine,
+];
+const TABLE_COUNT: usize = 14;
+
+static USE_WAVEFORM_TABLES: AtomicBool = AtomicBool::new(true);
+
+// One extra sample at the end of each table, so interpolation never has to wrap
+static mut WAVEFORM_TABLES: [[f32; WAVEFORM_TABLE_SIZE + 1]; TABLE_COUNT] =
+    [[0.0; WAVEFORM_TABLE_SIZE + 1]; TABLE_COUNT];
+
+/// Only generates the tables the first time it's called.
+pub(crate) fn init_waveform_tables() {
+    static INIT: Once = Once::new();
+
+    INIT.call_once(|| {
+        let mut state = WaveformState::default();
+
+        unsafe {
+            (*addr_of_mut!(WAVEFORM_TABLES))
+                .iter_mut()
+                .zip(TABLE_WAVEFORMS.iter())
+                .for_each(|(table, waveform)| {
+                    table.iter_mut().enumerate().for_each(|(index, output)| {
+                        let value =
+                            (index % WAVEFORM_TABLE_SIZE) as f32 * TAU / WAVEFORM_TABLE_SIZE as f32;
+                        *output = waveform.exact_func(value, 0.0, &mut state)
+                    })
+                });
+        }
+    });
+}
+
+/// Switches between the precomputed lookup tables and the exact math path.
+pub fn set_waveform_tables_enabled(enabled: bool) {
+    USE_WAVEFORM_TABLES.store(enabled, Ordering::Relaxed)
+}
+
+pub fn waveform_tables_enabled() -> bool {
+    USE_WAVEFORM_TABLES.load(Ordering::Relaxed)
+}
+
+impl Waveform {
+    /// Where this waveform's table is, found from `TABLE_WAVEFORMS` so the two can't disagree.
+    fn table_index(&self) -> Option<usize> {
+        let waveform = discriminant(self);
+        TABLE_WAVEFORMS
+            .iter()
+            .position(|table| discriminant(table) == waveform)
+    }
+
+    /// Reads the waveform from its lookup table, or None if the
+    /// waveform can't be precomputed.
+    pub(crate) fn table_func(&self, value: f32) -> Option<f32> {
+        match self {
+            Self::Pulse(duty) => Some(pulse_from_sine(table_lookup(0, value), *duty)),
+            _ => self.table_index().map(|table| table_lookup(table, value)),
+        }
+    }
+}
+
+/// Linearly interpolates between the two closest samples of the table.
+fn table_lookup(table: usize, value: f32) -> f32 {
+    let position = (value / TAU).rem_euclid(1.0) * WAVEFORM_TABLE_SIZE as f32;
+    let index = (position as usize).min(WAVEFORM_TABLE_SIZE - 1);
+    let fraction = position - index as f32;
+
+    unsafe {
+        let table = &(*addr_of_mut!(WAVEFORM_TABLES))[table];
+        let first = table[index];
+        let second = table[index + 1];
+        first + (second - first) * fraction
+    }
+}
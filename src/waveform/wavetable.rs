@@ -0,0 +1,153 @@
+use std::{
+    f32::consts::TAU,
+    fmt::{self, Debug},
+    sync::Arc,
+};
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Table lengths supported by the editor, similar to the
+/// wavetable channels on the PC Engine and Namco chips.
+pub const WAVETABLE_SIZES: [usize; 3] = [32, 64, 256];
+pub const DEFAULT_WAVETABLE_SIZE: usize = 32;
+
+/// A user drawn single cycle waveform. Cloning a wavetable shares
+/// the samples, so edits are heard by every operator using it.
+#[derive(Clone)]
+pub struct Wavetable {
+    /// Edits replace the samples rather than changing them, so a frozen copy never changes
+    samples: Arc<RwLock<Arc<[f32]>>>,
+    /// The samples when the table was frozen, read without taking the lock
+    frozen: Option<Arc<[f32]>>,
+}
+
+impl Default for Wavetable {
+    fn default() -> Self {
+        Self::new(DEFAULT_WAVETABLE_SIZE)
+    }
+}
+
+impl Wavetable {
+    /// Generates a new table of the given length, starting as a sine wave.
+    pub fn new(len: usize) -> Self {
+        Self::from_samples(
+            (0..len)
+                .map(|index| (index as f32 * TAU / len as f32).sin())
+                .collect(),
+        )
+    }
+
+    pub fn from_samples(samples: Vec<f32>) -> Self {
+        assert!(!samples.is_empty());
+
+        Self {
+            samples: Arc::new(RwLock::new(samples.into())),
+            frozen: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.read().len()
+    }
+
+    pub fn samples(&self) -> Vec<f32> {
+        self.samples.read().to_vec()
+    }
+
+    /// Sets a single sample, clamped to -1.0..=1.0
+    pub fn set(&self, index: usize, value: f32) {
+        let mut samples = self.samples.write();
+        if index < samples.len() {
+            let mut edited = samples.to_vec();
+            edited[index] = value.clamp(-1.0, 1.0);
+            *samples = edited.into();
+        }
+    }
+
+    /// Changes the length of the table, resampling the existing shape.
+    pub fn resize(&self, len: usize) {
+        let mut samples = self.samples.write();
+        let old = samples.clone();
+
+        *samples = (0..len).map(|index| old[index * old.len() / len]).collect();
+    }
+
+    /// A copy which keeps the current samples, and reads them without locking.
+    /// Still equal to the original, as it shares the same table.
+    pub(crate) fn frozen(&self) -> Self {
+        Self {
+            samples: self.samples.clone(),
+            frozen: Some(self.samples.read().clone()),
+        }
+    }
+
+    /// Reads the table without interpolation, as the hardware does.
+    /// Only takes the lock if the table isn't frozen.
+    pub(crate) fn func(&self, value: f32) -> f32 {
+        match &self.frozen {
+            Some(samples) => table_sample(samples, value),
+            None => table_sample(&self.samples.read(), value),
+        }
+    }
+}
+
+fn table_sample(samples: &[f32], value: f32) -> f32 {
+    let phase = (value / TAU).rem_euclid(1.0);
+    let index = ((phase * samples.len() as f32) as usize).min(samples.len() - 1);
+
+    samples[index]
+}
+
+// Two wavetables are only equal if they share the same samples
+impl PartialEq for Wavetable {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.samples, &other.samples)
+    }
+}
+
+impl Debug for Wavetable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Wavetable")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl Serialize for Wavetable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.samples.read().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Wavetable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let samples = Vec::<f32>::deserialize(deserializer)?;
+
+        if samples.is_empty() {
+            return Err(serde::de::Error::invalid_length(0, &"at least one sample"));
+        }
+
+        Ok(Self::from_samples(samples))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frozen_tables_keep_their_samples() {
+        let wavetable = Wavetable::from_samples(vec![0.0, 0.5, 1.0, -1.0]);
+        let frozen = wavetable.frozen();
+
+        wavetable.set(1, -0.25);
+        assert_eq!(wavetable.func(TAU * 0.3), -0.25);
+        assert_eq!(frozen.func(TAU * 0.3), 0.5);
+        assert!(frozen == wavetable);
+
+        wavetable.resize(2);
+        assert_eq!(frozen.func(TAU * 0.8), -1.0);
+        assert_eq!(wavetable.frozen().samples(), vec![0.0, 1.0]);
+    }
+}